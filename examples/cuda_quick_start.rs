@@ -6,10 +6,7 @@ fn main() {
     let workspaces_path = "workspaces"; // where to store the workspaces, executables, etc.
     let template_path = "src/main.cu"; // template file in the project
     let target_executable_file = "build/main"; // target executable file
-    let datas = (0..20)
-        .into_iter()
-        .map(|_| JsonValue::Null)
-        .collect::<Vec<JsonValue>>();
+    let datas = (0..20).map(|_| JsonValue::Null).collect::<Vec<JsonValue>>();
     let mut parabuilder = Parabuilder::new(
         project_path,
         workspaces_path,
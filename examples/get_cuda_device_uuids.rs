@@ -1,6 +1,8 @@
-use parabuild::get_cuda_mig_device_uuids;
+use parabuild::{get_cuda_device_indices, get_cuda_device_uuids, get_cuda_mig_device_uuids};
 
 fn main() {
     let cuda_device_uuids = get_cuda_mig_device_uuids();
     println!("{:?}", cuda_device_uuids);
+    println!("{:?}", get_cuda_device_uuids());
+    println!("{:?}", get_cuda_device_indices());
 }
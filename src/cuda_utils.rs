@@ -1,18 +1,208 @@
 use regex::Regex;
 use std::process::Command;
 
+/// How the auto-detected CUDA devices are put into `CUDA_VISIBLE_DEVICES`
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum CudaDeviceMode {
+    /// MIG device UUIDs, or full GPU UUIDs when there is no MIG device
+    #[default]
+    Uuid,
+    /// GPU indices `0`, `1`, ..., MIG devices are ignored
+    Index,
+}
+
+/// Queries NVML through `nvml-wrapper` instead of parsing `nvidia-smi -L`, the library is loaded
+/// at runtime so that parabuild still starts without a driver
+#[cfg(feature = "nvml")]
+mod nvml {
+    use nvml_wrapper::{error::NvmlError, Device, Nvml};
+    use std::sync::OnceLock;
+
+    /// A full GPU with the UUIDs of its MIG devices
+    pub struct Gpu {
+        pub index: u32,
+        pub uuid: String,
+        pub mig_uuids: Vec<String>,
+    }
+
+    /// Initialized on the first query, `None` when NVML can not be loaded or initialized,
+    /// e.g. without an NVIDIA driver
+    fn nvml() -> Option<&'static Nvml> {
+        static NVML: OnceLock<Option<Nvml>> = OnceLock::new();
+        NVML.get_or_init(|| Nvml::init().ok()).as_ref()
+    }
+
+    fn mig_uuids(device: &Device) -> Vec<String> {
+        // not supported without MIG, the GPU has no MIG device then
+        let max_mig_devices = device.mig_device_count().unwrap_or(0);
+        // the unused slots are not found
+        (0..max_mig_devices)
+            .filter_map(|mig_index| device.mig_device_by_index(mig_index).ok())
+            .filter_map(|mig_device| mig_device.uuid().ok())
+            .collect()
+    }
+
+    fn list_gpus(nvml: &Nvml) -> Result<Vec<Gpu>, NvmlError> {
+        (0..nvml.device_count()?)
+            .map(|index| {
+                let device = nvml.device_by_index(index)?;
+                Ok(Gpu {
+                    index,
+                    uuid: device.uuid()?,
+                    mig_uuids: mig_uuids(&device),
+                })
+            })
+            .collect()
+    }
+
+    /// `None` when NVML is not available or fails to list the GPUs
+    pub fn gpus() -> Option<Vec<Gpu>> {
+        list_gpus(nvml()?).ok()
+    }
+
+    /// In the order of `parse_mig_device_uuids()`
+    pub fn mig_device_uuids(gpus: &[Gpu]) -> Vec<String> {
+        let mut uuids: Vec<String> = gpus
+            .iter()
+            .flat_map(|gpu| gpu.mig_uuids.iter().cloned())
+            .collect();
+        uuids.reverse();
+        uuids
+    }
+}
+
+fn nvidia_smi_list() -> Option<String> {
+    Command::new("nvidia-smi")
+        .arg("-L")
+        .output()
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_mig_device_uuids(output: &str) -> Vec<String> {
+    let re = Regex::new(r"\(UUID: (MIG-[a-f0-9\-]+)\)").unwrap();
+    let mut uuids: Vec<String> = re
+        .captures_iter(output)
+        .map(|cap| cap[1].to_string())
+        .collect();
+    uuids.reverse();
+    uuids
+}
+
+/// `(index, uuid)` of every full GPU listed by `nvidia-smi -L`
+fn parse_gpus(output: &str) -> Vec<(String, String)> {
+    let re = Regex::new(r"(?m)^GPU (\d+):.*\(UUID: (GPU-[a-f0-9\-]+)\)").unwrap();
+    re.captures_iter(output)
+        .map(|cap| (cap[1].to_string(), cap[2].to_string()))
+        .collect()
+}
+
+fn parse_cuda_device_uuids(output: &str) -> Vec<String> {
+    let mig_device_uuids = parse_mig_device_uuids(output);
+    if !mig_device_uuids.is_empty() {
+        return mig_device_uuids;
+    }
+    parse_gpus(output)
+        .into_iter()
+        .map(|(_, uuid)| uuid)
+        .collect()
+}
+
+/// Listed by NVML with the `nvml` feature, falling back to `nvidia-smi -L` when NVML fails
 pub fn get_cuda_mig_device_uuids() -> Vec<String> {
-    match Command::new("nvidia-smi").arg("-L").output() {
-        Ok(output) => {
-            let output = String::from_utf8(output.stdout).unwrap();
-            let re = Regex::new(r"\(UUID: (MIG-[a-f0-9\-]+)\)").unwrap();
-            let mut uuids: Vec<String> = re
-                .captures_iter(&output)
-                .map(|cap| cap[1].to_string())
-                .collect();
-            uuids.reverse();
-            uuids
+    #[cfg(feature = "nvml")]
+    if let Some(gpus) = nvml::gpus() {
+        return nvml::mig_device_uuids(&gpus);
+    }
+    nvidia_smi_list()
+        .map(|output| parse_mig_device_uuids(&output))
+        .unwrap_or_default()
+}
+
+/// UUIDs of the MIG devices, falling back to the UUIDs of the full GPUs when there is no MIG device
+pub fn get_cuda_device_uuids() -> Vec<String> {
+    #[cfg(feature = "nvml")]
+    if let Some(gpus) = nvml::gpus() {
+        let mig_device_uuids = nvml::mig_device_uuids(&gpus);
+        if !mig_device_uuids.is_empty() {
+            return mig_device_uuids;
         }
-        Err(_) => Vec::new(),
+        return gpus.into_iter().map(|gpu| gpu.uuid).collect();
+    }
+    nvidia_smi_list()
+        .map(|output| parse_cuda_device_uuids(&output))
+        .unwrap_or_default()
+}
+
+/// Indices of the full GPUs
+pub fn get_cuda_device_indices() -> Vec<String> {
+    #[cfg(feature = "nvml")]
+    if let Some(gpus) = nvml::gpus() {
+        return gpus.iter().map(|gpu| gpu.index.to_string()).collect();
+    }
+    nvidia_smi_list()
+        .map(|output| {
+            parse_gpus(&output)
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL_GPUS: &str = "\
+GPU 0: NVIDIA GeForce RTX 3090 (UUID: GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77)
+GPU 1: NVIDIA GeForce RTX 3090 (UUID: GPU-1f2e3d4c-aaaa-bbbb-cccc-0123456789ab)
+";
+
+    const MIG_GPUS: &str = "\
+GPU 0: NVIDIA A100-SXM4-40GB (UUID: GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77)
+  MIG 3g.20gb     Device  0: (UUID: MIG-c6d4f1ef-42e4-5de3-91c7-45d71c87eb3f)
+  MIG 3g.20gb     Device  1: (UUID: MIG-0a1b2c3d-42e4-5de3-91c7-45d71c87eb3f)
+";
+
+    #[test]
+    fn test_parse_cuda_device_uuids() {
+        assert_eq!(
+            parse_cuda_device_uuids(FULL_GPUS),
+            vec![
+                "GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77",
+                "GPU-1f2e3d4c-aaaa-bbbb-cccc-0123456789ab"
+            ]
+        );
+        assert_eq!(
+            parse_cuda_device_uuids(MIG_GPUS),
+            vec![
+                "MIG-0a1b2c3d-42e4-5de3-91c7-45d71c87eb3f",
+                "MIG-c6d4f1ef-42e4-5de3-91c7-45d71c87eb3f"
+            ]
+        );
+        assert!(parse_cuda_device_uuids("").is_empty());
+        let indices: Vec<String> = parse_gpus(FULL_GPUS)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(indices, vec!["0", "1"]);
+    }
+
+    #[cfg(feature = "nvml")]
+    #[test]
+    fn test_nvml_mig_device_uuids() {
+        let gpus = vec![nvml::Gpu {
+            index: 0,
+            uuid: "GPU-5d5ba0d6-d33d-2b2c-524d-9e3d8d2b8a77".to_string(),
+            mig_uuids: vec![
+                "MIG-c6d4f1ef-42e4-5de3-91c7-45d71c87eb3f".to_string(),
+                "MIG-0a1b2c3d-42e4-5de3-91c7-45d71c87eb3f".to_string(),
+            ],
+        }];
+        assert_eq!(
+            nvml::mig_device_uuids(&gpus),
+            parse_mig_device_uuids(MIG_GPUS)
+        );
     }
 }
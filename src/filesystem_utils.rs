@@ -1,8 +1,13 @@
-use fs_extra;
-use ignore;
-use std::{path::Path, process::Command};
+use indicatif::ProgressBar;
+use std::io::Read;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    time::{Duration, SystemTime},
+};
 
-pub fn copy_dir<P, Q>(from: P, to: Q) -> Result<(), fs_extra::error::Error>
+/// Copy everything in `from` into `to`, reporting copied/total bytes to `pb`
+pub fn copy_dir<P, Q>(from: P, to: Q, pb: &ProgressBar) -> Result<(), fs_extra::error::Error>
 where
     P: AsRef<Path>,
     Q: AsRef<Path>,
@@ -12,28 +17,78 @@ where
         .overwrite(true)
         .copy_inside(true)
         .content_only(true);
-    fs_extra::dir::copy(from, to, &options)?;
+    fs_extra::dir::copy_with_progress(from, to, &options, |process| {
+        pb.set_length(process.total_bytes);
+        pb.set_position(process.copied_bytes);
+        fs_extra::dir::TransitProcessResult::ContinueOrAbort
+    })?;
     Ok(())
 }
 
-pub fn copy_dir_with_ignore<P, Q>(from: P, to: Q) -> Result<(), std::io::Error>
+/// How project files are placed into the workspaces
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum CopyStrategy {
+    /// Copy every file
+    #[default]
+    Copy,
+    /// Hard link the files, they share the content with the project
+    Hardlink,
+    /// Copy-on-write clone the files, supported by btrfs, XFS, etc.
+    Reflink,
+}
+
+/// Copy `from` into `to` skipping files ignored by `.gitignore` or matching the gitignore-style
+/// `excludes`, the total bytes are counted first, then copied/total bytes are reported to `pb`
+pub fn copy_dir_with_ignore<P, Q>(
+    from: P,
+    to: Q,
+    excludes: &[String],
+    pb: &ProgressBar,
+) -> Result<(), std::io::Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    copy_dir_with_strategy(from, to, CopyStrategy::Copy, &[], excludes, pb)
+}
+
+/// `copy_dir_with_ignore` that places the files with `strategy`,
+/// except files under `independent_paths` (relative to `from`) which are always copied
+///
+/// Falls back to copy with a warning when a file can not be linked
+pub fn copy_dir_with_strategy<P, Q>(
+    from: P,
+    to: Q,
+    strategy: CopyStrategy,
+    independent_paths: &[PathBuf],
+    excludes: &[String],
+    pb: &ProgressBar,
+) -> Result<(), std::io::Error>
 where
     P: AsRef<Path>,
     Q: AsRef<Path>,
 {
-    for entry in ignore::WalkBuilder::new(&from).git_ignore(true).build() {
+    let mut overrides = ignore::overrides::OverrideBuilder::new(&from);
+    for exclude in excludes {
+        overrides
+            .add(&format!("!{}", exclude))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    }
+    let overrides = overrides
+        .build()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut files = vec![];
+    for entry in ignore::WalkBuilder::new(&from)
+        .git_ignore(true)
+        .overrides(overrides)
+        .build()
+    {
         match entry {
-            Ok(ref entry) => {
+            Ok(entry) => {
                 let path = entry.path();
                 if path.is_file() {
-                    let relative_path = path
-                        .strip_prefix(from.as_ref())
-                        .expect("Failed to strip prefix");
-                    let destination = to.as_ref().join(relative_path);
-                    if let Some(parent) = destination.parent() {
-                        std::fs::create_dir_all(parent).expect("Failed to create parent directory");
-                    }
-                    std::fs::copy(path, destination).expect("Failed to copy file");
+                    let len = path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                    files.push((entry.into_path(), len));
                 }
             }
             Err(e) => {
@@ -41,10 +96,96 @@ where
             }
         }
     }
+    pb.set_length(files.iter().map(|(_, len)| len).sum());
+    pb.set_position(0);
+    let mut warned = false;
+    for (path, len) in files {
+        let relative_path = path
+            .strip_prefix(from.as_ref())
+            .expect("Failed to strip prefix");
+        let destination = to.as_ref().join(relative_path);
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let strategy = if independent_paths
+            .iter()
+            .any(|independent_path| relative_path.starts_with(independent_path))
+        {
+            CopyStrategy::Copy
+        } else {
+            strategy
+        };
+        // never write through an old link into the project, a hard link left by an earlier
+        // init shares its inode with the project file
+        if destination.is_symlink() || destination.exists() {
+            std::fs::remove_file(&destination)?;
+        }
+        let linked = match strategy {
+            CopyStrategy::Copy => Ok(()),
+            CopyStrategy::Hardlink => std::fs::hard_link(&path, &destination),
+            CopyStrategy::Reflink => reflink(&path, &destination),
+        };
+        match (strategy, linked) {
+            (CopyStrategy::Copy, _) => {
+                std::fs::copy(&path, &destination)?;
+            }
+            (_, Ok(())) => {}
+            (_, Err(e)) => {
+                if !warned {
+                    eprintln!(
+                        "Warning: failed to {:?} {:?}: {}, falling back to copy",
+                        strategy, path, e
+                    );
+                    warned = true;
+                }
+                std::fs::copy(&path, &destination)?;
+            }
+        }
+        pb.inc(len);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+    let source = std::fs::File::open(from)?;
+    let destination = std::fs::File::create(to)?;
+    let ret = unsafe {
+        libc::ioctl(
+            destination.as_raw_fd(),
+            libc::FICLONE as _,
+            source.as_raw_fd(),
+        )
+    };
+    if ret == -1 {
+        let e = std::io::Error::last_os_error();
+        drop(destination);
+        let _ = std::fs::remove_file(to);
+        return Err(e);
+    }
+    destination.set_permissions(source.metadata()?.permissions())?;
     Ok(())
 }
 
-pub fn copy_dir_with_rsync(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+#[cfg(not(target_os = "linux"))]
+fn reflink(_: &Path, _: &Path) -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reflink is only supported on linux",
+    ))
+}
+
+/// Copy `from` into `to` with `rsync -a <rsync_args>`, skipping files in `.gitignore`
+/// and files matching `excludes` (passed as `--exclude`),
+/// when `pb` is not hidden, `--info=progress2` is parsed to report transferred/total bytes to it
+pub fn copy_dir_with_rsync(
+    from: &Path,
+    to: &Path,
+    rsync_args: &[String],
+    excludes: &[String],
+    pb: &ProgressBar,
+) -> Result<(), std::io::Error> {
     let from_ends_with_slash = if from.ends_with("/") {
         from.to_str().unwrap().to_string()
     } else {
@@ -57,18 +198,64 @@ pub fn copy_dir_with_rsync(from: &Path, to: &Path) -> Result<(), std::io::Error>
     };
     let gitignore_file = from.join(".gitignore");
     let mut output = Command::new("rsync");
-    output.arg("-a");
+    output.arg("-a").args(rsync_args);
+    if !pb.is_hidden() {
+        output.arg("--info=progress2").arg("--no-inc-recursive");
+    }
     if gitignore_file.exists() {
         output.arg(format!(
             "--exclude-from={}",
             gitignore_file.to_str().unwrap()
         ));
     }
-    let output = output
+    for exclude in excludes {
+        output.arg(format!("--exclude={}", exclude));
+    }
+    let mut child = output
         .arg(from_ends_with_slash)
         .arg(to_ends_with_slash)
-        .output()?;
-    if !output.status.success() {
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr_handle = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        stderr.read_to_end(&mut buf).map(|_| buf)
+    });
+    // progress lines look like `  1,234,567  45%   10.00MB/s    0:00:01 (xfr#3, to-chk=0/5)`,
+    // separated by `\r`
+    let mut stdout_buf = Vec::new();
+    let mut line = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let n = stdout.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        stdout_buf.extend_from_slice(&buf[..n]);
+        for &byte in &buf[..n] {
+            if byte == b'\r' || byte == b'\n' {
+                if let Some((bytes, percent)) = parse_rsync_progress(&line) {
+                    if let Some(total) = (bytes * 100).checked_div(percent) {
+                        pb.set_length(total);
+                    }
+                    pb.set_position(bytes);
+                }
+                line.clear();
+            } else {
+                line.push(byte);
+            }
+        }
+    }
+    let status = child.wait()?;
+    let stderr = stderr_handle.join().unwrap()?;
+    if !status.success() {
+        let output = Output {
+            status,
+            stdout: stdout_buf,
+            stderr,
+        };
         return Err(std::io::Error::new(
             std::io::ErrorKind::Other,
             format!("Failed to copy directory: {:?}", output),
@@ -77,27 +264,77 @@ pub fn copy_dir_with_rsync(from: &Path, to: &Path) -> Result<(), std::io::Error>
     Ok(())
 }
 
-pub fn wait_until_file_ready(file_path: &Path) -> Result<(), std::io::Error> {
+/// `  1,234,567  45%   10.00MB/s ...` => `(1234567, 45)`
+fn parse_rsync_progress(line: &[u8]) -> Option<(u64, u64)> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut fields = line.split_whitespace();
+    let bytes = fields.next()?.replace(',', "").parse().ok()?;
+    let percent = fields.next()?.strip_suffix('%')?.parse().ok()?;
+    Some((bytes, percent))
+}
+
+/// How `wait_until_file_ready` decides that a target file is no longer being written
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum FileReadyCheck {
+    /// Wait until `lsof` reports that no process has the file open, requires `lsof`
+    #[default]
+    Lsof,
+    /// Wait until the file exists and its size and mtime stay the same across two polls,
+    /// for systems without `lsof`
+    Stable,
+}
+
+/// Polling of `wait_until_file_ready`, it gives up after `attempts` polls `interval` apart
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct FileReadyWait {
+    pub check: FileReadyCheck,
+    pub attempts: usize,
+    pub interval: Duration,
+}
+
+impl Default for FileReadyWait {
+    fn default() -> Self {
+        Self {
+            check: FileReadyCheck::Lsof,
+            attempts: 100,
+            interval: Duration::from_millis(100),
+        }
+    }
+}
+
+pub fn wait_until_file_ready(file_path: &Path, wait: FileReadyWait) -> Result<(), std::io::Error> {
     use std::thread::sleep;
-    use std::time::Duration;
     let mut attempts = 0;
-    fn ready(file_path: &Path) -> bool {
+    let mut last_snapshot: Option<(u64, SystemTime)> = None;
+    let mut ready = |file_path: &Path| -> bool {
         if !file_path.exists() {
             return false;
         }
-        let output = Command::new("lsof").arg(file_path).output().unwrap();
-        if output.stdout.is_empty() {
-            return true;
+        match wait.check {
+            FileReadyCheck::Lsof => {
+                let output = Command::new("lsof").arg(file_path).output().unwrap();
+                if output.stdout.is_empty() {
+                    return true;
+                }
+                eprintln!(
+                    "Waiting for file to be ready: {:?}, {:?}",
+                    file_path, output.stdout
+                );
+                false
+            }
+            FileReadyCheck::Stable => {
+                let snapshot = std::fs::metadata(file_path)
+                    .and_then(|metadata| Ok((metadata.len(), metadata.modified()?)))
+                    .ok();
+                let stable = snapshot.is_some() && snapshot == last_snapshot;
+                last_snapshot = snapshot;
+                stable
+            }
         }
-        eprintln!(
-            "Waiting for file to be ready: {:?}, {:?}",
-            file_path, output.stdout
-        );
-        false
-    }
+    };
     while !ready(file_path) {
         attempts += 1;
-        if attempts > 100 {
+        if attempts > wait.attempts {
             if !file_path.exists() {
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::NotFound,
@@ -110,13 +347,112 @@ pub fn wait_until_file_ready(file_path: &Path) -> Result<(), std::io::Error> {
                 ));
             }
         }
-        sleep(Duration::from_millis(100));
+        sleep(wait.interval);
     }
     Ok(())
 }
 
+/// Copy the target file `from` to `to`, recursively when it is a directory, replacing `to`
+pub fn copy_target(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    if from.is_dir() {
+        if to.exists() {
+            std::fs::remove_dir_all(to)?;
+        }
+        // not `copy_dir`, whose progress reporting copy drops the permissions of executables
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_target(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Whether the target file is a glob pattern like `build/*.ptx`
+pub fn is_glob_pattern(target_file: &Path) -> bool {
+    target_file.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Expand the glob patterns in `target_files` into the files present under `workspace_path`,
+/// the other target files are kept as is, all of them are relative to `workspace_path`
+///
+/// Errors when two of the resolved target files have the same file name,
+/// as they are staged by their file names
+pub fn resolve_target_files(
+    workspace_path: &Path,
+    target_files: &[PathBuf],
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut resolved = Vec::new();
+    for target_file in target_files {
+        if !is_glob_pattern(target_file) {
+            resolved.push(target_file.clone());
+            continue;
+        }
+        let pattern = format!(
+            "{}/{}",
+            glob::Pattern::escape(&workspace_path.to_string_lossy()),
+            target_file.to_string_lossy()
+        );
+        let paths = glob::glob(&pattern)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let mut matched = Vec::new();
+        for path in paths {
+            let path = path.map_err(std::io::Error::from)?;
+            matched.push(path.strip_prefix(workspace_path).unwrap().to_path_buf());
+        }
+        matched.sort();
+        resolved.extend(matched);
+    }
+    let mut file_names = std::collections::HashSet::new();
+    for target_file in resolved.iter() {
+        if !file_names.insert(target_file.file_name()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "more than one target file is named {:?}",
+                    target_file.file_name()
+                ),
+            ));
+        }
+    }
+    Ok(resolved)
+}
+
+/// Move the target file `from` to `to`, which may be a directory, replacing `to`
+pub fn move_target(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    // `rename` can not replace a non-empty directory, or a file by a directory
+    if to.is_dir() {
+        std::fs::remove_dir_all(to)?;
+    } else if from.is_dir() && to.exists() {
+        std::fs::remove_file(to)?;
+    }
+    std::fs::rename(from, to)
+}
+
+/// Check whether `command` can be found in `PATH` (like `which`), without running it
 pub fn is_command_installed(command: &str) -> bool {
-    Command::new(command).arg("--version").output().is_ok()
+    fn is_executable(path: &Path) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            path.metadata()
+                .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        }
+        #[cfg(not(unix))]
+        {
+            path.is_file() || path.with_extension("exe").is_file()
+        }
+    }
+    if command.contains(std::path::MAIN_SEPARATOR) || command.contains('/') {
+        return is_executable(Path::new(command));
+    }
+    match std::env::var_os("PATH") {
+        Some(paths) => std::env::split_paths(&paths).any(|dir| is_executable(&dir.join(command))),
+        None => false,
+    }
 }
 
 #[cfg(test)]
@@ -133,7 +469,7 @@ mod tests {
         let destination = &tempdir().unwrap().into_path();
         println!("source: {:?}", source);
         println!("destination: {:?}", destination);
-        copy_dir(source, destination).unwrap();
+        copy_dir(source, destination, &ProgressBar::hidden()).unwrap();
         let main_file = destination.join("src/main.cpp.template");
         let ignore_file = destination.join("src/example.ignore");
         let gitignore_file = destination.join(".gitignore");
@@ -149,7 +485,7 @@ mod tests {
         let destination = &tempdir().unwrap().into_path();
         println!("destination: {:?}", destination);
         fs_extra::dir::remove(destination).unwrap();
-        copy_dir_with_ignore(source, destination).unwrap();
+        copy_dir_with_ignore(source, destination, &[], &ProgressBar::hidden()).unwrap();
         let main_file = destination.join("src/main.cpp.template");
         let ignore_file = destination.join("src/example.ignore");
         let gitignore_file = destination.join(".gitignore");
@@ -159,10 +495,190 @@ mod tests {
         std::fs::remove_dir_all(destination).unwrap();
     }
 
+    #[test]
+    fn test_copy_dir_with_ignore_progress() {
+        let source = Path::new(EXAMPLE_PROJECT);
+        let destination = &tempdir().unwrap().into_path();
+        let pb = ProgressBar::hidden();
+        copy_dir_with_ignore(source, destination, &[], &pb).unwrap();
+        assert!(destination.join("src/main.cpp.template").exists());
+        assert!(pb.length().unwrap() > 0);
+        assert_eq!(pb.position(), pb.length().unwrap());
+        std::fs::remove_dir_all(destination).unwrap();
+    }
+
+    #[test]
+    fn test_copy_dir_with_excludes() {
+        let source = Path::new(EXAMPLE_PROJECT);
+        let destination = &tempdir().unwrap().into_path();
+        copy_dir_with_ignore(
+            source,
+            destination,
+            &["*.template".to_string()],
+            &ProgressBar::hidden(),
+        )
+        .unwrap();
+        assert!(!destination.join("src/main.cpp.template").exists());
+        assert!(!destination.join("src/example.ignore").exists());
+        assert!(destination.join("CMakeLists.txt").exists());
+        std::fs::remove_dir_all(destination).unwrap();
+    }
+
+    #[test]
+    fn test_copy_dir_with_strategy() {
+        let source = tempdir().unwrap().into_path();
+        std::fs::write(source.join("shared.txt"), "shared").unwrap();
+        std::fs::write(source.join("main.cpp"), "template").unwrap();
+        for strategy in [
+            CopyStrategy::Copy,
+            CopyStrategy::Hardlink,
+            CopyStrategy::Reflink,
+        ] {
+            let destination = tempdir().unwrap().into_path();
+            // twice, as workspaces are initialized again when cached
+            for _ in 0..2 {
+                copy_dir_with_strategy(
+                    &source,
+                    &destination,
+                    strategy,
+                    &[PathBuf::from("main.cpp")],
+                    &[],
+                    &ProgressBar::hidden(),
+                )
+                .unwrap();
+            }
+            assert_eq!(
+                std::fs::read_to_string(destination.join("shared.txt")).unwrap(),
+                "shared"
+            );
+            std::fs::write(destination.join("main.cpp"), "rendered").unwrap();
+            assert_eq!(
+                std::fs::read_to_string(source.join("main.cpp")).unwrap(),
+                "template"
+            );
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let nlink = std::fs::metadata(source.join("shared.txt"))
+                    .unwrap()
+                    .nlink();
+                assert_eq!(nlink > 1, strategy == CopyStrategy::Hardlink);
+            }
+            std::fs::remove_dir_all(destination).unwrap();
+        }
+        // copy over a workspace hard linked by an earlier init
+        let destination = tempdir().unwrap().into_path();
+        for strategy in [CopyStrategy::Hardlink, CopyStrategy::Copy] {
+            copy_dir_with_strategy(
+                &source,
+                &destination,
+                strategy,
+                &[],
+                &[],
+                &ProgressBar::hidden(),
+            )
+            .unwrap();
+        }
+        std::fs::write(destination.join("shared.txt"), "rendered").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(source.join("shared.txt")).unwrap(),
+            "shared"
+        );
+        std::fs::remove_dir_all(destination).unwrap();
+        std::fs::remove_dir_all(source).unwrap();
+    }
+
+    #[test]
+    fn test_parse_rsync_progress() {
+        assert_eq!(
+            parse_rsync_progress(
+                b"      1,234,567  45%   10.00MB/s    0:00:01 (xfr#3, to-chk=0/5)"
+            ),
+            Some((1234567, 45))
+        );
+        assert_eq!(parse_rsync_progress(b"sending incremental file list"), None);
+    }
+
+    #[test]
+    fn test_copy_and_move_target() {
+        let dir = tempdir().unwrap().into_path();
+        let from = dir.join("out");
+        std::fs::create_dir_all(from.join("nested")).unwrap();
+        std::fs::write(from.join("nested/a"), "a").unwrap();
+        let staged = dir.join("out_0");
+        std::fs::create_dir_all(&staged).unwrap();
+        std::fs::write(staged.join("stale"), "stale").unwrap();
+        copy_target(&from, &staged).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(staged.join("nested/a")).unwrap(),
+            "a"
+        );
+        assert!(!staged.join("stale").exists());
+
+        let to = dir.join("run/out");
+        std::fs::create_dir_all(to.join("old")).unwrap();
+        move_target(&staged, &to).unwrap();
+        assert!(!staged.exists());
+        assert!(!to.join("old").exists());
+        assert_eq!(std::fs::read_to_string(to.join("nested/a")).unwrap(), "a");
+
+        std::fs::write(dir.join("file"), "file").unwrap();
+        copy_target(&dir.join("file"), &dir.join("file_0")).unwrap();
+        move_target(&dir.join("file_0"), &dir.join("run/file")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("run/file")).unwrap(),
+            "file"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
     #[test]
     fn test_is_command_installed() {
         assert!(is_command_installed("ls"));
         assert!(!is_command_installed("ls_not_exist"));
+        assert!(is_command_installed("/bin/sh"));
+        assert!(!is_command_installed("src"));
+    }
+
+    #[test]
+    fn test_wait_until_file_ready_stable() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("main");
+        let wait = FileReadyWait {
+            check: FileReadyCheck::Stable,
+            attempts: 5,
+            interval: Duration::from_millis(10),
+        };
+        let err = wait_until_file_ready(&file_path, wait).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        std::fs::write(&file_path, "main").unwrap();
+        wait_until_file_ready(&file_path, wait).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_target_files() {
+        let workspace_path = tempdir().unwrap().into_path();
+        std::fs::create_dir_all(workspace_path.join("build")).unwrap();
+        for file in ["build/b.ptx", "build/a.ptx", "build/main", "a.txt"] {
+            std::fs::write(workspace_path.join(file), "").unwrap();
+        }
+        let target_files = [PathBuf::from("build/*.ptx"), PathBuf::from("build/main")];
+        assert_eq!(
+            resolve_target_files(&workspace_path, &target_files).unwrap(),
+            vec![
+                PathBuf::from("build/a.ptx"),
+                PathBuf::from("build/b.ptx"),
+                PathBuf::from("build/main")
+            ]
+        );
+        let target_files = [PathBuf::from("*.cubin")];
+        assert!(resolve_target_files(&workspace_path, &target_files)
+            .unwrap()
+            .is_empty());
+        std::fs::write(workspace_path.join("a.ptx"), "").unwrap();
+        let target_files = [PathBuf::from("build/*.ptx"), PathBuf::from("*.ptx")];
+        assert!(resolve_target_files(&workspace_path, &target_files).is_err());
+        std::fs::remove_dir_all(workspace_path).unwrap();
     }
 
     #[test]
@@ -172,7 +688,7 @@ mod tests {
         }
         let example_project_dir = Path::new(crate::test_constants::EXAMPLE_CMAKE_PROJECT_PATH);
         let working_dir = tempdir().unwrap().into_path();
-        copy_dir(example_project_dir, &working_dir).unwrap();
+        copy_dir(example_project_dir, &working_dir, &ProgressBar::hidden()).unwrap();
         let ignore_path = working_dir.join("src/example.ignore");
         assert!(ignore_path.exists());
         let file_path = working_dir.join("src/example.cpp");
@@ -182,7 +698,7 @@ mod tests {
         write!(file, "Hello, ").unwrap();
         file.sync_all().unwrap();
         let destination = tempdir().unwrap().into_path();
-        copy_dir_with_rsync(&working_dir, &destination).unwrap();
+        copy_dir_with_rsync(&working_dir, &destination, &[], &[], &ProgressBar::hidden()).unwrap();
         let ignore_destination = destination.join("src/example.ignore");
         let file_destination = destination.join("src/example.cpp");
         let main_destination = destination.join("src/main.cpp");
@@ -190,12 +706,23 @@ mod tests {
         assert!(file_destination.exists());
         writeln!(file, "world!").unwrap();
         file.sync_all().unwrap();
-        copy_dir_with_rsync(&working_dir, &destination).unwrap();
+        copy_dir_with_rsync(&working_dir, &destination, &[], &[], &ProgressBar::hidden()).unwrap();
         assert_eq!(
             std::fs::read_to_string(file_destination).unwrap(),
             "Hello, world!\n"
         );
         assert_eq!(main_old_mtime, get_mtime(&main_destination).unwrap(),);
+        let extra_destination = destination.join("src/extra.cpp");
+        std::fs::write(&extra_destination, "").unwrap();
+        copy_dir_with_rsync(
+            &working_dir,
+            &destination,
+            &["--delete".to_string()],
+            &[],
+            &ProgressBar::hidden(),
+        )
+        .unwrap();
+        assert!(!extra_destination.exists());
         std::fs::remove_dir_all(working_dir).unwrap();
         std::fs::remove_dir_all(destination).unwrap();
     }
@@ -1,8 +1,10 @@
 use handlebars::*;
+use std::path::{Path, PathBuf};
 
 /// default helper
 ///
-/// Usage: `{{default name 'default_value'}}`
+/// Usage: `{{default name 'default_value'}}`, the default is used when `name` is missing,
+/// null or an empty string, a missing parameter is a render error
 pub fn default_value_helper(
     h: &Helper,
     _: &Handlebars,
@@ -10,10 +12,14 @@ pub fn default_value_helper(
     _: &mut RenderContext,
     out: &mut dyn Output,
 ) -> HelperResult {
-    let param = h.param(0).unwrap();
-    let default = h.param(1).unwrap();
+    let param = h
+        .param(0)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("default", 0))?;
+    let default = h
+        .param(1)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("default", 1))?;
 
-    if param.value().is_null() {
+    if param.value().is_null() || param.value().as_str() == Some("") {
         out.write(default.value().render().as_ref())?;
     } else {
         out.write(param.value().render().as_ref())?;
@@ -21,6 +27,188 @@ pub fn default_value_helper(
     Ok(())
 }
 
+fn number_param(h: &Helper, name: &'static str, index: usize) -> Result<JsonValue, RenderError> {
+    let value = h
+        .param(index)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(name, index))?
+        .value();
+    if value.is_number() {
+        Ok(value.clone())
+    } else {
+        Err(RenderErrorReason::InvalidParamType("number").into())
+    }
+}
+
+fn arithmetic(
+    h: &Helper,
+    name: &'static str,
+    out: &mut dyn Output,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> HelperResult {
+    let a = number_param(h, name, 0)?;
+    let b = number_param(h, name, 1)?;
+    // stay integer when both are integers, e.g. `{{add 1 2}}` renders `3` rather than `3.0`
+    let result = match (a.as_i64(), b.as_i64()) {
+        (Some(a), Some(b)) => match int_op(a, b) {
+            Some(result) => JsonValue::from(result),
+            None => JsonValue::from(float_op(a as f64, b as f64)),
+        },
+        _ => JsonValue::from(float_op(a.as_f64().unwrap(), b.as_f64().unwrap())),
+    };
+    out.write(result.render().as_ref())?;
+    Ok(())
+}
+
+/// add helper
+///
+/// Usage: `{{add a b}}`
+pub fn add_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    arithmetic(h, "add", out, i64::checked_add, |a, b| a + b)
+}
+
+/// mul helper
+///
+/// Usage: `{{mul a b}}`
+pub fn mul_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    arithmetic(h, "mul", out, i64::checked_mul, |a, b| a * b)
+}
+
+/// range block helper, render the block for each integer in `[start, end)`
+///
+/// Usage: `{{#range 0 N}}a[{{this}}] = {{@index}};{{/range}}`, like `each`, use `../N` to access the outer data
+pub fn range_helper<'reg, 'rc>(
+    h: &Helper<'rc>,
+    r: &'reg Handlebars<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let start = number_param(h, "range", 0)?
+        .as_i64()
+        .ok_or(RenderErrorReason::InvalidParamType("integer"))?;
+    let end = number_param(h, "range", 1)?
+        .as_i64()
+        .ok_or(RenderErrorReason::InvalidParamType("integer"))?;
+    if let Some(template) = h.template() {
+        rc.push_block(BlockContext::new());
+        for i in start..end {
+            if let Some(block) = rc.block_mut() {
+                block.set_base_value(JsonValue::from(i));
+                block.set_local_var("index", JsonValue::from(i));
+            }
+            template.render(r, ctx, rc, out)?;
+        }
+        rc.pop_block();
+    }
+    Ok(())
+}
+
+/// include helper, inline the content of a file relative to `base_dir`
+///
+/// Usage: `{{include "common/header.cuh"}}`
+pub struct IncludeHelper {
+    pub base_dir: PathBuf,
+}
+
+impl HelperDef for IncludeHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let file = h
+            .param(0)
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("include", 0))?
+            .value()
+            .as_str()
+            .ok_or(RenderErrorReason::InvalidParamType("string"))?;
+        let base_dir = self.base_dir.canonicalize().map_err(|e| {
+            RenderErrorReason::Other(format!(
+                "include: invalid base dir {:?}: {}",
+                self.base_dir, e
+            ))
+        })?;
+        let path = base_dir.join(file);
+        let path = path.canonicalize().map_err(|_| {
+            RenderErrorReason::Other(format!("include: file not found: {:?}", path))
+        })?;
+        if !path.starts_with(&base_dir) {
+            return Err(RenderErrorReason::Other(format!(
+                "include: {:?} is outside of {:?}",
+                path, base_dir
+            ))
+            .into());
+        }
+        let content = std::fs::read_to_string(&path)?;
+        out.write(&content)?;
+        Ok(())
+    }
+}
+
+/// Register all the helpers above, `include` is relative to `base_dir`
+pub fn register_helpers(handlebars: &mut Handlebars, base_dir: &Path) {
+    handlebars.register_helper("default", Box::new(default_value_helper));
+    handlebars.register_helper(
+        "include",
+        Box::new(IncludeHelper {
+            base_dir: base_dir.to_path_buf(),
+        }),
+    );
+    handlebars.register_helper("add", Box::new(add_helper));
+    handlebars.register_helper("mul", Box::new(mul_helper));
+    handlebars.register_helper("range", Box::new(range_helper));
+}
+
+/// Rewrite a template written with the `open` and `close` delimiters into the handlebars syntax
+///
+/// The literal `{` in the template are rendered by `{{#with "{"}}{{this}}{{/with}}`, so they
+/// are kept as is and never merge into the handlebars expressions, an `open` without its `close`
+/// is kept as text
+pub fn translate_delimiters(template: &str, open: &str, close: &str) -> String {
+    let mut translated = String::with_capacity(template.len());
+    let mut rest = template;
+    while !rest.is_empty() {
+        if rest.starts_with(open) {
+            if let Some(end) = rest[open.len()..].find(close) {
+                translated.push_str("{{");
+                translated.push_str(&rest[open.len()..open.len() + end]);
+                translated.push_str("}}");
+                rest = &rest[open.len() + end + close.len()..];
+                continue;
+            }
+        }
+        if rest.starts_with('{') {
+            let braces = rest.len() - rest.trim_start_matches('{').len();
+            translated.push_str(&format!(
+                "{{{{#with \"{}\"}}}}{{{{this}}}}{{{{/with}}}}",
+                &rest[..braces]
+            ));
+            rest = &rest[braces..];
+            continue;
+        }
+        let c = rest.chars().next().unwrap();
+        translated.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    translated
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,8 +241,99 @@ mod tests {
         let rendered = hbs.render_template(template, &data).unwrap();
         assert_eq!(rendered, "default");
 
+        let template = "{{default empty 'default'}}";
+        let rendered = hbs
+            .render_template(template, &json!({"empty": ""}))
+            .unwrap();
+        assert_eq!(rendered, "default");
+
+        assert!(hbs.render_template("{{default}}", &data).is_err());
+        assert!(hbs.render_template("{{default name}}", &data).is_err());
+
         // let template = "{{default missing default}}";
         // let rendered = hbs.render_template(template, &data).unwrap();
         // assert_eq!(rendered, "");
     }
+
+    #[test]
+    fn test_arithmetic_helper() {
+        let mut hbs = Handlebars::new();
+        register_helpers(&mut hbs, Path::new("."));
+        let data = json!({"N": 2, "F": 0.5});
+
+        let rendered = hbs.render_template("{{add N 1}}", &data).unwrap();
+        assert_eq!(rendered, "3");
+        let rendered = hbs.render_template("{{add F 1}}", &data).unwrap();
+        assert_eq!(rendered, "1.5");
+        let rendered = hbs.render_template("{{mul N 3}}", &data).unwrap();
+        assert_eq!(rendered, "6");
+        let rendered = hbs.render_template("{{mul F 3}}", &data).unwrap();
+        assert_eq!(rendered, "1.5");
+
+        assert!(hbs.render_template("{{add N}}", &data).is_err());
+        assert!(hbs.render_template("{{add N 'a'}}", &data).is_err());
+    }
+
+    #[test]
+    fn test_range_helper() {
+        let mut hbs = Handlebars::new();
+        register_helpers(&mut hbs, Path::new("."));
+        let data = json!({"N": 3});
+
+        let template = "{{#range 0 N}}a[{{this}}]={{@index}};{{/range}}";
+        let rendered = hbs.render_template(template, &data).unwrap();
+        assert_eq!(rendered, "a[0]=0;a[1]=1;a[2]=2;");
+
+        let template = "{{#range 1 N}}{{add this ../N}},{{/range}}";
+        let rendered = hbs.render_template(template, &data).unwrap();
+        assert_eq!(rendered, "4,5,");
+
+        let template = "{{#range N N}}empty{{/range}}";
+        let rendered = hbs.render_template(template, &data).unwrap();
+        assert_eq!(rendered, "");
+
+        let template = "{{#range 0 1.5}}{{/range}}";
+        assert!(hbs.render_template(template, &data).is_err());
+    }
+
+    #[test]
+    fn test_include_helper() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let project_path = tmp_dir.path().join("project");
+        std::fs::create_dir_all(project_path.join("common")).unwrap();
+        std::fs::write(project_path.join("common/header.cuh"), "#define N 1").unwrap();
+        std::fs::write(tmp_dir.path().join("outside.cuh"), "").unwrap();
+        let mut hbs = Handlebars::new();
+        register_helpers(&mut hbs, &project_path);
+        let data = json!({});
+
+        let template = r#"{{include "common/header.cuh"}}"#;
+        let rendered = hbs.render_template(template, &data).unwrap();
+        assert_eq!(rendered, "#define N 1");
+
+        let template = r#"{{include "common/missing.cuh"}}"#;
+        let err = hbs.render_template(template, &data).unwrap_err();
+        assert!(err.to_string().contains("file not found"), "got: {}", err);
+
+        let template = r#"{{include "../outside.cuh"}}"#;
+        let err = hbs.render_template(template, &data).unwrap_err();
+        assert!(err.to_string().contains("outside of"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_translate_delimiters() {
+        let mut hbs = Handlebars::new();
+        hbs.register_helper("add", Box::new(add_helper));
+        let data = json!({"N": 10});
+
+        let template = "int a[2][1] = {{<<N>>}, {<<add N 1>>}};";
+        let translated = translate_delimiters(template, "<<", ">>");
+        let rendered = hbs.render_template(&translated, &data).unwrap();
+        assert_eq!(rendered, "int a[2][1] = {{10}, {11}};");
+
+        let template = "[[N]] {{N}} [[N";
+        let translated = translate_delimiters(template, "[[", "]]");
+        let rendered = hbs.render_template(&translated, &data).unwrap();
+        assert_eq!(rendered, "10 {{N}} [[N");
+    }
 }
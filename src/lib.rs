@@ -53,6 +53,7 @@
 //!     /*
 //!     [
 //!         {
+//!             "id": 0,
 //!             "data": {
 //!                 "N": "10"
 //!             },
@@ -61,6 +62,7 @@
 //!             "stdout": "10\n"
 //!         },
 //!         {
+//!             "id": 1,
 //!             "data": {
 //!                 "N": "20"
 //!             },
@@ -140,6 +142,7 @@
 //! ```shell
 //! [
 //!   {
+//!     "id": 0,
 //!     "data": {
 //!       "N": 10
 //!     },
@@ -148,6 +151,7 @@
 //!     "stdout": "10\n"
 //!   },
 //!   {
+//!     "id": 1,
 //!     "data": {
 //!       "N": 20
 //!     },
@@ -204,14 +208,15 @@
 //!    --makefile
 //! ```
 //!
-//! Or specify `.enable_cppflags(true)` when using the rust library.
+//! Or specify `.enable_cppflags(true)` and `.no_template()` when using the rust library.
 //!
 //! # Features
 //!
 //! - Use handlebars template language to generate source file.
 //! - Ignore `.gitignore` files in the project, which may speed up the copying process.
 //! - Support multi-threading compilation/executing, these two parts can share threads, meaning they can be executed immediately after compilation, or they can be separated. For example, four threads can be used for compilation and one thread for execution. This is suitable for scenarios where only one executable file should be active in the system, such as when testing GPU performance. In this case, multiple CPU threads compile in the background while one CPU thread is responsible for execution.
-//! - [TODO] Support multiple template files.
+//! - Support multiple template files, see `Parabuilder::template_files`.
+//! - Pause starting new builds and runs with `kill -USR1 <pid>` and resume with `kill -USR2 <pid>`, see `Parabuilder::pause`.
 //!
 //! # Notes
 //!
@@ -222,15 +227,22 @@
 //! > A measurement of the system clock, useful for talking to external entities like the file system or other processes.
 //! >
 //! > Distinct from the Instant type, this time measurement is not monotonic. This means that you can save a file to the file system, then save another file to the file system, and the second file has a SystemTime measurement earlier than the first. In other words, an operation that happens after another operation in real time may have an earlier SystemTime!
+#![allow(clippy::tabs_in_doc_comments)]
 
 mod cuda_utils;
 mod filesystem_utils;
 mod handlebars_helper;
 mod parabuilder;
-pub use cuda_utils::get_cuda_mig_device_uuids;
+mod process_utils;
+pub use cuda_utils::{
+    get_cuda_device_indices, get_cuda_device_uuids, get_cuda_mig_device_uuids, CudaDeviceMode,
+};
+pub use filesystem_utils::{CopyStrategy, FileReadyCheck};
 pub use parabuilder::{
-    CompliationErrorHandlingMethod, Parabuilder, RunMethod, IGNORE_ON_ERROR_DEFAULT_RUN_FUNC,
-    PANIC_ON_ERROR_DEFAULT_RUN_FUNC,
+    CompileContext, CompliationErrorHandlingMethod, ControlMsg, DataSample, MetricExtractor,
+    Parabuilder, ProgressEvent, RunContext, RunMethod, RunReport, ShellKind, UnprocessedData,
+    UnprocessedReason, VerifyFunc, WorkerStatus, WorkspaceInfo, DEFAULT_COMPILE_FUNC,
+    IGNORE_ON_ERROR_DEFAULT_RUN_FUNC, PANIC_ON_ERROR_DEFAULT_RUN_FUNC,
 };
 
 #[cfg(test)]
@@ -1,45 +1,202 @@
-use clap::Parser;
-use parabuild::{CompliationErrorHandlingMethod, Parabuilder, RunMethod};
+use clap::{Parser, ValueEnum};
+use crossbeam_channel::Sender;
+use parabuild::{
+    CompliationErrorHandlingMethod, CopyStrategy, CudaDeviceMode, DataSample, FileReadyCheck,
+    Parabuilder, RunMethod, RunReport, ShellKind, UnprocessedReason,
+};
 use serde_json::Value as JsonValue;
 use std::collections::HashSet;
+use std::io::BufRead;
+#[cfg(unix)]
 use std::io::Write;
+#[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
 use std::process::Command;
 use std::vec;
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum FailOnError {
+    /// fail when any data fails to compile or run
+    Any,
+    /// fail only when all datas fail to compile or run
+    All,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Shell {
+    Bash,
+    Sh,
+    Powershell,
+    Cmd,
+}
+
+impl From<Shell> for ShellKind {
+    fn from(shell: Shell) -> Self {
+        match shell {
+            Shell::Bash => ShellKind::Bash,
+            Shell::Sh => ShellKind::Sh,
+            Shell::Powershell => ShellKind::Powershell,
+            Shell::Cmd => ShellKind::Cmd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum CopyMode {
+    Copy,
+    Hardlink,
+    Reflink,
+}
+
+impl From<CopyMode> for CopyStrategy {
+    fn from(copy_mode: CopyMode) -> Self {
+        match copy_mode {
+            CopyMode::Copy => CopyStrategy::Copy,
+            CopyMode::Hardlink => CopyStrategy::Hardlink,
+            CopyMode::Reflink => CopyStrategy::Reflink,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum FileReadyMode {
+    Lsof,
+    Stable,
+}
+
+impl From<FileReadyMode> for FileReadyCheck {
+    fn from(file_ready_mode: FileReadyMode) -> Self {
+        match file_ready_mode {
+            FileReadyMode::Lsof => FileReadyCheck::Lsof,
+            FileReadyMode::Stable => FileReadyCheck::Stable,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum CudaMode {
+    Uuid,
+    Index,
+}
+
+impl From<CudaMode> for CudaDeviceMode {
+    fn from(cuda_mode: CudaMode) -> Self {
+        match cuda_mode {
+            CudaMode::Uuid => CudaDeviceMode::Uuid,
+            CudaMode::Index => CudaDeviceMode::Index,
+        }
+    }
+}
 
 #[derive(Parser)]
 #[command(version, author, about, long_about)]
 struct Cli {
     /// project path
-    project_path: PathBuf,
+    #[arg(required_unless_present = "list_workspaces")]
+    project_path: Option<PathBuf>,
 
-    /// target files in the project, which will be moved between build/run workspaces for further processing
+    /// target files or directories in the project, which will be moved between build/run workspaces for further processing
     ///
-    /// e.g. `build/main,data_generate_when_build`
+    /// e.g. `build/main,data_generate_when_build`, glob patterns like `build/*.ptx` are expanded after each compilation
     #[arg(value_delimiter = ',')]
     target_files: Vec<PathBuf>,
 
-    /// template file in the project
-    #[arg(short, long)]
-    template_file: Option<PathBuf>,
+    /// template files in the project
+    ///
+    /// e.g. `src/kernel.cu.template,include/config.h.template`
+    #[arg(short, long, value_delimiter = ',')]
+    template_file: Vec<PathBuf>,
 
     /// where to store the workspaces, executables, etc.
     #[arg(short, long, default_value = ".parabuild/workspaces")]
     workspaces_path: PathBuf,
 
+    /// list the workspaces under `--workspaces-path` with their last modified time and whether they have a build dir, then exit
+    #[arg(long)]
+    list_workspaces: bool,
+
+    /// prefix of the workspace names, give concurrent runs sharing a `--workspaces-path` distinct prefixes
+    #[arg(long)]
+    workspace_prefix: Option<String>,
+
     /// json format data
     #[arg(long)]
     data: Option<String>,
 
-    /// json format data file, when used together with the `--data` option, ignore this option
+    /// data file, when used together with the `--data` option, ignore this option
+    ///
+    /// The format follows the extension: `.yaml`/`.yml` is a YAML list (with the `yaml` feature),
+    /// `.toml` lists the datas as `[[data]]` tables, anything else is json
     #[arg(short, long)]
     data_file: Option<PathBuf>,
 
+    /// read datas from stdin, one json object per line, building starts before all of them are read
+    #[arg(long, conflicts_with_all = ["data", "data_file"])]
+    data_stdin: bool,
+
+    /// with `--data-stdin`, read at most this many datas ahead of the builds
+    #[arg(long, requires = "data_stdin")]
+    data_queue_capacity: Option<usize>,
+
+    /// build every combination of the parameter values instead of a data list,
+    /// a single value is the same as a list of it
+    ///
+    /// e.g. `--grid '{"N": [1, 2], "B": [32, 64]}'` gives 4 datas
+    #[arg(long, conflicts_with_all = ["data", "data_file", "data_stdin"])]
+    grid: Option<String>,
+
+    /// run the targets staged in the `manifest.json` of an earlier run with `--run-workers 0`
+    /// without compiling them, the datas are those of the manifest
+    #[arg(long, conflicts_with_all = ["data", "data_file", "data_stdin", "grid", "continue_from"])]
+    run_targets: Option<PathBuf>,
+
+    /// only build and run `N` datas picked at random, the datas keep their original ids
+    #[arg(long, conflicts_with_all = ["stride", "data_stdin"])]
+    sample: Option<usize>,
+
+    /// the seed of `--sample`, the same seed picks the same datas
+    #[arg(long, default_value = "0")]
+    sample_seed: u64,
+
+    /// only build and run every `K`-th data, the datas keep their original ids
+    #[arg(long, conflicts_with = "data_stdin")]
+    stride: Option<usize>,
+
+    /// only build and run the datas whose `__label` is one of these labels, e.g. `baseline,fast`,
+    /// the datas keep their original ids
+    #[arg(long, value_delimiter = ',')]
+    run_only_labels: Vec<String>,
+
+    /// copy the rendered template files and the compile stderr of datas failed to compile into this directory
+    #[arg(long)]
+    save_rendered_on_error: Option<PathBuf>,
+
+    /// append the compile output of every data to `parabuild_compile.log` in its build workspace
+    #[arg(long)]
+    tee_build_logs: bool,
+
+    /// json schema file, every data is validated against it before building
+    #[arg(long)]
+    data_schema: Option<PathBuf>,
+
     /// output the json format result to a file, default to stdout
     #[arg(short, long)]
     output_file: Option<PathBuf>,
 
+    /// also write each run result to a file as a json line as soon as it finishes
+    #[arg(long)]
+    output_jsonl: Option<PathBuf>,
+
+    /// publish the build/run start and finish events as json lines to the unix socket a monitor
+    /// listens on, e.g. `socat UNIX-LISTEN:/tmp/parabuild.sock -`
+    #[arg(long)]
+    event_socket: Option<PathBuf>,
+
     /// init bash script
     ///
     /// Default to `cmake -S . -B build -DPARABUILD=ON`
@@ -66,6 +223,11 @@ struct Cli {
     #[arg(long)]
     compile_bash_script_file: Option<PathBuf>,
 
+    /// compile bash script file relative to each workspace (a copy of the project), e.g. `scripts/compile.sh`,
+    /// when used together with the `--compile-bash-script` or `--compile-bash-script-file` option, ignore this option
+    #[arg(long)]
+    compile_script_in_project: Option<PathBuf>,
+
     /// make target, when used together with the `--compile-bash-script` or `--compile-bash-script-file` option, ignore this option
     #[arg(short, long)]
     make_target: Option<String>,
@@ -81,14 +243,55 @@ struct Cli {
     #[arg(long)]
     run_bash_script_file: Option<PathBuf>,
 
+    /// run bash script file relative to each workspace (a copy of the project), e.g. `scripts/run.sh`,
+    /// when used together with the `--run-bash-script` or `--run-bash-script-file` option, ignore this option
+    #[arg(long)]
+    run_script_in_project: Option<PathBuf>,
+
     /// do not show progress bar
     #[arg(short, long)]
     silent: bool,
 
+    /// the indicatif template of the building and running bars,
+    /// e.g. `--progress-style "{elapsed} {wide_bar} {pos}/{len} {msg}"`
+    #[arg(long)]
+    progress_style: Option<String>,
+
+    /// draw the progress bars with ASCII characters only, e.g. for CI logs
+    #[arg(long)]
+    progress_ascii: bool,
+
     /// build workers
     #[arg(short = 'j', long)]
     build_workers: Option<usize>,
 
+    /// one build worker per available CPU divided by `--compile-jobs`
+    #[arg(long, conflicts_with = "build_workers")]
+    build_workers_auto: bool,
+
+    /// number of build workspaces, the build workers claim a free one for each data,
+    /// extra workspaces beyond `build_workers` are pre-warmed spares
+    #[arg(long)]
+    workspace_pool_size: Option<usize>,
+
+    /// re-run the init bash script in a build workspace after this many compile failures in a row there
+    #[arg(long)]
+    reset_after_failures: Option<usize>,
+
+    /// stop taking new datas once this many compiles have failed, the rest are left unprocessed
+    #[arg(long)]
+    max_compile_errors: Option<usize>,
+
+    /// parallelism inside a single compile, available as `$PARABUILD_COMPILE_JOBS` in the compile bash script,
+    /// e.g. `make -j$PARABUILD_COMPILE_JOBS`, keep `build_workers * compile_jobs` around the number of CPU cores
+    #[arg(long, visible_alias = "jobs-per-data")]
+    compile_jobs: Option<usize>,
+
+    /// share one ccache/sccache directory between the build workspaces, the compile bash script
+    /// must still invoke the cache, e.g. `-DCMAKE_CXX_COMPILER_LAUNCHER=ccache`
+    #[arg(long)]
+    compiler_cache_dir: Option<PathBuf>,
+
     /// run workers
     ///
     /// We have four execution modes:
@@ -122,14 +325,41 @@ struct Cli {
     #[arg(short = 'J', long)]
     run_workers: Option<isize>,
 
+    /// use one run worker per CUDA device (`--gpu-devices` or the auto-detected MIG devices),
+    /// when used together with the `--run-workers` option, ignore that option
+    #[arg(long)]
+    run_workers_auto_cuda: bool,
+
     /// run in place, which means we will not move the TARGET_FILES between build/run workspaces
     #[arg(long)]
     run_in_place: bool,
 
+    /// run while building like a positive `--run-workers`, but pause the compilations while a
+    /// run is active, e.g. for accurate GPU timing, the number of run workers is `|--run-workers|`
+    #[arg(long, conflicts_with_all = ["run_in_place", "run_workers_auto_cuda"])]
+    interleaved: bool,
+
     /// seperate template file, as opposed to using the same file to render in place
     #[arg(long)]
     seperate_template: bool,
 
+    /// write the template expressions with these delimiters instead of `{{` and `}}`
+    ///
+    /// e.g. `--template-delimiters '<<' '>>'` for templates like `int a[] = {{<<N>>}};`
+    #[arg(long, num_args = 2, value_names = ["OPEN", "CLOSE"])]
+    template_delimiters: Option<Vec<String>>,
+
+    /// render the template file of each data to this path (itself a template) inside the workspace,
+    /// e.g. `--template-output-path-template 'src/kernel_{{N}}.cu'`,
+    /// the compile bash script finds it in `$PARABUILD_TEMPLATE_OUTPUT`
+    #[arg(long)]
+    template_output_path_template: Option<String>,
+
+    /// fail to render a data when the template uses a variable missing from it,
+    /// instead of rendering an empty string, `{{default N 42}}` still works
+    #[arg(long)]
+    strict_templates: bool,
+
     /// Clear the contents in `workspaces` before running
     #[arg(long)]
     no_cache: bool,
@@ -139,6 +369,37 @@ struct Cli {
     #[arg(long)]
     without_rsync: bool,
 
+    /// extra argument of the `rsync -a` that copies the project, can be repeated
+    ///
+    /// e.g. `--rsync-arg=--no-perms --rsync-arg=--no-owner`
+    #[arg(long = "rsync-arg", allow_hyphen_values = true)]
+    rsync_args: Vec<String>,
+
+    /// gitignore-style glob pattern of extra project files not copied into the workspaces,
+    /// can be repeated
+    ///
+    /// e.g. `--copy-exclude tests/fixtures/ --copy-exclude '*.bin'`
+    #[arg(long = "copy-exclude")]
+    copy_excludes: Vec<String>,
+
+    /// how to place the project files into the workspaces, `hardlink` and `reflink` are faster
+    /// for large projects, the template outputs and the target files are always copied
+    #[arg(long, value_enum, default_value = "copy")]
+    copy_strategy: CopyMode,
+
+    /// how the run workers wait for a moved target file to be ready, `lsof` requires `lsof`,
+    /// `stable` waits until the size and mtime of the file stop changing
+    #[arg(long, value_enum, default_value = "lsof")]
+    file_ready_check: FileReadyMode,
+
+    /// poll a moved target file up to this many times before giving up
+    #[arg(long, default_value = "100")]
+    file_ready_attempts: usize,
+
+    /// interval between the polls of a moved target file, e.g. `100ms`
+    #[arg(long, default_value = "100ms")]
+    file_ready_interval: String,
+
     /// Mark that you are actually working on a makefile project
     ///
     /// pass `data` to `CPPFLAGS` environment variable in the compile bash script
@@ -147,10 +408,18 @@ struct Cli {
     #[arg(long)]
     makefile: bool,
 
+    /// do not start the `CPPFLAGS` of `--makefile` with `-DPARABUILD=ON`
+    #[arg(long)]
+    no_parabuild_define: bool,
+
     /// panic on compile error
     #[arg(long)]
     panic_on_compile_error: bool,
 
+    /// abort the whole job on the first run with a non-zero status
+    #[arg(long)]
+    panic_on_run_error: bool,
+
     /// format the output when printing to stdout (only valid when `--output-file` is not provided)
     #[arg(long)]
     format_output: bool,
@@ -173,15 +442,193 @@ struct Cli {
     #[arg(long, default_value = ".parabuild/autosave")]
     autosave_dir: PathBuf,
 
+    /// gzip the autosave files, `--continue` reads both compressed and plain autosaves
+    #[arg(long)]
+    autosave_compress: bool,
+
+    /// number of previous autosaves kept as `.json.{timestamp}`, `0` keeps none
+    #[arg(long, default_value = "1")]
+    autosave_generations: usize,
+
     /// Specify GPU devices to use (can be UUIDs or indices)
     ///
     /// e.g. `--gpu-devices 0,1,2,3` or `--gpu-devices GPU-xxx,GPU-yyy`
     ///
-    /// If not provided, will auto-detect CUDA MIG devices
+    /// If not provided, will auto-detect CUDA devices, see `--cuda-device-mode`.
+    /// A data with the reserved field `"__cuda_device"` runs on that device instead
     #[arg(long, value_delimiter = ',')]
     gpu_devices: Option<Vec<String>>,
+
+    /// how to auto-detect CUDA devices, `uuid` uses the MIG devices or the full GPUs when there is no MIG device,
+    /// `index` uses the GPU indices
+    #[arg(long, value_enum)]
+    cuda_device_mode: Option<CudaMode>,
+
+    /// pin the run bash script of each workspace to one CPU chosen by the workspace id, like `CUDA_VISIBLE_DEVICES`
+    #[arg(long)]
+    pin_run_workers: bool,
+
+    /// CPUs to pin the run workers to, implies `--pin-run-workers`, e.g. `--run-cpus 0,2,4,6`
+    #[arg(long, value_delimiter = ',')]
+    run_cpus: Option<Vec<usize>>,
+
+    /// kill the run bash script when it takes longer than this, e.g. `30s`, `5m`
+    #[arg(long)]
+    run_timeout: Option<String>,
+
+    /// re-execute a failed run bash script up to this many times
+    #[arg(long, default_value = "0")]
+    run_retries: usize,
+
+    /// wait this long before each retry, e.g. `1s`
+    #[arg(long)]
+    run_retry_backoff: Option<String>,
+
+    /// kill the compile bash script when it takes longer than this, e.g. `10m`
+    ///
+    /// the data is then reported as `{"data": ..., "category": "timeout"}` in `compile_error_datas.json`
+    #[arg(long)]
+    compile_timeout: Option<String>,
+
+    /// exit with a non-zero status code when datas fail to compile or run
+    ///
+    /// `--fail-on-error` is the same as `--fail-on-error=any`
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "any")]
+    fail_on_error: Option<FailOnError>,
+
+    /// leave the runs that exit with a non-zero status out of the output,
+    /// they are written to `run_error_datas.json` in the current directory instead
+    #[arg(long)]
+    split_run_results: bool,
+
+    /// only render the templates to check whether every data renders, do not compile or run
+    #[arg(long)]
+    dry_run: bool,
+
+    /// print what the templates render to for the data of this id, or for every data
+    /// when the id is omitted, then exit without initializing any workspace
+    ///
+    /// e.g. `--print-rendered=3`
+    #[arg(long, num_args = 0..=1, require_equals = true, conflicts_with_all = ["data_stdin", "run_targets"])]
+    print_rendered: Option<Option<usize>>,
+
+    /// add `started_at` and `finished_at` timestamps of the run bash script to the output
+    #[arg(long)]
+    record_timestamps: bool,
+
+    /// add the peak resident set size of the run bash script in KiB to the output as `max_rss_kb`
+    #[arg(long)]
+    measure_memory: bool,
+
+    /// do not compile datas rendering identically to an already built one, reuse its target files
+    #[arg(long)]
+    dedupe_identical_renders: bool,
+
+    /// skip the compile of a data rendering identically to the last one built in the same workspace
+    #[arg(long)]
+    trust_incremental: bool,
+
+    /// skip the datas whose successful result is recorded under `<workspaces_path>/.cache` by a prior invocation,
+    /// reporting the recorded result instead, `--no-cache` removes the recorded results
+    #[arg(long)]
+    result_cache: bool,
+
+    /// copy and init each run workspace when its run worker gets the first data to run,
+    /// instead of before building
+    #[arg(long)]
+    lazy_run_workspace_init: bool,
+
+    /// shell used to run the init/compile/run scripts
+    #[arg(long, value_enum, default_value = "bash")]
+    shell: Shell,
+
+    /// extra environment variables for the init/compile/run scripts, can be repeated
+    ///
+    /// e.g. `--env CC=clang --env CUDA_ARCH=80`
+    #[arg(long = "env", value_parser = parse_env)]
+    envs: Vec<(String, String)>,
+
+    /// tag the datas failed to compile whose stderr or stdout matches REGEX with LABEL in
+    /// `compile_error_datas.json`, can be repeated, the first match wins
+    ///
+    /// e.g. `--compile-error-category overload='no matching function' --compile-error-category ptxas='ptxas error'`
+    #[arg(long = "compile-error-category", value_parser = parse_env, value_name = "LABEL=REGEX")]
+    compile_error_categories: Vec<(String, String)>,
+
+    /// write the datas whose compile succeeded with warnings to `warning_datas.json` in the current directory,
+    /// with the stderr lines matching `--warning-regex`
+    #[arg(long)]
+    collect_warnings: bool,
+
+    /// the regex matched against each compile stderr line by `--collect-warnings`
+    #[arg(long, default_value = Parabuilder::DEFAULT_WARNING_REGEX)]
+    warning_regex: String,
+
+    /// expose each field of the data to the compile/run scripts as an environment variable with this prefix
+    ///
+    /// e.g. with `--data-env-prefix PARA_`, data `{"N": 10}` gives `PARA_N=10`
+    #[arg(long)]
+    data_env_prefix: Option<String>,
+
+    /// run the run script in this directory relative to the workspace, e.g. `build`
+    #[arg(long)]
+    run_cwd: Option<PathBuf>,
+
+    /// run the compile bash script in this directory relative to the workspace, e.g. `cpp`
+    #[arg(long)]
+    compile_cwd: Option<PathBuf>,
+
+    /// the directory the compile bash script puts the artifacts in, relative to the workspace,
+    /// target files are resolved relative to it and the run bash script runs in it by default
+    #[arg(long)]
+    build_dir: Option<PathBuf>,
+
+    /// sort the results by the index of their data instead of the order the runs finish in
+    #[arg(long)]
+    sort_output_by_id: bool,
+
+    /// render the compile and run bash scripts as handlebars templates against each data,
+    /// e.g. `--compile-bash-script "cmake --build build --target {{target}}"`, the values are not escaped
+    #[arg(long)]
+    templated_scripts: bool,
+
+    /// render this handlebars template against each data and write it to the stdin of the run bash script
+    #[arg(long)]
+    run_stdin_template: Option<String>,
+
+    /// handlebars template of an argument passed to the first target, which is then executed directly
+    /// instead of through bash unless a run bash script is provided, can be repeated,
+    /// e.g. `--run-arg --size --run-arg "{{N}}"`
+    #[arg(long = "run-arg", allow_hyphen_values = true)]
+    run_args: Vec<String>,
+
+    /// execute the run script this many extra times before the recorded run of each data, discarding their outputs
+    #[arg(long, default_value = "0")]
+    warmup_runs: usize,
+
+    /// run each data this many times and add `repeats` and the `stats` (min/max/mean/stddev)
+    /// of the samples to its result, the samples are `run_ms` unless `--metric-regex` is set
+    #[arg(long, default_value = "1")]
+    repeat_runs: usize,
+
+    /// sample the first capture group (or the whole match) of this regex in the stdout of each run
+    /// as a number, e.g. `--metric-regex 'latency: ([0-9.]+)'`
+    #[arg(long)]
+    metric_regex: Option<String>,
+
+    /// parse the stdout of each run as json and lift the value at this JSON Pointer into `metric`,
+    /// e.g. `--extract-json /result/latency`
+    #[arg(long)]
+    extract_json: Option<String>,
 }
 
+fn parse_env(env: &str) -> Result<(String, String), String> {
+    env.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("invalid KEY=VALUE: no `=` found in `{}`", env))
+}
+
+#[cfg(unix)]
 fn _command_platform_specific_behavior_check() {
     fn create_file_with_executable_permission(file_path: &str, msg: &str) {
         if let Some(parent) = std::path::Path::new(file_path).parent() {
@@ -191,7 +638,7 @@ fn _command_platform_specific_behavior_check() {
         let mut perms = file.metadata().unwrap().permissions();
         perms.set_mode(0o755);
         file.set_permissions(perms).unwrap();
-        write!(file, "{}", "#!/bin/bash\n").unwrap();
+        writeln!(file, "#!/bin/bash").unwrap();
         writeln!(file, "{}", msg).unwrap();
     }
     create_file_with_executable_permission("test.sh", "echo parent");
@@ -222,24 +669,161 @@ fn is_empty(value: &JsonValue) -> bool {
     }
 }
 
-fn main() {
-    let args = Cli::parse();
-    let data = if let Some(data_str) = args.data {
-        if data_str.is_empty() {
-            panic!("data must not be empty");
+/// Parse the content of a data file by the extension of `data_path` into json,
+/// a TOML file gives the array of its `[[data]]` tables
+fn parse_data_file(data_path: &Path, data_str: &str) -> Result<JsonValue, String> {
+    let extension = data_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        #[cfg(feature = "yaml")]
+        Some("yaml") | Some("yml") => serde_norway::from_str(data_str).map_err(|e| e.to_string()),
+        #[cfg(not(feature = "yaml"))]
+        Some("yaml") | Some("yml") => {
+            Err("yaml data files need parabuild built with the `yaml` feature".to_string())
         }
-        JsonValue::from_str(&data_str).unwrap()
-    } else if let Some(data_path) = args.data_file {
-        if !data_path.exists() {
-            panic!("data file not exists");
+        Some("toml") => {
+            let mut table: JsonValue = toml::from_str(data_str).map_err(|e| e.to_string())?;
+            match table.get_mut("data").map(JsonValue::take) {
+                Some(datas @ JsonValue::Array(_)) => Ok(datas),
+                _ => Err("toml data file must list the datas as `[[data]]` tables".to_string()),
+            }
         }
-        let data_str = std::fs::read_to_string(data_path).unwrap();
-        JsonValue::from_str(&data_str).unwrap()
-    } else {
-        panic!("either `--data` or `--data-file` must be provided");
+        _ => JsonValue::from_str(data_str).map_err(|e| e.to_string()),
+    }
+}
+
+/// `{"N": [1, 2], "B": 32}` => the datas of every combination, see `Parabuilder::cartesian_datas`
+fn parse_grid(grid_str: &str) -> Result<Vec<JsonValue>, String> {
+    let params = match JsonValue::from_str(grid_str).map_err(|e| e.to_string())? {
+        JsonValue::Object(params) => params,
+        _ => return Err("grid must be a json object of parameter lists".to_string()),
     };
+    Ok(Parabuilder::cartesian_datas(params.into_iter().map(
+        |(key, values)| match values {
+            JsonValue::Array(values) => (key, values),
+            value => (key, vec![value]),
+        },
+    )))
+}
+
+/// Send each non-empty line of `reader` as a data to `data_queue_sender`, skipping `skip_ids`,
+/// returns the number of datas read with one of `labels`, every data if `labels` is empty
+fn read_ndjson_datas<R: BufRead>(
+    reader: R,
+    data_queue_sender: Sender<(usize, JsonValue)>,
+    skip_ids: &HashSet<usize>,
+    labels: &[String],
+) -> usize {
+    let mut id = 0;
+    let mut selected = 0;
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.expect("failed to read stdin");
+        if line.trim().is_empty() {
+            continue;
+        }
+        let data = match JsonValue::from_str(&line) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("invalid json in stdin line {}: {}", line_number + 1, e);
+                std::process::exit(1);
+            }
+        };
+        if labels.is_empty() || Parabuilder::has_label(&data, labels) {
+            if !skip_ids.contains(&id) && data_queue_sender.send((id, data)).is_err() {
+                break;
+            }
+            selected += 1;
+        }
+        id += 1;
+    }
+    selected
+}
+
+fn list_workspaces(workspaces_path: &PathBuf) {
+    let workspaces = Parabuilder::list_workspaces(workspaces_path);
+    if workspaces.is_empty() {
+        println!("No workspace found in {:?}", workspaces_path);
+        return;
+    }
+    println!("{:<32}{:<24}build dir", "workspace", "last modified");
+    for workspace in workspaces {
+        let modified: chrono::DateTime<chrono::Local> = workspace.modified.into();
+        println!(
+            "{:<32}{:<24}{}",
+            workspace.path.file_name().unwrap().to_string_lossy(),
+            modified.format("%Y-%m-%d %H:%M:%S").to_string(),
+            if workspace.has_build_dir { "yes" } else { "no" }
+        );
+    }
+}
+
+/// Print the rendered templates of `datas[id]`, or of every data, for `--print-rendered`
+fn print_rendered(
+    parabuilder: &Parabuilder,
+    datas: &[JsonValue],
+    id: Option<usize>,
+) -> Result<(), String> {
+    let ids = match id {
+        Some(id) if id >= datas.len() => {
+            return Err(format!("data id {} out of range 0..{}", id, datas.len()))
+        }
+        Some(id) => id..id + 1,
+        None => 0..datas.len(),
+    };
+    for id in ids {
+        let rendered = parabuilder
+            .render_data(&datas[id])
+            .map_err(|e| format!("data {}: {}", id, e))?;
+        println!("==> data {}: {} <==", id, datas[id]);
+        for (path, content) in rendered {
+            println!("--- {}", path.display());
+            println!("{}", content);
+        }
+    }
+    Ok(())
+}
 
-    let datas = data.as_array().expect("data must be an array").to_owned();
+fn main() {
+    let args = Cli::parse();
+    if args.list_workspaces {
+        list_workspaces(&args.workspaces_path);
+        return;
+    }
+    let datas = if args.data_stdin || args.run_targets.is_some() {
+        None
+    } else {
+        let data = if let Some(grid_str) = args.grid {
+            match parse_grid(&grid_str) {
+                Ok(datas) => JsonValue::Array(datas),
+                Err(e) => {
+                    eprintln!("invalid grid: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        } else if let Some(data_str) = args.data {
+            if data_str.is_empty() {
+                panic!("data must not be empty");
+            }
+            JsonValue::from_str(&data_str).unwrap()
+        } else if let Some(data_path) = args.data_file {
+            if !data_path.exists() {
+                panic!("data file not exists");
+            }
+            let data_str = std::fs::read_to_string(&data_path).unwrap();
+            match parse_data_file(&data_path, &data_str) {
+                Ok(data) => data,
+                Err(e) => {
+                    eprintln!("invalid data file {:?}: {}", data_path, e);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            panic!("either `--data`, `--data-file`, `--data-stdin` or `--grid` must be provided");
+        };
+        Some(data.as_array().expect("data must be an array").to_owned())
+    };
 
     let init_bash_script = if args.no_init {
         Some("".to_string())
@@ -266,19 +850,55 @@ fn main() {
         .expect("invalid autosave interval")
         .as_secs();
 
+    let file_ready_interval =
+        humantime::parse_duration(&args.file_ready_interval).expect("invalid file ready interval");
+
     let mut parabuilder = Parabuilder::new(
-        args.project_path,
+        args.project_path.unwrap(),
         args.workspaces_path,
-        args.template_file.unwrap_or_else(|| PathBuf::from("")),
+        "",
         &args.target_files,
     )
+    .template_files(&args.template_file)
     .in_place_template(!args.seperate_template)
     .disable_progress_bar(args.silent)
+    .progress_ascii(args.progress_ascii)
     .no_cache(args.no_cache)
     .without_rsync(args.without_rsync)
+    .rsync_args(&args.rsync_args)
+    .copy_excludes(&args.copy_excludes)
+    .copy_strategy(args.copy_strategy.into())
+    .file_ready_check(args.file_ready_check.into())
+    .file_ready_poll(args.file_ready_attempts, file_ready_interval)
     .enable_cppflags(args.makefile)
+    .parabuild_define(!args.no_parabuild_define)
     .autosave_interval(autosave_interval_secs)
     .autosave_dir(args.autosave_dir)
+    .autosave_compress(args.autosave_compress)
+    .autosave_generations(args.autosave_generations)
+    .dry_run(args.dry_run)
+    .record_timestamps(args.record_timestamps)
+    .measure_memory(args.measure_memory)
+    .strict_templates(args.strict_templates)
+    .tee_build_logs(args.tee_build_logs)
+    .dedupe_identical_renders(args.dedupe_identical_renders)
+    .trust_incremental(args.trust_incremental)
+    .result_cache(args.result_cache)
+    .lazy_run_workspace_init(args.lazy_run_workspace_init)
+    .panic_on_run_error(args.panic_on_run_error)
+    .templated_scripts(args.templated_scripts)
+    .sort_output_by_id(args.sort_output_by_id)
+    .shell(args.shell.into())
+    .envs(args.envs.into_iter().collect())
+    .compile_error_categories(
+        &args
+            .compile_error_categories
+            .iter()
+            .map(|(label, regex)| (regex, label))
+            .collect::<Vec<_>>(),
+    )
+    .collect_warnings(args.collect_warnings)
+    .warning_regex(&args.warning_regex)
     .compilation_error_handling_method(if args.panic_on_compile_error {
         CompliationErrorHandlingMethod::Panic
     } else {
@@ -293,6 +913,9 @@ fn main() {
         Some(compile_bash_script)
     } else if let Some(compile_bash_script_file) = args.compile_bash_script_file {
         Some(std::fs::read_to_string(compile_bash_script_file).unwrap())
+    } else if let Some(compile_script_in_project) = args.compile_script_in_project {
+        parabuilder = parabuilder.compile_script_in_project(compile_script_in_project);
+        None
     } else if let Some(target) = args.make_target {
         if !args.makefile {
             Some(format!(r#"cmake --build build --target {} -- -B"#, target))
@@ -316,6 +939,10 @@ fn main() {
     } else if let Some(run_bash_script_file) = args.run_bash_script_file {
         let run_bash_script = std::fs::read_to_string(run_bash_script_file).unwrap();
         parabuilder = parabuilder.run_bash_script(&run_bash_script);
+    } else if let Some(run_script_in_project) = args.run_script_in_project {
+        parabuilder = parabuilder.run_script_in_project(run_script_in_project);
+    } else if !args.run_args.is_empty() {
+        parabuilder = parabuilder.run_args_template(&args.run_args);
     } else {
         println!(
             "Warning: no run bash script provided, we will run {} directly",
@@ -329,58 +956,296 @@ fn main() {
         parabuilder = parabuilder.build_workers(build_workers);
     }
 
+    if let Some(workspace_prefix) = &args.workspace_prefix {
+        parabuilder = parabuilder.workspace_prefix(workspace_prefix);
+    }
+
+    if let Some(workspace_pool_size) = args.workspace_pool_size {
+        parabuilder = parabuilder.workspace_pool_size(workspace_pool_size);
+    }
+
+    if let Some(reset_after_failures) = args.reset_after_failures {
+        parabuilder = parabuilder.reset_after_failures(reset_after_failures);
+    }
+    if let Some(max_compile_errors) = args.max_compile_errors {
+        parabuilder = parabuilder.max_compile_errors(max_compile_errors);
+    }
+
+    if let Some(compile_jobs) = args.compile_jobs {
+        parabuilder = parabuilder.compile_jobs(compile_jobs);
+    }
+    // after `compile_jobs`, which it divides the CPUs by
+    if args.build_workers_auto {
+        parabuilder = parabuilder.build_workers_auto();
+    }
+
+    if let Some(compiler_cache_dir) = args.compiler_cache_dir {
+        parabuilder = parabuilder.compiler_cache_dir(compiler_cache_dir);
+    }
+
     if let Some(run_workers) = args.run_workers {
         if !args.run_in_place {
             parabuilder = parabuilder.run_workers(run_workers);
         }
     }
 
+    if args.interleaved {
+        let run_workers = args
+            .run_workers
+            .map_or(1, |run_workers| run_workers.unsigned_abs());
+        parabuilder = parabuilder.run_method(RunMethod::Interleaved(run_workers.max(1)));
+    }
+
     if args.run_in_place {
         parabuilder = parabuilder.run_method(RunMethod::InPlace);
     }
 
+    if args.run_workers_auto_cuda && !args.run_in_place {
+        parabuilder = parabuilder.run_workers_auto_cuda();
+    }
+
+    if let Some(save_rendered_on_error) = args.save_rendered_on_error {
+        parabuilder = parabuilder.save_rendered_on_error(save_rendered_on_error);
+    }
+
+    if let Some(data_schema) = args.data_schema {
+        let data_schema = std::fs::read_to_string(data_schema).unwrap();
+        parabuilder = parabuilder.data_schema(
+            JsonValue::from_str(&data_schema).expect("data schema must be a valid json"),
+        );
+    }
+    if !args.run_only_labels.is_empty() {
+        parabuilder = parabuilder.run_only_labels(&args.run_only_labels);
+    }
+
     if let Some(gpu_devices) = args.gpu_devices {
         parabuilder = parabuilder.gpu_devices(gpu_devices);
     }
 
+    if let Some(cuda_device_mode) = args.cuda_device_mode {
+        parabuilder = parabuilder.cuda_device_mode(cuda_device_mode.into());
+    }
+
+    parabuilder = parabuilder.pin_run_workers(args.pin_run_workers);
+
+    if let Some(run_cpus) = args.run_cpus {
+        parabuilder = parabuilder.run_cpus(run_cpus);
+    }
+
+    if let Some(data_env_prefix) = args.data_env_prefix {
+        parabuilder = parabuilder.data_env_prefix(&data_env_prefix);
+    }
+
+    if let Some(run_cwd) = args.run_cwd {
+        parabuilder = parabuilder.run_cwd(run_cwd);
+    }
+    if let Some(compile_cwd) = args.compile_cwd {
+        parabuilder = parabuilder.compile_cwd(compile_cwd);
+    }
+    if let Some(build_dir) = args.build_dir {
+        parabuilder = parabuilder.build_dir(build_dir);
+    }
+    if let Some(progress_style) = args.progress_style {
+        parabuilder = parabuilder.progress_style(progress_style);
+    }
+    if let Some(run_stdin_template) = args.run_stdin_template {
+        parabuilder = parabuilder.run_stdin_template(run_stdin_template);
+    }
+
+    parabuilder = parabuilder
+        .warmup_runs(args.warmup_runs)
+        .repeat_runs(args.repeat_runs);
+
+    if let Some(metric_regex) = args.metric_regex {
+        let metric_regex = regex::Regex::new(&metric_regex).expect("invalid metric regex");
+        parabuilder = parabuilder.metric_extractor(move |stdout| {
+            let captures = metric_regex.captures(stdout)?;
+            let metric = captures.get(1).or_else(|| captures.get(0))?;
+            metric.as_str().trim().parse().ok()
+        });
+    }
+    if let Some(extract_json) = args.extract_json {
+        parabuilder = parabuilder.extract_json(extract_json);
+    }
+
+    if let Some(template_delimiters) = args.template_delimiters {
+        parabuilder =
+            parabuilder.template_delimiters(&template_delimiters[0], &template_delimiters[1]);
+    }
+
+    if let Some(template_output_path_template) = args.template_output_path_template {
+        parabuilder = parabuilder.template_output_path_template(template_output_path_template);
+    }
+
+    if let Some(output_jsonl) = args.output_jsonl {
+        parabuilder = parabuilder
+            .stream_output_writer(Box::new(std::fs::File::create(output_jsonl).unwrap()));
+    }
+
+    if let Some(data_queue_capacity) = args.data_queue_capacity {
+        parabuilder = parabuilder.data_queue_capacity(data_queue_capacity);
+    }
+
+    if let Some(event_socket) = args.event_socket {
+        parabuilder = parabuilder.event_socket(event_socket);
+    }
+
+    if let Some(run_timeout) = args.run_timeout {
+        parabuilder = parabuilder
+            .run_timeout(humantime::parse_duration(&run_timeout).expect("invalid run timeout"));
+    }
+
+    parabuilder = parabuilder.run_retries(args.run_retries);
+
+    if let Some(run_retry_backoff) = args.run_retry_backoff {
+        parabuilder = parabuilder.run_retry_backoff(
+            humantime::parse_duration(&run_retry_backoff).expect("invalid run retry backoff"),
+        );
+    }
+
+    if let Some(compile_timeout) = args.compile_timeout {
+        parabuilder = parabuilder.compile_timeout(
+            humantime::parse_duration(&compile_timeout).expect("invalid compile timeout"),
+        );
+    }
+
+    if let Some(id) = args.print_rendered {
+        if let Err(e) = print_rendered(&parabuilder, datas.as_deref().unwrap(), id) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let (last_run_datas, last_comile_error_datas, last_processed_data_ids) =
         match args.continue_from {
             Some(cont) => parabuilder.autosave_load(cont),
             None => (JsonValue::Null, vec![], vec![]),
         };
 
-    let processed_data_ids_set: HashSet<usize> =
-        last_processed_data_ids.clone().into_iter().collect();
-
-    let datas_len = datas.len();
-    // parabuilder.set_datas(datas).unwrap();
-    parabuilder
-        .set_datas_with_processed_data_ids_set(datas, processed_data_ids_set)
-        .unwrap();
-    parabuilder.init_workspace().unwrap();
-    let (mut run_data, mut compile_error_datas, mut processed_data_ids): (
-        JsonValue,
-        Vec<JsonValue>,
-        Vec<usize>,
-    ) = parabuilder.run().unwrap();
+    let mut datas_len = 0;
+    let stdin_reader = match datas {
+        Some(datas) => {
+            let selected = |datas: &[JsonValue], id: usize| {
+                args.run_only_labels.is_empty()
+                    || Parabuilder::has_label(&datas[id], &args.run_only_labels)
+            };
+            let sample = match (args.sample, args.stride) {
+                (Some(count), _) => Some(DataSample::Random {
+                    count,
+                    seed: args.sample_seed,
+                }),
+                (_, Some(k)) => Some(DataSample::Stride(k)),
+                _ => None,
+            };
+            // the datas processed before `--continue` are skipped
+            let result = match sample {
+                Some(sample) => sample.sample_ids(datas.len()).and_then(|ids| {
+                    datas_len = ids.iter().filter(|id| selected(&datas, **id)).count();
+                    parabuilder.set_datas_sampled(datas, sample)
+                }),
+                None => {
+                    datas_len = (0..datas.len()).filter(|id| selected(&datas, *id)).count();
+                    parabuilder.set_datas(datas)
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            None
+        }
+        None if args.run_targets.is_some() => None,
+        None => {
+            let data_queue_sender = parabuilder.get_data_queue_sender().unwrap();
+            let skip_ids: HashSet<usize> = last_processed_data_ids.iter().copied().collect();
+            let labels = args.run_only_labels.clone();
+            Some(std::thread::spawn(move || {
+                read_ndjson_datas(
+                    std::io::stdin().lock(),
+                    data_queue_sender,
+                    &skip_ids,
+                    &labels,
+                )
+            }))
+        }
+    };
+    if let Err(problems) = parabuilder.validate() {
+        for problem in problems.iter() {
+            eprintln!("{}", problem);
+        }
+        std::process::exit(1);
+    }
+    if let Err(e) = parabuilder.init_workspace() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let (run_data, mut compile_error_datas, mut processed_data_ids) = match &args.run_targets {
+        Some(manifest) => {
+            let run_output = parabuilder.run_targets(manifest).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+            datas_len = run_output.2.len();
+            run_output
+        }
+        None => parabuilder.run().unwrap(),
+    };
+    if let Some(stdin_reader) = stdin_reader {
+        // a stopped run leaves the reader blocked on a full bounded queue
+        parabuilder.close_data_queue();
+        datas_len = stdin_reader.join().unwrap();
+    }
 
     compile_error_datas.extend(last_comile_error_datas);
     processed_data_ids.extend(last_processed_data_ids);
 
-    (run_data, compile_error_datas, processed_data_ids) = parabuilder
+    let mut report: RunReport = parabuilder
         .gather_data(
             vec![run_data, last_run_datas],
             compile_error_datas,
             processed_data_ids,
         )
-        .unwrap();
+        .unwrap()
+        .into();
+    report.unprocessed = parabuilder.unprocessed_datas();
+    report.warning_datas = parabuilder.warning_datas();
+    if args.split_run_results {
+        report.split_run_errors();
+    }
+    let RunReport {
+        run_data,
+        run_errors: run_error_datas,
+        compile_errors: compile_error_datas,
+        warning_datas,
+        processed_ids: processed_data_ids,
+        unprocessed: unprocessed_datas,
+    } = &report;
+
+    if args.dry_run {
+        let render_error_datas = compile_error_datas;
+        for render_error_data in render_error_datas.iter() {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&render_error_data).unwrap()
+            );
+        }
+        println!();
+        println!("Render Summary");
+        println!("===================");
+        println!(
+            "Success: {}\tFailed: {}",
+            report.compile_success(),
+            render_error_datas.len()
+        );
+        if args.fail_on_error.is_some() && !render_error_datas.is_empty() {
+            std::process::exit(1);
+        }
+        return;
+    }
 
     if let Some(output_file) = args.output_file {
-        std::fs::write(
-            output_file,
-            serde_json::to_string_pretty(&run_data).unwrap(),
-        )
-        .unwrap();
+        std::fs::write(output_file, serde_json::to_string_pretty(run_data).unwrap()).unwrap();
     } else {
         if args.format_output {
             for data in run_data.as_array().unwrap().iter() {
@@ -398,7 +1263,7 @@ fn main() {
                 println!();
             }
         } else {
-            println!("{}", serde_json::to_string_pretty(&run_data).unwrap());
+            println!("{}", serde_json::to_string_pretty(run_data).unwrap());
         }
     }
 
@@ -408,6 +1273,21 @@ fn main() {
 
     if processed_data_len != datas_len {
         println!("Unprocessed: {}", datas_len - processed_data_len);
+        for reason in [
+            UnprocessedReason::Cancelled,
+            UnprocessedReason::NotStarted,
+            UnprocessedReason::Stopped,
+            UnprocessedReason::Aborted,
+        ] {
+            let ids: Vec<usize> = unprocessed_datas
+                .iter()
+                .filter(|unprocessed_data| unprocessed_data.reason == reason)
+                .map(|unprocessed_data| unprocessed_data.id)
+                .collect();
+            if !ids.is_empty() {
+                println!("{:?}: {:?}", reason, ids);
+            }
+        }
         println!();
     }
 
@@ -415,37 +1295,118 @@ fn main() {
     println!("===================");
     println!(
         "Success: {}\tFailed: {}",
-        processed_data_ids.len() - compile_error_datas.len(),
+        report.compile_success(),
         compile_error_datas.len()
     );
+    let categories = report.compile_error_categories();
+    // nothing to break down when no error has a category
+    if categories
+        .iter()
+        .any(|(category, _)| category != "uncategorized")
+    {
+        for (category, count) in categories {
+            println!("  {}: {}", category, count);
+        }
+    }
+    if args.collect_warnings {
+        println!("With warnings: {}", warning_datas.len());
+    }
     println!();
     println!("Execution Summary");
     println!("===================");
-    if run_data.is_array()
-        && run_data.as_array().unwrap().len() > 0
-        && run_data.as_array().unwrap()[0].is_object()
-        && !run_data.as_array().unwrap()[0]["status"].is_null()
-    {
-        let success = run_data
-            .as_array()
-            .unwrap()
-            .iter()
-            .filter(|data| data["status"].as_i64().unwrap() == 0)
-            .count();
-        let failed = run_data.as_array().unwrap().len() - success;
-        println!("Success: {}\tFailed: {}", success, failed);
-    } else {
-        if is_empty(&run_data) {
-            println!("Empty run_data");
+    let run_summary =
+        if let (Some(success), Some(failed)) = (report.run_success(), report.run_failed()) {
+            println!("Success: {}\tFailed: {}", success, failed);
+            Some((success, failed))
         } else {
-            println!("Unknown run_data format, please check the output");
-        }
-    }
+            if is_empty(run_data) {
+                println!("Empty run_data");
+            } else {
+                println!("Unknown run_data format, please check the output");
+            }
+            None
+        };
 
     // write compile error datas to current directory
     std::fs::write(
         "compile_error_datas.json",
-        serde_json::to_string_pretty(&compile_error_datas).unwrap(),
+        serde_json::to_string_pretty(compile_error_datas).unwrap(),
     )
     .unwrap();
+    if args.collect_warnings {
+        std::fs::write(
+            "warning_datas.json",
+            serde_json::to_string_pretty(warning_datas).unwrap(),
+        )
+        .unwrap();
+    }
+    if args.split_run_results {
+        std::fs::write(
+            "run_error_datas.json",
+            serde_json::to_string_pretty(run_error_datas).unwrap(),
+        )
+        .unwrap();
+    }
+
+    if let Some(fail_on_error) = args.fail_on_error {
+        let compile_failed = compile_error_datas.len();
+        let compile_success = report.compile_success();
+        // when nothing is run, only the compilation counts
+        let (run_success, run_failed) = run_summary.unwrap_or((compile_success, 0));
+        let failed = match fail_on_error {
+            FailOnError::Any => compile_failed > 0 || run_failed > 0,
+            FailOnError::All => processed_data_len > 0 && run_success == 0,
+        };
+        if failed {
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_grid() {
+        assert_eq!(
+            parse_grid(r#"{"B": [32, 64], "N": 1}"#).unwrap(),
+            vec![json!({"B": 32, "N": 1}), json!({"B": 64, "N": 1})]
+        );
+        assert!(parse_grid("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_parse_data_file() {
+        let expected = json!([{"N": 10, "name": "a"}, {"N": 20, "name": "b"}]);
+        let json = r#"[{"N": 10, "name": "a"}, {"N": 20, "name": "b"}]"#;
+        assert_eq!(
+            parse_data_file(Path::new("data.json"), json).unwrap(),
+            expected
+        );
+        assert_eq!(parse_data_file(Path::new("data"), json).unwrap(), expected);
+        let toml = "[[data]]\nN = 10\nname = \"a\"\n\n[[data]]\nN = 20\nname = \"b\"\n";
+        assert_eq!(
+            parse_data_file(Path::new("data.toml"), toml).unwrap(),
+            expected
+        );
+        assert!(parse_data_file(Path::new("data.toml"), "N = 10").is_err());
+        assert!(parse_data_file(Path::new("data.yaml"), "- N: [").is_err());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_parse_yaml_data_file() {
+        let expected = json!([{"N": 10, "name": "a"}, {"N": 20, "name": "b"}]);
+        let yaml = "- N: 10\n  name: a\n- N: 20\n  name: b\n";
+        assert_eq!(
+            parse_data_file(Path::new("data.yaml"), yaml).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_data_file(Path::new("data.YML"), yaml).unwrap(),
+            expected
+        );
+    }
 }
@@ -1,16 +1,24 @@
-use crate::cuda_utils::get_cuda_mig_device_uuids;
+use crate::cuda_utils::{get_cuda_device_indices, get_cuda_device_uuids, CudaDeviceMode};
 use crate::filesystem_utils::{
-    copy_dir, copy_dir_with_ignore, copy_dir_with_rsync, is_command_installed,
-    wait_until_file_ready,
+    copy_dir, copy_dir_with_ignore, copy_dir_with_rsync, copy_dir_with_strategy, copy_target,
+    is_command_installed, move_target, resolve_target_files, wait_until_file_ready, CopyStrategy,
+    FileReadyCheck, FileReadyWait,
 };
 use crate::handlebars_helper::*;
+use crate::process_utils::{
+    allowed_cpus, output_of_steps_with_max_rss, output_of_steps_with_timeout, pin_command_to_cpu,
+};
 use chrono::Local;
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use handlebars::Handlebars;
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use handlebars::{Handlebars, RenderError, RenderErrorReason};
 use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use regex::Regex;
 use serde_json::{json, Value as JsonValue};
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::error::Error;
 use std::io::Write;
@@ -18,11 +26,11 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::OnceLock;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Condvar, Mutex,
 };
 use std::thread::JoinHandle;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use tempfile::tempdir;
 use uuid::Uuid;
 
@@ -31,7 +39,8 @@ use uuid::Uuid;
 pub enum CompliationErrorHandlingMethod {
     /// Just ignore this data
     Ignore,
-    /// Collect this data into `compile_error_datas` returned by `run()`
+    /// Collect this data into `compile_error_datas` returned by `run()`,
+    /// as `{"data": data, "category": ...}`
     Collect,
     /// Panic when there is a compilation error
     Panic,
@@ -48,11 +57,69 @@ pub enum RunMethod {
     OutOfPlace(usize),
     /// After compile, run in a `usize` thread/workspace
     Exclusive(usize),
+    /// Like `OutOfPlace`, but no compilation runs while any of the `usize` run workers is running,
+    /// the builds pause while a run is active and resume after, e.g. for accurate GPU timing
+    Interleaved(usize),
+}
+
+/// Shell used to run the init/compile/run scripts
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum ShellKind {
+    /// `bash -c <script>`
+    #[default]
+    Bash,
+    /// `sh -c <script>`
+    Sh,
+    /// `powershell -Command <script>`
+    Powershell,
+    /// `cmd /C <script>`
+    Cmd,
+}
+
+impl ShellKind {
+    /// The interpreter to invoke
+    pub fn program(&self) -> &'static str {
+        match self {
+            ShellKind::Bash => "bash",
+            ShellKind::Sh => "sh",
+            ShellKind::Powershell => "powershell",
+            ShellKind::Cmd => "cmd",
+        }
+    }
+
+    /// The flag telling the interpreter to run the following script
+    pub fn flag(&self) -> &'static str {
+        match self {
+            ShellKind::Bash | ShellKind::Sh => "-c",
+            ShellKind::Powershell => "-Command",
+            ShellKind::Cmd => "/C",
+        }
+    }
+
+    /// Build a `Command` that runs `script` with this shell
+    pub fn command(&self, script: &str) -> Command {
+        let mut command = Command::new(self.program());
+        command.arg(self.flag()).arg(script);
+        command
+    }
 }
 
 static CUDA_DEVICE_UUIDS: OnceLock<Vec<String>> = OnceLock::new();
+static CUDA_DEVICE_INDICES: OnceLock<Vec<String>> = OnceLock::new();
 
-fn get_cuda_device_uuid_by_id(id: usize, custom_devices: &Option<Vec<String>>) -> Option<String> {
+/// The auto-detected CUDA devices, detected once per process
+fn detected_cuda_devices(cuda_device_mode: CudaDeviceMode) -> &'static [String] {
+    match cuda_device_mode {
+        CudaDeviceMode::Uuid => CUDA_DEVICE_UUIDS.get_or_init(get_cuda_device_uuids),
+        CudaDeviceMode::Index => CUDA_DEVICE_INDICES.get_or_init(get_cuda_device_indices),
+    }
+}
+
+fn get_cuda_device_uuid_by_id(
+    id: usize,
+    custom_devices: &Option<Vec<String>>,
+    cuda_device_mode: CudaDeviceMode,
+) -> Option<String> {
     // If custom devices are specified, use them
     if let Some(devices) = custom_devices {
         if id < devices.len() {
@@ -63,7 +130,7 @@ fn get_cuda_device_uuid_by_id(id: usize, custom_devices: &Option<Vec<String>>) -
     }
 
     // Otherwise, use auto-detected CUDA devices
-    let cuda_device_uuids = CUDA_DEVICE_UUIDS.get_or_init(|| get_cuda_mig_device_uuids());
+    let cuda_device_uuids = detected_cuda_devices(cuda_device_mode);
     if id < cuda_device_uuids.len() {
         Some(cuda_device_uuids[id].clone())
     } else {
@@ -71,1403 +138,8216 @@ fn get_cuda_device_uuid_by_id(id: usize, custom_devices: &Option<Vec<String>>) -
     }
 }
 
-/// The main body of building system
-pub struct Parabuilder {
-    project_path: PathBuf,
-    workspaces_path: PathBuf,
-    template_file: PathBuf,
-    target_files: Vec<PathBuf>,
-    target_files_base: Vec<String>,
-    init_bash_script: String,
-    compile_bash_script: String,
-    run_bash_script: String,
-    build_workers: usize,
-    run_method: RunMethod,
-    temp_target_path_dir: PathBuf,
-    run_func_data: RunFunc,
-    data_queue_receiver: Option<Receiver<(usize, JsonValue)>>,
-    compilation_error_handling_method: CompliationErrorHandlingMethod,
-    auto_gather_array_data: bool,
-    in_place_template: bool,
-    disable_progress_bar: bool,
-    mpb: MultiProgress,
-    no_cache: bool,
-    without_rsync: bool,
-    enable_cppflags: bool,
-    autosave_interval: u64,
-    autosave_dir: PathBuf,
-    continue_from_start_time: Option<String>,
-    gpu_devices: Option<Vec<String>>,
+/// Extra information passed to the `RunFunc` besides the workspace, script and data
+#[derive(Clone, Default)]
+pub struct RunContext {
+    /// GPU devices set by `gpu_devices()`, `None` means auto-detect
+    pub gpu_devices: Option<Vec<String>>,
+    /// How the auto-detected devices are assigned when `gpu_devices` is `None`
+    pub cuda_device_mode: CudaDeviceMode,
+    /// Pin the run script of workspace `i` to the CPU `run_cpus[i % run_cpus.len()]`,
+    /// `None` means no pinning
+    pub run_cpus: Option<Vec<usize>>,
+    /// Compile time of the current data in milliseconds, `None` if it was not compiled
+    pub compile_ms: Option<u64>,
+    /// Id of the current data, its index in the datas, `None` for `run_one()`
+    pub data_id: Option<usize>,
+    /// Kill the run script when it takes longer than this
+    pub run_timeout: Option<Duration>,
+    /// Re-execute a failed run script up to this many times
+    pub run_retries: usize,
+    /// Wait this long before each retry
+    pub run_retry_backoff: Duration,
+    /// Shell used to run the run script
+    pub shell: ShellKind,
+    /// The commands set by `run_bash_scripts()`, run one by one until the first failing one,
+    /// empty means running the run script passed to the `RunFunc` as a whole
+    pub run_script_steps: Vec<String>,
+    /// Extra environment variables set by `envs()`
+    pub envs: HashMap<String, String>,
+    /// Expose each field of the data as an environment variable with this prefix
+    pub data_env_prefix: Option<String>,
+    /// Add `started_at` and `finished_at` to the result
+    pub record_timestamps: bool,
+    /// Add the `max_rss_kb` of the run script to the result
+    pub measure_memory: bool,
+    /// Run the run script in this directory relative to the workspace, `None` means the workspace root
+    pub run_cwd: Option<PathBuf>,
+    /// Run the run script this many times per data and report the `stats` of the samples,
+    /// `0` and `1` run it once
+    pub repeat_runs: usize,
+    /// Extract the sample of a run from its stdout, `None` samples `run_ms`
+    pub metric_extractor: Option<MetricExtractor>,
+    /// Written to the stdin of the run script, rendered from `Parabuilder::run_stdin_template` for each data
+    pub run_stdin: Option<String>,
+    /// The target and its arguments executed directly instead of the run script, without a shell,
+    /// rendered from `Parabuilder::run_args_template` for each data
+    pub run_command: Option<Vec<String>>,
+    /// The directory the target of `run_command` is found in relative to the workspace,
+    /// `Parabuilder::build_dir`, whatever `run_cwd` is
+    pub build_dir: Option<PathBuf>,
+    /// Check each result, the outcome is added as `verified`
+    pub verify_func: Option<VerifyFunc>,
+    /// JSON Pointer of the value lifted from the stdout parsed as json into `metric`
+    pub extract_json: Option<String>,
 }
 
-fn run_func_data_pre_(
-    workspace_path: &PathBuf,
-    run_script: &str,
-    data: &JsonValue,
-    _: &mut JsonValue,
-    gpu_devices: &Option<Vec<String>>,
-) -> Result<(bool, JsonValue), Box<dyn Error>> {
-    let workspace_id = workspace_path
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .split('_')
-        .last()
-        .unwrap();
-    let mut output = Command::new("bash");
-    output
-        .arg("-c")
-        .arg(run_script)
-        .env("PARABUILD_ID", workspace_id);
-    if let Some(mig_uuid) = get_cuda_device_uuid_by_id(workspace_id.parse().unwrap(), gpu_devices) {
-        output.env("CUDA_VISIBLE_DEVICES", mig_uuid);
-    }
-    output.current_dir(&workspace_path);
-    let output = output.output().unwrap();
-    let stdout = String::from_utf8(output.stdout).unwrap();
-    let stderr = String::from_utf8(output.stderr).unwrap();
-    let this_data = json! {
-        {
-            "status": match output.status.code() {
-                Some(code) => code,
-                None => -1
-            },
-            "stdout": stdout,
-            "stderr": stderr,
-            "data": data
-        }
-    };
-    Ok((output.status.success(), this_data))
-}
+/// Extracts the benchmark metric from the stdout of a run, see `Parabuilder::metric_extractor`
+pub type MetricExtractor = Arc<dyn Fn(&str) -> Option<f64> + Send + Sync>;
 
-fn run_func_data_post_(
-    this_data: JsonValue,
-    run_data: &mut JsonValue,
-) -> Result<JsonValue, Box<dyn Error>> {
-    if run_data.is_null() {
-        *run_data = JsonValue::Array(vec![this_data.clone()]);
-    } else {
-        run_data.as_array_mut().unwrap().push(this_data.clone());
+/// `(data, this_data) => passed` checking the result of a run, see `Parabuilder::verify_func`
+pub type VerifyFunc = Arc<dyn Fn(&JsonValue, &JsonValue) -> bool + Send + Sync>;
+
+/// `{"samples": [...], "min": ..., "max": ..., "mean": ..., "stddev": ...}` of the samples,
+/// the population standard deviation
+fn sample_stats(samples: &[f64]) -> JsonValue {
+    if samples.is_empty() {
+        return json!({"samples": []});
     }
-    Ok(this_data)
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n;
+    json!({
+        "samples": samples,
+        "min": samples.iter().copied().fold(f64::INFINITY, f64::min),
+        "max": samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        "mean": mean,
+        "stddev": variance.sqrt(),
+    })
 }
 
-fn run_func_data_panic_on_error(
-    workspace_path: &PathBuf,
-    run_script: &str,
-    data: &JsonValue,
-    run_data: &mut JsonValue,
-    stop_flag: &Arc<AtomicBool>,
-    gpu_devices: &Option<Vec<String>>,
-) -> Result<JsonValue, Box<dyn Error>> {
-    let (success, this_data) = run_func_data_pre_(workspace_path, run_script, data, run_data, gpu_devices)?;
-    if !success {
-        Err(format!("stderr: {}", this_data["stderr"]).as_str())?;
+type StreamOutputWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Messages sent through `Parabuilder::get_control_sender` while running
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlMsg {
+    /// Skip the data with this id when a build worker takes it, it is left unprocessed
+    Cancel(usize),
+}
+
+/// The ids cancelled by `ControlMsg::Cancel`, shared by the build workers
+#[derive(Clone, Default)]
+struct CancelledDatas {
+    receiver: Option<Receiver<ControlMsg>>,
+    ids: Arc<Mutex<HashSet<usize>>>,
+}
+
+impl CancelledDatas {
+    /// Take the pending control messages, then check whether `id` is cancelled
+    fn contains(&self, id: usize) -> bool {
+        let mut ids = self.ids.lock().unwrap();
+        if let Some(receiver) = &self.receiver {
+            for msg in receiver.try_iter() {
+                match msg {
+                    ControlMsg::Cancel(id) => ids.insert(id),
+                };
+            }
+        }
+        ids.contains(&id)
     }
-    if stop_flag.load(Ordering::Relaxed) {
-        Ok(JsonValue::Null)
-    } else {
-        run_func_data_post_(this_data, run_data)
+}
+
+/// Why a data is left unprocessed by `run()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnprocessedReason {
+    /// Skipped by `ControlMsg::Cancel`
+    Cancelled,
+    /// Not started by any worker before the run was stopped, e.g. by Ctrl-C
+    NotStarted,
+    /// Started, but dropped before it was run because the run was stopped, e.g. by the first Ctrl-C
+    Stopped,
+    /// Aborted while building or running, e.g. by the second Ctrl-C
+    Aborted,
+}
+
+/// A data left unprocessed by `run()`, see `Parabuilder::unprocessed_datas()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnprocessedData {
+    pub id: usize,
+    pub data: JsonValue,
+    pub reason: UnprocessedReason,
+}
+
+/// Which datas `Parabuilder::set_datas_sampled()` feeds into the data queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataSample {
+    /// Every `k`-th data, i.e. the ids `0, k, 2k, ...`
+    Stride(usize),
+    /// `count` datas picked at random, the same `seed` picks the same ids
+    Random { count: usize, seed: u64 },
+}
+
+impl DataSample {
+    /// The sorted ids sampled out of `len` datas
+    pub fn sample_ids(&self, len: usize) -> Result<Vec<usize>, Box<dyn Error>> {
+        match *self {
+            DataSample::Stride(0) => Err("The sample stride must be positive".into()),
+            DataSample::Stride(k) => Ok((0..len).step_by(k).collect()),
+            DataSample::Random { count, seed } => {
+                // partial Fisher-Yates shuffle driven by splitmix64
+                let mut state = seed;
+                let mut next = || {
+                    state = state.wrapping_add(0x9e3779b97f4a7c15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                    z ^ (z >> 31)
+                };
+                let mut ids: Vec<usize> = (0..len).collect();
+                let count = count.min(len);
+                for i in 0..count {
+                    let j = i + (next() % (len - i) as u64) as usize;
+                    ids.swap(i, j);
+                }
+                ids.truncate(count);
+                ids.sort_unstable();
+                Ok(ids)
+            }
+        }
     }
 }
 
-fn run_func_data_ignore_on_error(
-    workspace_path: &PathBuf,
-    run_script: &str,
-    data: &JsonValue,
-    run_data: &mut JsonValue,
-    stop_flag: &Arc<AtomicBool>,
-    gpu_devices: &Option<Vec<String>>,
-) -> Result<JsonValue, Box<dyn Error>> {
-    let (_, this_data) = run_func_data_pre_(workspace_path, run_script, data, run_data, gpu_devices)?;
-    if stop_flag.load(Ordering::Relaxed) {
-        Ok(JsonValue::Null)
-    } else {
-        run_func_data_post_(this_data, run_data)
+/// Datas taken from the data queue by the build workers, to find the unprocessed ones after `run()`
+#[derive(Clone, Default)]
+struct TakenDatas {
+    /// started datas, unprocessed if they are not in the processed ids at the end
+    started: Arc<Mutex<Vec<(usize, JsonValue)>>>,
+    /// datas skipped right when they were taken
+    skipped: Arc<Mutex<Vec<UnprocessedData>>>,
+}
+
+impl TakenDatas {
+    fn skip(&self, id: usize, data: JsonValue, reason: UnprocessedReason) {
+        self.skipped
+            .lock()
+            .unwrap()
+            .push(UnprocessedData { id, data, reason });
     }
 }
 
-type RunFunc = fn(
-    &PathBuf,
-    &str,
-    &JsonValue,
-    &mut JsonValue,
-    &Arc<AtomicBool>,
-    &Option<Vec<String>>,
-) -> Result<JsonValue, Box<dyn Error>>;
+/// Set by Ctrl-C, `stop` stops taking new datas, `abort` also aborts the in-flight ones
+#[derive(Clone, Default)]
+struct StopFlags {
+    stop: Arc<AtomicBool>,
+    abort: Arc<AtomicBool>,
+}
 
-/// Default run function that panics when there is an error
-pub const PANIC_ON_ERROR_DEFAULT_RUN_FUNC: RunFunc = run_func_data_panic_on_error;
+/// Set by SIGUSR1 and cleared by SIGUSR2 while `PauseSignalHandlers` are installed, cleared
+/// again when they are installed and restored
+static SIGNAL_PAUSED: AtomicBool = AtomicBool::new(false);
 
-/// Default run function that ignores when there is an error
-pub const IGNORE_ON_ERROR_DEFAULT_RUN_FUNC: RunFunc = run_func_data_ignore_on_error;
+#[cfg(unix)]
+extern "C" fn on_pause_signal(signal: libc::c_int) {
+    // only an atomic store is async-signal-safe here
+    SIGNAL_PAUSED.store(signal == libc::SIGUSR1, Ordering::Relaxed);
+}
 
-impl Parabuilder {
-    pub const TEMP_TARGET_PATH_DIR: &'static str = "targets";
+/// The number of live `PauseSignalHandlers` and the SIGUSR1/SIGUSR2 handlers they replaced
+#[cfg(unix)]
+static PAUSE_SIGNAL_HANDLERS: Mutex<Option<(usize, [libc::sigaction; 2])>> = Mutex::new(None);
 
-    pub fn new<P, Q, R, S>(
-        project_path: P,
-        workspaces_path: Q,
-        template_file: R,
-        target_files: &[S],
-    ) -> Self
-    where
-        P: AsRef<Path>,
-        Q: AsRef<Path>,
-        R: AsRef<Path>,
-        S: AsRef<Path>,
-    {
-        let project_path = project_path.as_ref().to_path_buf();
-        let workspaces_path = workspaces_path.as_ref().to_path_buf();
-        let template_file = template_file.as_ref().to_path_buf();
-        let target_files: Vec<PathBuf> = target_files
-            .into_iter()
-            .map(|target_file| target_file.as_ref().to_path_buf())
-            .collect();
-        let target_files_base = target_files
-            .iter()
-            .map(|target_file| {
-                target_file
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
-            })
-            .collect();
+/// Keeps `on_pause_signal` installed for SIGUSR1 and SIGUSR2 during `run()`, the previous
+/// handlers are restored when the last guard of the process is dropped
+#[cfg(unix)]
+struct PauseSignalHandlers;
 
-        let default_run_bash_script = if target_files.len() > 0 {
-            format!(
-                r#"
-                ./{}
-                "#,
-                target_files[0].to_string_lossy()
-            )
-        } else {
-            "".to_string()
-        };
+#[cfg(unix)]
+impl PauseSignalHandlers {
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGUSR1, libc::SIGUSR2];
 
-        let temp_target_path_dir = workspaces_path.join(Self::TEMP_TARGET_PATH_DIR);
-        let init_bash_script = r#"
-        cmake -B build -S . -DPARABUILD=ON
-        "#;
-        let compile_bash_script = r#"
-        cmake --build build --target all -- -B
-        "#;
-        let build_workers = 1;
-        Self {
-            project_path,
-            workspaces_path,
-            template_file,
-            target_files,
-            target_files_base,
-            init_bash_script: init_bash_script.to_string(),
-            compile_bash_script: compile_bash_script.to_string(),
-            run_bash_script: default_run_bash_script,
-            build_workers,
-            run_method: RunMethod::Exclusive(1),
-            temp_target_path_dir,
-            run_func_data: IGNORE_ON_ERROR_DEFAULT_RUN_FUNC,
-            data_queue_receiver: None,
-            compilation_error_handling_method: CompliationErrorHandlingMethod::Collect,
-            auto_gather_array_data: true,
-            in_place_template: false,
-            disable_progress_bar: false,
-            mpb: MultiProgress::new(),
-            no_cache: false,
-            without_rsync: false,
-            enable_cppflags: false,
-            autosave_interval: 0,
-            autosave_dir: PathBuf::from(".parabuild/autosave"),
-            continue_from_start_time: None,
-            gpu_devices: None,
+    fn install() -> Self {
+        let mut installed = PAUSE_SIGNAL_HANDLERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match installed.as_mut() {
+            Some((guards, _)) => *guards += 1,
+            None => {
+                // a pause by signal never carries over from an earlier run
+                SIGNAL_PAUSED.store(false, Ordering::Relaxed);
+                let previous = Self::SIGNALS.map(|signal| unsafe {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction =
+                        on_pause_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                    action.sa_flags = libc::SA_RESTART;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    let mut previous: libc::sigaction = std::mem::zeroed();
+                    libc::sigaction(signal, &action, &mut previous);
+                    previous
+                });
+                *installed = Some((1, previous));
+            }
         }
+        PauseSignalHandlers
     }
+}
 
-    pub fn init_bash_script(mut self, init_bash_script: &str) -> Self {
-        self.init_bash_script = init_bash_script.to_string();
-        self
+#[cfg(unix)]
+impl Drop for PauseSignalHandlers {
+    fn drop(&mut self) {
+        let mut installed = PAUSE_SIGNAL_HANDLERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some((guards, previous)) = installed.as_mut() {
+            *guards -= 1;
+            if *guards == 0 {
+                for (signal, previous) in Self::SIGNALS.iter().zip(previous.iter()) {
+                    unsafe {
+                        libc::sigaction(*signal, previous, std::ptr::null_mut());
+                    }
+                }
+                *installed = None;
+                SIGNAL_PAUSED.store(false, Ordering::Relaxed);
+            }
+        }
     }
+}
 
-    pub fn compile_bash_script(mut self, compile_bash_script: &str) -> Self {
-        self.compile_bash_script = compile_bash_script.to_string();
-        self
-    }
+/// Set by `Parabuilder::pause()`, the workers wait at the top of their loops while it
+/// (or `SIGNAL_PAUSED`) is set
+#[derive(Clone, Default)]
+struct PauseFlag(Arc<(Mutex<bool>, Condvar)>);
 
-    pub fn run_bash_script(mut self, run_bash_script: &str) -> Self {
-        self.run_bash_script = run_bash_script.to_string();
-        self
+impl PauseFlag {
+    fn set(&self, paused: bool) {
+        let (flag, resumed) = &*self.0;
+        *flag.lock().unwrap() = paused;
+        resumed.notify_all();
     }
 
-    pub fn build_workers(mut self, build_workers: usize) -> Self {
-        self.build_workers = build_workers;
-        self
+    fn is_set(&self) -> bool {
+        *self.0 .0.lock().unwrap() || SIGNAL_PAUSED.load(Ordering::Relaxed)
     }
 
-    pub fn run_workers(mut self, run_workers: isize) -> Self {
-        if run_workers > 0 {
-            self.run_method = RunMethod::OutOfPlace(run_workers as usize);
-        } else if run_workers == 0 {
-            self.run_method = RunMethod::No;
-        } else if run_workers < 0 {
-            self.run_method = RunMethod::Exclusive(-run_workers as usize);
+    /// Block while paused, returns early once `stop` is set so Ctrl-C still works
+    fn wait(&self, stop: &AtomicBool) {
+        let (flag, resumed) = &*self.0;
+        let mut paused = flag.lock().unwrap();
+        while (*paused || SIGNAL_PAUSED.load(Ordering::Relaxed)) && !stop.load(Ordering::Relaxed) {
+            // neither SIGUSR2 nor Ctrl-C notifies the condvar
+            paused = resumed
+                .wait_timeout(paused, Duration::from_millis(100))
+                .unwrap()
+                .0;
         }
-        self
     }
+}
 
-    pub fn run_workers_exclusive(mut self, run_workers: isize) -> Self {
-        self.run_method = RunMethod::Exclusive(run_workers as usize);
-        self
-    }
+/// Events passed to the callback set by `Parabuilder::progress_callback`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// Start to render and compile the data with `id`
+    BuildStarted { id: usize },
+    /// The compile script of the data with `id` finished
+    BuildFinished { id: usize, success: bool },
+    /// Start to run the data with `id`
+    RunStarted { id: usize },
+    /// The run of the data with `id` finished, `status` is `None` when the result has no status
+    RunFinished { id: usize, status: Option<i64> },
+}
 
-    pub fn run_method(mut self, run_method: RunMethod) -> Self {
-        self.run_method = run_method;
-        self
+type ProgressCallback = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// Called with the index of each workspace initialized by `Parabuilder::init_workspace()`
+type WorkspaceReadyCallback = Arc<dyn Fn(usize) + Send + Sync>;
+
+impl ProgressEvent {
+    /// e.g. `{"event": "build_finished", "id": 3, "success": true}`
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            ProgressEvent::BuildStarted { id } => json!({"event": "build_started", "id": id}),
+            ProgressEvent::BuildFinished { id, success } => {
+                json!({"event": "build_finished", "id": id, "success": success})
+            }
+            ProgressEvent::RunStarted { id } => json!({"event": "run_started", "id": id}),
+            ProgressEvent::RunFinished { id, status } => {
+                json!({"event": "run_finished", "id": id, "status": status})
+            }
+        }
     }
+}
 
-    pub fn run_func(mut self, run_func: RunFunc) -> Self {
-        self.run_func_data = run_func;
-        self
+/// The connection to the Unix socket set by `Parabuilder::event_socket`, held during `run()`
+#[derive(Clone, Default)]
+struct EventSocket {
+    #[cfg(unix)]
+    stream: Arc<Mutex<Option<std::os::unix::net::UnixStream>>>,
+}
+
+impl EventSocket {
+    #[cfg(unix)]
+    fn connect(&self, path: &Path) {
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(stream) => *self.stream.lock().unwrap() = Some(stream),
+            Err(e) => eprintln!(
+                "Warning: failed to connect to the event socket {:?}: {}",
+                path, e
+            ),
+        }
     }
 
-    pub fn compilation_error_handling_method(
-        mut self,
-        compilation_error_handling_method: CompliationErrorHandlingMethod,
-    ) -> Self {
-        self.compilation_error_handling_method = compilation_error_handling_method;
-        self
+    #[cfg(not(unix))]
+    fn connect(&self, path: &Path) {
+        eprintln!(
+            "Warning: the event socket {:?} is only supported on unix",
+            path
+        );
     }
 
-    pub fn auto_gather_array_data(mut self, auto_gather_array_data: bool) -> Self {
-        self.auto_gather_array_data = auto_gather_array_data;
-        self
+    /// Write `event` as a json line, the socket is dropped after the first failed write
+    #[cfg(unix)]
+    fn send(&self, event: &ProgressEvent) {
+        let mut stream = self.stream.lock().unwrap();
+        if let Some(writer) = stream.as_mut() {
+            if let Err(e) = writeln!(writer, "{}", event.to_json()) {
+                eprintln!("Warning: failed to write to the event socket: {}", e);
+                *stream = None;
+            }
+        }
     }
 
-    pub fn in_place_template(mut self, in_place_template: bool) -> Self {
-        self.in_place_template = in_place_template;
-        self
+    #[cfg(not(unix))]
+    fn send(&self, _: &ProgressEvent) {}
+
+    fn disconnect(&self) {
+        #[cfg(unix)]
+        self.stream.lock().unwrap().take();
     }
+}
 
-    pub fn disable_progress_bar(mut self, disable_progress_bar: bool) -> Self {
-        self.disable_progress_bar = disable_progress_bar;
-        self
+#[derive(Debug, Default)]
+struct WorkerCounters {
+    builds_in_flight: AtomicUsize,
+    builds_completed: AtomicUsize,
+    runs_in_flight: AtomicUsize,
+    runs_completed: AtomicUsize,
+}
+
+/// Live counters of the current `run()`, returned by `Parabuilder::worker_status()`,
+/// it can be polled from another thread while `run()` is executing
+///
+/// The counters are reset when `run()` starts, the cancelled datas are never counted
+#[derive(Debug, Clone, Default)]
+pub struct WorkerStatus(Arc<WorkerCounters>);
+
+impl WorkerStatus {
+    /// Number of datas that finished rendering and compiling, including the failed ones
+    pub fn builds_completed(&self) -> usize {
+        self.0.builds_completed.load(Ordering::Relaxed)
     }
 
-    pub fn no_cache(mut self, no_cache: bool) -> Self {
-        self.no_cache = no_cache;
-        self
+    /// Number of datas whose run finished, including the failed ones
+    pub fn runs_completed(&self) -> usize {
+        self.0.runs_completed.load(Ordering::Relaxed)
     }
 
-    pub fn without_rsync(mut self, without_rsync: bool) -> Self {
-        self.without_rsync = without_rsync;
-        self
+    /// Number of datas being rendered or compiled
+    pub fn builds_in_flight(&self) -> usize {
+        self.0.builds_in_flight.load(Ordering::Relaxed)
     }
 
-    pub fn enable_cppflags(mut self, enable_cppflags: bool) -> Self {
-        self.enable_cppflags = enable_cppflags;
-        self
+    /// Number of datas being run
+    pub fn runs_in_flight(&self) -> usize {
+        self.0.runs_in_flight.load(Ordering::Relaxed)
     }
 
-    pub fn autosave_interval(mut self, autosave_interval: u64) -> Self {
-        self.autosave_interval = autosave_interval;
-        self
+    /// Number of datas being built or run
+    pub fn in_flight(&self) -> usize {
+        self.builds_in_flight() + self.runs_in_flight()
     }
 
-    pub fn autosave_dir<S: AsRef<Path>>(mut self, autosave_dir: S) -> Self {
-        self.autosave_dir = autosave_dir.as_ref().to_path_buf();
-        self
+    fn record(&self, event: &ProgressEvent) {
+        let counters = &self.0;
+        match event {
+            ProgressEvent::BuildStarted { .. } => {
+                counters.builds_in_flight.fetch_add(1, Ordering::Relaxed);
+            }
+            ProgressEvent::BuildFinished { .. } => {
+                counters.builds_in_flight.fetch_sub(1, Ordering::Relaxed);
+                counters.builds_completed.fetch_add(1, Ordering::Relaxed);
+            }
+            ProgressEvent::RunStarted { .. } => {
+                counters.runs_in_flight.fetch_add(1, Ordering::Relaxed);
+            }
+            ProgressEvent::RunFinished { .. } => {
+                counters.runs_in_flight.fetch_sub(1, Ordering::Relaxed);
+                counters.runs_completed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
     }
 
-    /// Set GPU devices to use (can be UUIDs or indices)
-    ///
-    /// e.g. `vec!["0".to_string(), "1".to_string()]` or `vec!["GPU-xxx".to_string(), "GPU-yyy".to_string()]`
-    pub fn gpu_devices(mut self, gpu_devices: Vec<String>) -> Self {
-        self.gpu_devices = Some(gpu_devices);
-        self
+    fn reset(&self) {
+        let counters = &self.0;
+        counters.builds_in_flight.store(0, Ordering::Relaxed);
+        counters.builds_completed.store(0, Ordering::Relaxed);
+        counters.runs_in_flight.store(0, Ordering::Relaxed);
+        counters.runs_completed.store(0, Ordering::Relaxed);
     }
 
-    /// Set datas to be rendered into the template
-    pub fn set_datas(&mut self, datas: Vec<JsonValue>) -> Result<(), Box<dyn Error>> {
-        if self.data_queue_receiver.is_some() {
-            return Err("Data queue receiver is already initialized".into());
+    /// Nothing is in flight once `run()` returns, e.g. the aborted datas
+    fn settle(&self) {
+        self.0.builds_in_flight.store(0, Ordering::Relaxed);
+        self.0.runs_in_flight.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Saves the progress of a worker into `autosave_dir/start_time/worker_id`
+struct Autosaver {
+    autosave_dir: PathBuf,
+    start_time: String,
+    worker_id: Uuid,
+    datas_hash: Option<String>,
+    /// Write `.json.gz` files instead of `.json`
+    compress: bool,
+    /// Number of previous autosaves kept as `.json.{timestamp}`
+    generations: usize,
+}
+
+impl Autosaver {
+    /// Save autosave data
+    fn save(
+        &self,
+        run_datas: &JsonValue,
+        compile_error_datas: &Vec<JsonValue>,
+        processed_data_ids: &Vec<usize>,
+    ) {
+        // 包含当前时间的文件名
+        let autosave_dir = self.autosave_dir.join(&self.start_time);
+        let datas_hash_file = autosave_dir.join(Parabuilder::DATAS_HASH_FILE);
+        let autosave_dir = autosave_dir.join(self.worker_id.to_string());
+        if !autosave_dir.exists() {
+            std::fs::create_dir_all(&autosave_dir).expect("Failed to create autosave dir");
         }
-        let (data_queue_sender, data_queue_receiver) = unbounded();
-        self.data_queue_receiver = Some(data_queue_receiver);
-        for id_data in datas.into_iter().enumerate() {
-            data_queue_sender.send(id_data).unwrap();
+        if let Some(datas_hash) = &self.datas_hash {
+            // the other workers of the same run write the same file
+            let tmp_path = datas_hash_file.with_extension(format!("{}.tmp", self.worker_id));
+            let mut file = std::fs::File::create(&tmp_path).unwrap();
+            file.write_all(json!({"hash": datas_hash}).to_string().as_bytes())
+                .and_then(|()| file.sync_all())
+                .unwrap();
+            std::fs::rename(&tmp_path, &datas_hash_file).unwrap();
         }
-        Ok(())
+        self.write(&autosave_dir, "run_datas", |writer| {
+            serde_json::to_writer(writer, &run_datas)
+        });
+        self.write(&autosave_dir, "compile_error_datas", |writer| {
+            serde_json::to_writer(writer, &compile_error_datas)
+        });
+        self.write(&autosave_dir, "processed_data_ids", |writer| {
+            serde_json::to_writer(writer, &processed_data_ids)
+        });
     }
 
-    /// Set datas to be rendered into the template
-    pub fn set_datas_with_processed_data_ids_set(
-        &mut self,
-        datas: Vec<JsonValue>,
-        processed_data_ids_set: HashSet<usize>,
-    ) -> Result<(), Box<dyn Error>> {
-        if self.data_queue_receiver.is_some() {
-            return Err("Data queue receiver is already initialized".into());
-        }
-        let (data_queue_sender, data_queue_receiver) = unbounded();
-        self.data_queue_receiver = Some(data_queue_receiver);
-        for id_data in datas.into_iter().enumerate() {
-            if !processed_data_ids_set.contains(&id_data.0) {
-                data_queue_sender.send(id_data).unwrap();
+    /// Write `{name}.json` atomically, the previous one is kept as `{name}.json.{timestamp}`,
+    /// the UTC time it was replaced, and only the latest `generations` of them are kept
+    ///
+    /// The data is written to `{name}.json.tmp` and synced before it is renamed over
+    /// `{name}.json`, so a crash never leaves a truncated autosave behind
+    fn write<F>(&self, dir: &Path, name: &str, write_json: F)
+    where
+        F: FnOnce(&mut dyn Write) -> serde_json::Result<()>,
+    {
+        let extension = if self.compress { "json.gz" } else { "json" };
+        let path = dir.join(format!("{}.{}", name, extension));
+        let tmp_path = dir.join(format!("{}.{}.tmp", name, extension));
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp_path).unwrap());
+        let file = if self.compress {
+            let mut encoder = GzEncoder::new(file, Compression::fast());
+            write_json(&mut encoder).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            write_json(&mut file).unwrap();
+            file
+        };
+        file.into_inner().unwrap().sync_all().unwrap();
+        if path.exists() && self.generations > 0 {
+            let timestamp = chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S%.9f");
+            let generation_path = dir.join(format!("{}.{}.{}", name, extension, timestamp));
+            // keep `path` in place until the rename, so there is always a complete autosave
+            if std::fs::hard_link(&path, &generation_path).is_err() {
+                std::fs::copy(&path, &generation_path).unwrap();
             }
         }
-        Ok(())
+        std::fs::rename(&tmp_path, &path).unwrap();
+        // the timestamps sort chronologically, everything but the latest `generations` is pruned
+        let mut generation_paths = Self::generations(dir, name, extension);
+        let pruned = generation_paths.len().saturating_sub(self.generations);
+        for generation_path in generation_paths.drain(..pruned) {
+            let _ = std::fs::remove_file(generation_path);
+        }
     }
 
-    pub fn get_data_queue_sender(&mut self) -> Result<Sender<(usize, JsonValue)>, Box<dyn Error>> {
-        if self.data_queue_receiver.is_some() {
-            return Err("Data queue receiver is already initialized".into());
-        }
-        let (data_queue_sender, data_queue_receiver) = unbounded();
-        self.data_queue_receiver = Some(data_queue_receiver);
-        Ok(data_queue_sender)
+    /// The kept generations of `{name}.{extension}` in `dir`, the oldest first
+    fn generations(dir: &Path, name: &str, extension: &str) -> Vec<PathBuf> {
+        let prefix = format!("{}.{}.", name, extension);
+        let mut generation_paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                // not the `.tmp` file nor the `.json.gz` files of a plain `.json`
+                path.file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .and_then(|file_name| file_name.strip_prefix(&prefix))
+                    .is_some_and(|suffix| suffix.starts_with(|c: char| c.is_ascii_digit()))
+            })
+            .collect();
+        generation_paths.sort();
+        generation_paths
     }
 
-    /// Initialize workspaces
-    pub fn init_workspace(&self) -> Result<(), Box<dyn Error>> {
-        if !is_command_installed("rsync") {
-            if !self.without_rsync {
-                return Err("rsync is not installed, set `without_rsync` to true to ignore".into());
-            }
-        }
-        let out_of_place_run_workers = match self.run_method {
-            RunMethod::OutOfPlace(run_workers) => run_workers,
-            RunMethod::Exclusive(run_workers) => run_workers,
-            _ => 0,
-        };
-        let workspaces_path = if self.workspaces_path.is_absolute() {
-            self.workspaces_path.clone()
+    /// Open `{name}.json.gz` or `{name}.json` in `dir`, whichever exists
+    fn open(dir: &Path, name: &str) -> Box<dyn std::io::Read> {
+        let compressed = dir.join(format!("{}.json.gz", name));
+        if compressed.exists() {
+            let file = std::fs::File::open(compressed).unwrap();
+            Box::new(GzDecoder::new(std::io::BufReader::new(file)))
         } else {
-            env::current_dir().unwrap().join(&self.workspaces_path)
-        };
-        if self.no_cache {
-            if self.workspaces_path.exists() {
-                std::fs::remove_dir_all(&self.workspaces_path).unwrap();
-            }
-        }
-        std::fs::create_dir_all(&workspaces_path).unwrap();
-        let mut project_path = self.project_path.clone();
-        let move_to_temp_dir = workspaces_path
-            .starts_with(std::fs::canonicalize(&self.project_path).unwrap())
-            && self.without_rsync;
-        let mut build_handles = vec![];
-        if move_to_temp_dir {
-            self.add_spinner("copying to temp dir");
-            project_path = tempdir().unwrap().into_path();
-            copy_dir_with_ignore(&self.project_path, &project_path).unwrap();
+            let file = std::fs::File::open(dir.join(format!("{}.json", name))).unwrap();
+            Box::new(std::io::BufReader::new(file))
         }
-        for (i, destination) in (0..self.build_workers).map(|i| (i, format!("workspace_{}", i))) {
-            let source = project_path.clone();
-            let destination = self.workspaces_path.join(destination);
-            let init_bash_script = self.init_bash_script.clone();
-            let mpb = self.mpb.clone();
-            let disable_progress_bar = self.disable_progress_bar;
-            let without_rsync = self.without_rsync;
-            let handle = std::thread::spawn(move || {
-                let sp = Self::add_spinner2(
-                    disable_progress_bar,
-                    &mpb,
-                    format!("init workspace {}: copying", i),
-                );
-                if move_to_temp_dir {
-                    copy_dir(&source, &destination).unwrap();
-                } else {
-                    if without_rsync {
-                        copy_dir_with_ignore(&source, &destination).unwrap();
-                    } else {
-                        copy_dir_with_rsync(&source, &destination).unwrap();
-                    }
-                }
-                sp.set_message(format!("init workspace {}: init", i));
-                Command::new("bash")
-                    .arg("-c")
-                    .arg(&init_bash_script)
-                    .current_dir(&destination)
-                    .output()
-                    .unwrap();
-            });
-            build_handles.push(handle);
+    }
+}
+
+/// A workspace found by `Parabuilder::list_workspaces`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceInfo {
+    pub path: PathBuf,
+    /// Last modified time of the workspace directory
+    pub modified: SystemTime,
+    /// Whether it has a `build` directory, e.g. configured by the default cmake init script
+    pub has_build_dir: bool,
+}
+
+type ReduceFunc = Arc<dyn Fn(&[JsonValue]) -> JsonValue + Send + Sync>;
+
+/// Keeps the compilations and the runs of `RunMethod::Interleaved` apart, a waiting run blocks
+/// new compilations so the runs are not starved
+#[derive(Default)]
+struct ExclusiveGate {
+    state: Mutex<ExclusiveGateState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct ExclusiveGateState {
+    builds: usize,
+    runs: usize,
+    waiting_runs: usize,
+}
+
+impl ExclusiveGate {
+    /// Block until no run is active or waiting, the returned guard holds a compilation
+    fn build(&self) -> ExclusiveGuard<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.runs > 0 || state.waiting_runs > 0 {
+            state = self.changed.wait(state).unwrap();
         }
-        let mut run_handles = vec![];
-        if out_of_place_run_workers > 0 {
-            // only compile to executable when run_workers = 0
-            std::fs::create_dir_all(self.workspaces_path.join(Self::TEMP_TARGET_PATH_DIR)).unwrap();
-            for (i, destination) in
-                (0..out_of_place_run_workers).map(|i| (i, format!("workspace_exe_{}", i)))
-            {
-                let source = project_path.clone();
-                let destination = self.workspaces_path.join(destination);
-                let init_bash_script = self.init_bash_script.clone();
-                // let compile_bash_script = self.compile_bash_script.clone();
-                // let in_place_template = self.in_place_template;
-                let mpb = self.mpb.clone();
-                let disable_progress_bar = self.disable_progress_bar;
-                let without_rsync = self.without_rsync;
-                let handle = std::thread::spawn(move || {
-                    let sp = Self::add_spinner2(
-                        disable_progress_bar,
-                        &mpb,
-                        format!("init workspace_run {}: copying", i),
-                    );
-                    if move_to_temp_dir {
-                        copy_dir(&source, &destination).unwrap();
-                    } else {
-                        if without_rsync {
-                            copy_dir_with_ignore(&source, &destination).unwrap();
-                        } else {
-                            copy_dir_with_rsync(&source, &destination).unwrap();
-                        }
-                    }
-                    sp.set_message(format!("init workspace_run {}: init", i));
-                    match Command::new("bash")
-                        .arg("-c")
-                        .arg(&init_bash_script)
-                        .current_dir(&destination)
-                        .output()
-                    {
-                        Ok(output) => {
-                            if !output.status.success() {
-                                panic!(
-                                    "Init bash script failed in workspace_run_{}: {:?}",
-                                    i, output
-                                );
-                            }
-                        }
-                        Err(e) => {
-                            panic!("Init bash script failed in workspace_run_{}: {:?}", i, e);
-                        }
-                    }
-                    // assert!(output.status.success());
-                    // let output = Command::new("bash")
-                    //     .arg("-c")
-                    //     .arg(&compile_bash_script)
-                    //     .current_dir(&destination)
-                    //     .output()
-                    //     .unwrap();
-                    // assert!(output.status.success());
-                });
-                run_handles.push(handle);
-            }
+        state.builds += 1;
+        ExclusiveGuard {
+            gate: self,
+            is_run: false,
         }
+    }
 
-        for handle in build_handles {
-            handle.join().unwrap();
+    /// Block until no compilation is active, the returned guard holds a run
+    fn run(&self) -> ExclusiveGuard<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.waiting_runs += 1;
+        while state.builds > 0 {
+            state = self.changed.wait(state).unwrap();
         }
-        for handle in run_handles {
-            handle.join().unwrap();
+        state.waiting_runs -= 1;
+        state.runs += 1;
+        ExclusiveGuard {
+            gate: self,
+            is_run: true,
         }
+    }
+}
 
-        std::fs::create_dir_all(&self.temp_target_path_dir).unwrap();
+struct ExclusiveGuard<'a> {
+    gate: &'a ExclusiveGate,
+    is_run: bool,
+}
 
-        Ok(())
+impl Drop for ExclusiveGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.gate.state.lock().unwrap();
+        if self.is_run {
+            state.runs -= 1;
+        } else {
+            state.builds -= 1;
+        }
+        self.gate.changed.notify_all();
     }
+}
 
-    fn latest_folder<P: AsRef<Path>>(dir: P) -> Option<PathBuf> {
-        if !dir.as_ref().exists() {
-            return None;
+/// Build workspaces shared by all build workers, each data claims one of them while it is built
+#[derive(Clone)]
+struct WorkspacePool {
+    sender: Sender<(PathBuf, usize, Option<String>)>,
+    receiver: Receiver<(PathBuf, usize, Option<String>)>,
+}
+
+impl WorkspacePool {
+    fn new(workspace_paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let (sender, receiver) = unbounded();
+        for workspace_path in workspace_paths {
+            sender.send((workspace_path, 0, None)).unwrap();
         }
-        let mut latest = None;
-        let mut latest_time = None;
-        for entry in std::fs::read_dir(dir).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            let metadata = entry.metadata().unwrap();
-            if metadata.is_dir() {
-                let time = metadata.modified().unwrap();
-                if latest_time.is_none() || time > latest_time.unwrap() {
-                    latest = Some(path);
-                    latest_time = Some(time);
-                }
-            }
+        Self { sender, receiver }
+    }
+
+    /// Block until a workspace is free, it goes back to the pool when the returned guard is dropped
+    fn claim(&self) -> ClaimedWorkspace {
+        let (workspace_path, consecutive_failures, built_render_hash) =
+            self.receiver.recv().unwrap();
+        ClaimedWorkspace {
+            pool: self.clone(),
+            workspace_path: Some(workspace_path),
+            consecutive_failures,
+            built_render_hash,
         }
-        latest
     }
+}
 
-    /// Load autosave data (run_datas, compile_error_datas, processed_data_ids)
-    pub fn autosave_load(&mut self, start_time: String) -> (JsonValue, Vec<JsonValue>, Vec<usize>) {
-        let autosave_dir = if start_time.is_empty() {
-            let latest = Self::latest_folder(&self.autosave_dir);
-            if latest.is_none() {
-                panic!("No autosave data found, consider running without --continue");
-            }
-            latest.unwrap()
-        } else {
-            self.autosave_dir.join(start_time)
-        };
-        println!("Loading autosave data from {:?}", autosave_dir);
-        self.continue_from_start_time = Some(
-            autosave_dir
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string(),
-        );
-        let datas = std::fs::read_dir(&autosave_dir).unwrap().into_iter().fold(
-            (vec![], vec![], vec![]),
-            |(mut run_datas_array, mut compile_error_datas_array, mut processed_data_ids_array),
-             entry| {
-                let entry = entry.unwrap();
-                let path = entry.path();
-                let run_datas_file = path.join("run_datas.json");
-                let compile_error_datas_file = path.join("compile_error_datas.json");
-                let processed_data_ids_file = path.join("processed_data_ids.json");
-                let run_datas: JsonValue =
-                    serde_json::from_reader(std::fs::File::open(&run_datas_file).unwrap()).unwrap();
-                let compile_error_datas: Vec<JsonValue> = serde_json::from_reader(
-                    std::fs::File::open(&compile_error_datas_file).unwrap(),
-                )
-                .unwrap();
-                let processed_data_ids: Vec<usize> =
-                    serde_json::from_reader(std::fs::File::open(&processed_data_ids_file).unwrap())
-                        .unwrap();
-                run_datas_array.push(run_datas);
-                compile_error_datas_array.extend(compile_error_datas);
-                processed_data_ids_array.extend(processed_data_ids);
-                (
-                    run_datas_array,
-                    compile_error_datas_array,
-                    processed_data_ids_array,
-                )
-            },
-        );
-        self.gather_data(datas.0, datas.1, datas.2).unwrap()
+struct ClaimedWorkspace {
+    pool: WorkspacePool,
+    workspace_path: Option<PathBuf>,
+    /// Compile failures in a row in this workspace, kept across claims
+    consecutive_failures: usize,
+    /// Render hash of the targets currently built in this workspace, see `trust_incremental`
+    built_render_hash: Option<String>,
+}
+
+impl std::ops::Deref for ClaimedWorkspace {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        self.workspace_path.as_ref().unwrap()
     }
+}
 
-    /// Save autosave data
-    fn autosave_save<P: AsRef<Path>>(
-        autosave_dir: P,
-        start_time: &str,
-        run_datas: &JsonValue,
-        compile_error_datas: &Vec<JsonValue>,
-        processed_data_ids: &Vec<usize>,
-        workspace_id: Uuid,
-    ) {
-        // 包含当前时间的文件名
-        let autosave_dir = autosave_dir
-            .as_ref()
-            .to_path_buf()
-            .join(start_time)
-            .join(workspace_id.to_string());
-        if !autosave_dir.exists() {
-            std::fs::create_dir_all(&autosave_dir).expect("Failed to create autosave dir");
-        }
-        let run_datas_file = autosave_dir.join("run_datas.json");
-        let run_datas_file1 = autosave_dir.join("run_datas.json.1");
-        let compile_error_datas_file = autosave_dir.join("compile_error_datas.json");
-        let compile_error_datas_file1 = autosave_dir.join("compile_error_datas.json.1");
-        let processed_data_ids_file = autosave_dir.join("processed_data_ids.json");
-        let processed_data_ids_file1 = autosave_dir.join("processed_data_ids.json.1");
-        if run_datas_file.exists() {
-            std::fs::rename(&run_datas_file, &run_datas_file1).unwrap();
-        }
-        if compile_error_datas_file.exists() {
-            std::fs::rename(&compile_error_datas_file, &compile_error_datas_file1).unwrap();
+impl Drop for ClaimedWorkspace {
+    fn drop(&mut self) {
+        if let Some(workspace_path) = self.workspace_path.take() {
+            let _ = self.pool.sender.send((
+                workspace_path,
+                self.consecutive_failures,
+                self.built_render_hash.take(),
+            ));
         }
-        if processed_data_ids_file.exists() {
-            std::fs::rename(&processed_data_ids_file, &processed_data_ids_file1).unwrap();
+    }
+}
+
+fn emit_progress_event(
+    progress_callback: &Option<ProgressCallback>,
+    worker_status: &WorkerStatus,
+    event: ProgressEvent,
+) {
+    worker_status.record(&event);
+    if let Some(progress_callback) = progress_callback {
+        progress_callback(event);
+    }
+}
+
+/// Write `this_data` as a single json line
+fn write_stream_output(stream_output_writer: &Option<StreamOutputWriter>, this_data: &JsonValue) {
+    if let Some(writer) = stream_output_writer {
+        let mut writer = writer.lock().unwrap();
+        if let Err(e) = writeln!(writer, "{}", this_data).and_then(|_| writer.flush()) {
+            eprintln!("Failed to write stream output: {}", e);
         }
-        let run_datas_file = std::fs::File::create(&run_datas_file).unwrap();
-        let compile_error_datas_file = std::fs::File::create(&compile_error_datas_file).unwrap();
-        let processed_data_ids_file = std::fs::File::create(&processed_data_ids_file).unwrap();
-        serde_json::to_writer(run_datas_file, &run_datas).unwrap();
-        serde_json::to_writer(compile_error_datas_file, &compile_error_datas).unwrap();
-        serde_json::to_writer(processed_data_ids_file, &processed_data_ids).unwrap();
     }
+}
 
-    /// run the build system
-    pub fn run(&self) -> Result<(JsonValue, Vec<JsonValue>, Vec<usize>), Box<dyn Error>> {
-        let start_time = if let Some(start_time) = &self.continue_from_start_time {
-            start_time.clone()
+/// Copy the rendered template outputs of data `id` and its compile log into `dir`
+fn save_rendered(dir: &Path, id: usize, template_outputs: &[(String, PathBuf)], log: &str) {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        eprintln!("Failed to create {:?}: {}", dir, e);
+        return;
+    }
+    for (template_id, (_, template_output_path)) in template_outputs.iter().enumerate() {
+        let mut file_name = if template_outputs.len() == 1 {
+            format!("data_{}", id)
         } else {
-            Local::now().format("%Y-%m-%d_%H-%M-%S").to_string()
+            format!("data_{}_{}", id, template_id)
         };
-        println!("Start from: {}", start_time);
-        if !self.data_queue_receiver.is_some() {
-            return Err("Data queue receiver is not initialized".into());
+        if let Some(ext) = template_output_path.extension() {
+            file_name.push('.');
+            file_name.push_str(&ext.to_string_lossy());
         }
-        if !is_command_installed("bash") {
-            return Err("bash is not installed".into());
+        if let Err(e) = std::fs::copy(template_output_path, dir.join(&file_name)) {
+            eprintln!("Failed to save {:?}: {}", template_output_path, e);
         }
-        if !is_command_installed("lsof") {
-            return Err("lsof is not installed, which may lead to strange problems that are difficult to reproduce".into());
-        }
-        let mut build_handles = vec![];
-        let mut run_handles = Vec::new();
-        let (executable_queue_sender, executable_queue_receiver) = unbounded();
-        let data_size = self.data_queue_receiver.as_ref().unwrap().len() as u64;
-        let build_pb = self.add_progress_bar("Building", data_size, "All builds done");
-        let run_pb = if !matches!(self.run_method, RunMethod::No) {
-            if matches!(self.run_method, RunMethod::Exclusive(_)) {
-                self.add_progress_bar("Waiting to run (exclusive)", data_size, "All runs done")
-            } else {
-                self.add_progress_bar("Running", data_size, "All runs done")
+    }
+    if let Err(e) = std::fs::write(dir.join(format!("data_{}.log", id)), log) {
+        eprintln!("Failed to save compile log of data {}: {}", id, e);
+    }
+}
+
+const BUILD_LOG_FILE: &str = "parabuild_compile.log";
+
+/// Append the compile output of data `id` to the `BUILD_LOG_FILE` in `workspace_path`
+fn append_build_log(workspace_path: &Path, id: usize, data: &JsonValue, compile_data: &JsonValue) {
+    let log_path = workspace_path.join(BUILD_LOG_FILE);
+    let mut log = match std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+    {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("Failed to open {:?}: {}", log_path, e);
+            return;
+        }
+    };
+    let mut entry = format!(
+        "==== data {}: {} (status: {}) ====\n",
+        id, data, compile_data["status"]
+    );
+    for stream in ["stdout", "stderr"] {
+        if let JsonValue::String(output) = &compile_data[stream] {
+            entry.push_str(output);
+            if !output.is_empty() && !output.ends_with('\n') {
+                entry.push('\n');
             }
+        }
+    }
+    if let Err(e) = log.write_all(entry.as_bytes()) {
+        eprintln!("Failed to write {:?}: {}", log_path, e);
+    }
+}
+
+/// The commands of a script, `script_steps` or the whole `script` when it is empty
+fn script_steps<'a>(script: &'a str, script_steps: &'a [String]) -> Vec<&'a str> {
+    if script_steps.is_empty() {
+        vec![script]
+    } else {
+        script_steps.iter().map(String::as_str).collect()
+    }
+}
+
+/// Run the init script in `workspace_path` step by step, stop at the first failing one,
+/// returns the output and the index of the failing step
+fn init_script_output(
+    shell: ShellKind,
+    script: &str,
+    steps: &[String],
+    envs: &HashMap<String, String>,
+    workspace_path: &Path,
+) -> Result<(std::process::Output, Option<usize>), std::io::Error> {
+    let commands = script_steps(script, steps).into_iter().map(|step| {
+        let mut command = shell.command(step);
+        command.envs(envs).current_dir(workspace_path);
+        command
+    });
+    output_of_steps_with_timeout(commands, None, None, None)
+        .map(|(output, _, failed_step)| (output, failed_step))
+}
+
+/// ` at step {i}: {step}` when there are several steps, empty otherwise
+fn failed_step_message(steps: &[String], failed_step: Option<usize>) -> String {
+    match failed_step {
+        Some(i) if steps.len() > 1 => format!(" at step {}: {}", i, steps[i]),
+        _ => String::new(),
+    }
+}
+
+/// How `init_workspace()` copies the project into a workspace and initializes it
+#[derive(Clone)]
+struct WorkspaceInit {
+    source: PathBuf,
+    /// `source` is a temporary copy of the project, copied as a whole
+    plain_copy: bool,
+    without_rsync: bool,
+    rsync_args: Vec<String>,
+    copy_excludes: Vec<String>,
+    copy_strategy: CopyStrategy,
+    independent_paths: Vec<PathBuf>,
+    shell: ShellKind,
+    init_bash_script: String,
+    init_bash_script_steps: Vec<String>,
+    envs: HashMap<String, String>,
+}
+
+impl WorkspaceInit {
+    /// Copy the project into `destination` reporting to `pb`, then run the init script there
+    fn init(
+        &self,
+        workspace: &str,
+        destination: &Path,
+        pb: &ProgressBar,
+        label: &str,
+    ) -> Result<(), String> {
+        let copied = if self.copy_strategy != CopyStrategy::Copy {
+            copy_dir_with_strategy(
+                &self.source,
+                destination,
+                self.copy_strategy,
+                &self.independent_paths,
+                &self.copy_excludes,
+                pb,
+            )
+            .map_err(|e| e.to_string())
+        } else if self.plain_copy {
+            copy_dir(&self.source, destination, pb).map_err(|e| e.to_string())
+        } else if self.without_rsync {
+            copy_dir_with_ignore(&self.source, destination, &self.copy_excludes, pb)
+                .map_err(|e| e.to_string())
         } else {
-            ProgressBar::hidden()
+            copy_dir_with_rsync(
+                &self.source,
+                destination,
+                &self.rsync_args,
+                &self.copy_excludes,
+                pb,
+            )
+            .map_err(|e| e.to_string())
         };
-        let stop_flag = Arc::new(AtomicBool::new(false));
-        if !cfg!(test) {
-            ctrlc::set_handler({
-                let stop_flag = Arc::clone(&stop_flag);
-                move || {
-                    println!("Ctrl-C received, stopping...");
-                    stop_flag.store(true, Ordering::Relaxed);
+        copied.map_err(|e| format!("Failed to copy the project into {}: {}", workspace, e))?;
+        pb.set_message(format!("{}: init", label));
+        init_workspace_script(
+            workspace,
+            self.shell,
+            &self.init_bash_script,
+            &self.init_bash_script_steps,
+            &self.envs,
+            destination,
+        )
+    }
+}
+
+/// Run the init script in a workspace of `init_workspace()`, the error names the workspace
+/// and carries the captured stderr
+fn init_workspace_script(
+    workspace: &str,
+    shell: ShellKind,
+    init_bash_script: &str,
+    init_bash_script_steps: &[String],
+    envs: &HashMap<String, String>,
+    destination: &Path,
+) -> Result<(), String> {
+    match init_script_output(
+        shell,
+        init_bash_script,
+        init_bash_script_steps,
+        envs,
+        destination,
+    ) {
+        Ok((output, failed_step)) if !output.status.success() => Err(format!(
+            "Init bash script failed in {}{}: {}\n{}",
+            workspace,
+            failed_step_message(init_bash_script_steps, failed_step),
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Init bash script failed in {}: {}", workspace, e)),
+    }
+}
+
+/// The keys that tell parabuild how to handle a data, they are left out of `CPPFLAGS`
+/// and the environment variables of the data
+fn is_reserved_key(key: &str) -> bool {
+    key == Parabuilder::CUDA_DEVICE_KEY || key == Parabuilder::LABEL_KEY
+}
+
+/// `{"N": 10}` => `-DPARABUILD=ON -DN=10 `, or `-DN=10 ` without `parabuild_define`
+fn data_to_cppflags(data: &JsonValue, parabuild_define: bool) -> String {
+    let mut cppflags = if parabuild_define {
+        "-DPARABUILD=ON ".to_string()
+    } else {
+        String::new()
+    };
+    /* {"key":value} => -Dkey=value*/
+    for (key, value) in data.as_object().unwrap().iter() {
+        if is_reserved_key(key) {
+            continue;
+        }
+        cppflags.push_str(&format!("-D{}={} ", key, value));
+    }
+    cppflags
+}
+
+/// Render `data` into every `(template name, template output path)`, stop at the first error
+fn render_templates(
+    handlebars: &Handlebars,
+    template_outputs: &[(String, PathBuf)],
+    data: &JsonValue,
+) -> Result<(), (PathBuf, RenderError)> {
+    for (template_name, template_output_path) in template_outputs.iter() {
+        if let Some(parent) = template_output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .unwrap_or_else(|_| panic!("Failed to create {:?}", parent));
+        }
+        let mut template_output = std::fs::File::create(template_output_path)
+            .unwrap_or_else(|_| panic!("Failed to create {:?}", template_output_path));
+        handlebars
+            .render_to_write(template_name, data, &template_output)
+            .map_err(|e| (template_output_path.clone(), e))?;
+        template_output.flush().unwrap();
+    }
+    Ok(())
+}
+
+/// Name of the template registered by `Parabuilder::template_output_path_registry()`
+const TEMPLATE_OUTPUT_PATH: &str = "template_output_path";
+
+/// `template_outputs` (relative to a workspace) with the first one moved to the path rendered
+/// from `data` by `output_path`, see `template_output_path_template()`
+fn place_template_outputs(
+    template_outputs: &[(String, PathBuf)],
+    output_path: Option<&Handlebars>,
+    data: &JsonValue,
+) -> Result<Vec<(String, PathBuf)>, (PathBuf, RenderError)> {
+    let mut template_outputs = template_outputs.to_vec();
+    if let (Some(output_path), Some((_, template_output_file))) =
+        (output_path, template_outputs.first_mut())
+    {
+        let rendered = output_path
+            .render(TEMPLATE_OUTPUT_PATH, data)
+            .map(PathBuf::from)
+            .and_then(|rendered| {
+                // must stay in the workspace
+                let escapes = rendered.as_os_str().is_empty()
+                    || rendered.is_absolute()
+                    || rendered
+                        .components()
+                        .any(|component| component == std::path::Component::ParentDir);
+                if escapes {
+                    Err(RenderErrorReason::Other(format!(
+                        "{:?} is not a path inside the workspace",
+                        rendered
+                    ))
+                    .into())
+                } else {
+                    Ok(rendered)
                 }
             })
-            .expect("Error setting Ctrl-C handler");
+            .map_err(|e| (PathBuf::from("<template output path>"), e))?;
+        *template_output_file = rendered;
+    }
+    Ok(template_outputs)
+}
+
+/// The compile and run scripts of a data
+#[derive(Clone)]
+struct Scripts {
+    compile: String,
+    compile_steps: Vec<String>,
+    run: String,
+    run_steps: Vec<String>,
+    /// Written to the stdin of the run script, always a template
+    run_stdin: Option<String>,
+    /// The target and its arguments, always templates
+    run_command: Option<Vec<String>>,
+}
+
+/// The scripts and, when `templated_scripts`, `run_stdin_template` or `run_args_template` is set,
+/// the registry to render them against each data
+struct ScriptTemplates {
+    scripts: Scripts,
+    /// Set by `compile_script_in_project()` and `run_script_in_project()`, read from the
+    /// workspace by `for_data_in()` in place of `scripts.compile` and `scripts.run`
+    compile_in_project: Option<PathBuf>,
+    run_in_project: Option<PathBuf>,
+    handlebars: Option<Handlebars<'static>>,
+    templated_scripts: bool,
+}
+
+impl ScriptTemplates {
+    /// The scripts of `data`, borrowed as is when they are not templated
+    fn for_data(&self, data: &JsonValue) -> Result<Cow<'_, Scripts>, RenderError> {
+        self.render(Cow::Borrowed(&self.scripts), data)
+    }
+
+    /// Like `for_data()`, with the scripts in project read from `workspace_path`,
+    /// a script missing from the workspace fails like a render error
+    fn for_data_in(
+        &self,
+        workspace_path: &Path,
+        data: &JsonValue,
+    ) -> Result<Cow<'_, Scripts>, RenderError> {
+        self.read_in(workspace_path, true, data)
+    }
+
+    /// Like `for_data_in()` for a run workspace, where only the run script is read
+    fn for_run_in(
+        &self,
+        workspace_path: &Path,
+        data: &JsonValue,
+    ) -> Result<Cow<'_, Scripts>, RenderError> {
+        self.read_in(workspace_path, false, data)
+    }
+
+    fn read_in(
+        &self,
+        workspace_path: &Path,
+        read_compile: bool,
+        data: &JsonValue,
+    ) -> Result<Cow<'_, Scripts>, RenderError> {
+        let read = |script_path: &PathBuf| {
+            let script_path = workspace_path.join(script_path);
+            std::fs::read_to_string(&script_path).map_err(|e| {
+                RenderErrorReason::Other(format!(
+                    "Failed to read the script {:?}: {}",
+                    script_path, e
+                ))
+            })
+        };
+        let mut scripts = Cow::Borrowed(&self.scripts);
+        if let Some(compile_in_project) = self.compile_in_project.as_ref().filter(|_| read_compile)
+        {
+            scripts.to_mut().compile = read(compile_in_project)?;
         }
-        build_pb.tick();
-        run_pb.tick();
-        let spawn_build_workers = || {
-            for i in 0..self.build_workers {
-                let workspace_path = self.workspaces_path.join(format!("workspace_{}", i));
-                let build_handle = self.build_worker(
-                    workspace_path,
-                    executable_queue_sender.clone(),
-                    build_pb.clone(),
-                    run_pb.clone(),
-                    Arc::clone(&stop_flag),
-                    start_time.clone(),
-                );
-                build_handles.push(build_handle);
-            }
-            drop(executable_queue_sender);
+        if let Some(run_in_project) = &self.run_in_project {
+            scripts.to_mut().run = read(run_in_project)?;
+        }
+        self.render(scripts, data)
+    }
+
+    fn render<'a>(
+        &self,
+        scripts: Cow<'a, Scripts>,
+        data: &JsonValue,
+    ) -> Result<Cow<'a, Scripts>, RenderError> {
+        let handlebars = match &self.handlebars {
+            Some(handlebars) => handlebars,
+            None => return Ok(scripts),
         };
-        let spawn_run_workers = || {
-            let run_workers = match self.run_method {
-                RunMethod::OutOfPlace(run_workers) => run_workers,
-                RunMethod::Exclusive(run_workers) => run_workers,
-                _ => 0,
-            };
-            for i in 0..run_workers {
-                let workspace_path = self.workspaces_path.join(format!("workspace_exe_{}", i));
-                let run_handle = self.run_worker(
-                    workspace_path,
-                    executable_queue_receiver.clone(),
-                    run_pb.clone(),
-                    Arc::clone(&stop_flag),
-                    start_time.clone(),
-                );
-                run_handles.push(run_handle);
+        let render = |script: &String| handlebars.render_template(script, data);
+        let mut rendered = if self.templated_scripts {
+            Scripts {
+                compile: render(&scripts.compile)?,
+                compile_steps: scripts
+                    .compile_steps
+                    .iter()
+                    .map(render)
+                    .collect::<Result<_, _>>()?,
+                run: render(&scripts.run)?,
+                run_steps: scripts
+                    .run_steps
+                    .iter()
+                    .map(render)
+                    .collect::<Result<_, _>>()?,
+                run_stdin: None,
+                run_command: None,
             }
-            drop(executable_queue_receiver);
+        } else {
+            scripts.as_ref().clone()
         };
-        let gather_build_handlers =
-            |build_handles: Vec<JoinHandle<(JsonValue, Vec<JsonValue>, Vec<usize>)>>| {
-                build_handles.into_iter().fold(
-                    (vec![], vec![], vec![]),
-                    |(
-                        mut run_datas_array,
-                        mut compile_error_datas_array,
-                        mut processed_data_ids_array,
-                    ),
-                     handle| {
-                        let (run_datas, compile_error_datas, processed_data_ids) =
-                            handle.join().unwrap();
-                        run_datas_array.push(run_datas);
-                        compile_error_datas_array.extend(compile_error_datas);
-                        processed_data_ids_array.extend(processed_data_ids);
-                        (
-                            run_datas_array,
-                            compile_error_datas_array,
-                            processed_data_ids_array,
-                        )
-                    },
-                )
-            };
-        let gather_run_handlers = |run_handles: Vec<JoinHandle<(JsonValue, Vec<usize>)>>| {
-            run_handles.into_iter().fold(
-                (vec![], vec![]),
-                |(mut run_datas_array, mut processed_data_ids_array), handle| {
-                    let (run_datas, processed_data_ids) = handle.join().unwrap();
-                    run_datas_array.push(run_datas);
-                    processed_data_ids_array.extend(processed_data_ids);
-                    (run_datas_array, processed_data_ids_array)
-                },
-            )
+        rendered.run_stdin = scripts.run_stdin.as_ref().map(render).transpose()?;
+        rendered.run_command = scripts
+            .run_command
+            .as_ref()
+            .map(|run_command| run_command.iter().map(render).collect())
+            .transpose()?;
+        Ok(Cow::Owned(rendered))
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// Feed `bytes` into the FNV-1a `hash`
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Stable FNV-1a hash of `datas`, used to check whether the datas changed since the autosave
+fn datas_hash(datas: &[JsonValue]) -> String {
+    let hash = fnv1a(
+        FNV_OFFSET_BASIS,
+        serde_json::to_string(datas).unwrap().as_bytes(),
+    );
+    format!("{:016x}", hash)
+}
+
+/// Hash of everything the compile result of `data` depends on, the rendered template outputs,
+/// the compile script, and the `CPPFLAGS` and data envs passed to it
+fn render_hash(
+    template_outputs: &[(String, PathBuf)],
+    compile_script: &str,
+    context: &CompileContext,
+    data: &JsonValue,
+) -> Result<String, std::io::Error> {
+    let mut hash = FNV_OFFSET_BASIS;
+    for (_, template_output_path) in template_outputs.iter() {
+        let content = std::fs::read(template_output_path)?;
+        hash = fnv1a(hash, &(content.len() as u64).to_le_bytes());
+        hash = fnv1a(hash, &content);
+    }
+    // the template output moves per data with `template_output_path_template`
+    if let Some(template_output) = &context.template_output {
+        hash = fnv1a(hash, template_output.to_string_lossy().as_bytes());
+    }
+    // the compile script differs per data with `templated_scripts`
+    for script in std::iter::once(compile_script)
+        .chain(context.compile_script_steps.iter().map(String::as_str))
+    {
+        hash = fnv1a(hash, &(script.len() as u64).to_le_bytes());
+        hash = fnv1a(hash, script.as_bytes());
+    }
+    if let Some(cppflags) = &context.cppflags {
+        hash = fnv1a(hash, cppflags.as_bytes());
+    }
+    if let Some(prefix) = &context.data_env_prefix {
+        hash = fnv1a(
+            hash,
+            serde_json::to_string(&data_to_envs(prefix, data))
+                .unwrap()
+                .as_bytes(),
+        );
+    }
+    Ok(format!("{:016x}", hash))
+}
+
+/// Hash of everything the run result of `data` depends on, its `render_hash`, the data itself,
+/// and the run script with its stdin or arguments
+fn result_hash(render_hash: &str, data: &JsonValue, scripts: &Scripts) -> String {
+    let data = serde_json::to_string(data).unwrap();
+    let mut hash = FNV_OFFSET_BASIS;
+    for part in [render_hash, &data, &scripts.run]
+        .into_iter()
+        .chain(scripts.run_steps.iter().map(String::as_str))
+        .chain(scripts.run_stdin.as_deref())
+        .chain(scripts.run_command.iter().flatten().map(String::as_str))
+    {
+        hash = fnv1a(hash, &(part.len() as u64).to_le_bytes());
+        hash = fnv1a(hash, part.as_bytes());
+    }
+    format!("{:016x}", hash)
+}
+
+/// The results of the successful runs kept under `Parabuilder::RESULT_CACHE_DIR` across invocations,
+/// one `<result hash>.json` per result
+#[derive(Clone)]
+struct ResultCache {
+    dir: PathBuf,
+    /// data id => result hash of the datas compiled but not run yet
+    pending: Arc<Mutex<HashMap<usize, String>>>,
+}
+
+impl ResultCache {
+    fn result_path(&self, result_hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", result_hash))
+    }
+
+    fn get(&self, result_hash: &str) -> Option<JsonValue> {
+        let content = std::fs::read_to_string(self.result_path(result_hash)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Remember the result hash of the data `id`, whose result is then recorded by `store()`
+    fn expect(&self, id: usize, result_hash: String) {
+        self.pending.lock().unwrap().insert(id, result_hash);
+    }
+
+    /// Record the result of the data `id` when it exits with status 0
+    fn store(&self, id: usize, result: &JsonValue) {
+        let result_hash = match self.pending.lock().unwrap().remove(&id) {
+            Some(result_hash) => result_hash,
+            None => return,
         };
-        spawn_build_workers();
-        drop(build_pb);
-        match self.run_method {
-            RunMethod::No | RunMethod::InPlace => {
-                let (run_datas, compile_error_datas, processed_data_ids) =
-                    gather_build_handlers(build_handles);
-                self.gather_data(run_datas, compile_error_datas, processed_data_ids)
-            }
-            RunMethod::Exclusive(_) => {
-                let (_, compile_error_datas, mut processed_data_ids) =
-                    gather_build_handlers(build_handles);
-                run_pb.set_message("Running");
-                spawn_run_workers();
-                let (run_datas, run_processed_data_ids) = gather_run_handlers(run_handles);
-                processed_data_ids.extend(run_processed_data_ids);
-                self.gather_data(run_datas, compile_error_datas, processed_data_ids)
+        if result["status"] != 0 {
+            return;
+        }
+        // renamed into place, another invocation never reads a partial result
+        let temp_path = self
+            .dir
+            .join(format!("{}.{}_{}.tmp", result_hash, std::process::id(), id));
+        let stored = std::fs::create_dir_all(&self.dir)
+            .and_then(|()| std::fs::write(&temp_path, result.to_string()))
+            .and_then(|()| std::fs::rename(&temp_path, self.result_path(&result_hash)));
+        if let Err(e) = stored {
+            eprintln!("Failed to cache the result of data {}: {}", id, e);
+        }
+    }
+}
+
+/// `{"N": 10, "MODE": "fast"}` => `[("{prefix}N", "10"), ("{prefix}MODE", "fast")]`,
+/// nested values are serialized as json strings
+fn data_to_envs(prefix: &str, data: &JsonValue) -> Vec<(String, String)> {
+    match data.as_object() {
+        Some(map) => map
+            .iter()
+            .filter(|(key, _)| !is_reserved_key(key))
+            .map(|(key, value)| {
+                let value = match value {
+                    JsonValue::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                (format!("{}{}", prefix, key), value)
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// The main body of building system
+pub struct Parabuilder {
+    project_path: PathBuf,
+    workspaces_path: PathBuf,
+    template_files: Vec<PathBuf>,
+    target_files: Vec<PathBuf>,
+    build_dir: Option<PathBuf>,
+    compile_cwd: Option<PathBuf>,
+    init_bash_script: String,
+    compile_bash_script: String,
+    run_bash_script: String,
+    init_bash_script_steps: Vec<String>,
+    compile_bash_script_steps: Vec<String>,
+    run_bash_script_steps: Vec<String>,
+    run_stdin_template: Option<String>,
+    run_args_template: Option<Vec<String>>,
+    /// Whether `run_bash_script()` or `run_bash_scripts()` replaced the default run script
+    custom_run_bash_script: bool,
+    compile_script_in_project: Option<PathBuf>,
+    run_script_in_project: Option<PathBuf>,
+    build_workers: usize,
+    workspace_pool_size: Option<usize>,
+    workspace_prefix: String,
+    reset_after_failures: usize,
+    max_compile_errors: usize,
+    /// Failed compiles so far across all build workers, reset by every `run()`
+    compile_error_count: Arc<AtomicUsize>,
+    run_method: RunMethod,
+    temp_target_path_dir: PathBuf,
+    run_func_data: RunFunc,
+    compile_func: CompileFunc,
+    compile_jobs: usize,
+    compiler_cache_dir: Option<PathBuf>,
+    data_queue_receiver: Option<Receiver<(usize, JsonValue)>>,
+    data_queue_capacity: Option<usize>,
+    compile_error_categories: Vec<(String, String)>,
+    collect_warnings: bool,
+    warning_regex: String,
+    /// Collected by the build workers with `collect_warnings`, cleared by every `run()`
+    warning_datas: Arc<Mutex<Vec<JsonValue>>>,
+    compilation_error_handling_method: CompliationErrorHandlingMethod,
+    auto_gather_array_data: bool,
+    sort_output_by_id: bool,
+    in_place_template: bool,
+    template_delimiters: Option<(String, String)>,
+    template_output_path_template: Option<String>,
+    strict_templates: bool,
+    disable_progress_bar: bool,
+    progress_template: Option<String>,
+    progress_ascii: bool,
+    mpb: MultiProgress,
+    no_cache: bool,
+    without_rsync: bool,
+    rsync_args: Vec<String>,
+    copy_excludes: Vec<String>,
+    copy_strategy: CopyStrategy,
+    file_ready_wait: FileReadyWait,
+    enable_cppflags: bool,
+    parabuild_define: bool,
+    autosave_interval: u64,
+    autosave_dir: PathBuf,
+    autosave_compress: bool,
+    autosave_generations: usize,
+    continue_from_start_time: Option<String>,
+    datas_hash: Option<String>,
+    continue_datas_hash: Option<String>,
+    continue_processed_data_ids: Option<HashSet<usize>>,
+    /// datas skipped by `set_datas()` because an autosave already processed them
+    resumed_data_count: u64,
+    gpu_devices: Option<Vec<String>>,
+    cuda_device_mode: CudaDeviceMode,
+    pin_run_workers: bool,
+    run_cpus: Option<Vec<usize>>,
+    run_workers_auto_cuda: bool,
+    data_schema: Option<JsonValue>,
+    run_only_labels: Option<Vec<String>>,
+    save_rendered_on_error: Option<PathBuf>,
+    tee_build_logs: bool,
+    run_timeout: Option<Duration>,
+    run_retries: usize,
+    run_retry_backoff: Duration,
+    compile_timeout: Option<Duration>,
+    stream_output_writer: Option<StreamOutputWriter>,
+    event_socket_path: Option<PathBuf>,
+    event_socket: EventSocket,
+    progress_callback: Option<ProgressCallback>,
+    on_workspace_ready: Option<WorkspaceReadyCallback>,
+    worker_status: WorkerStatus,
+    pause_flag: PauseFlag,
+    reduce_func: Option<ReduceFunc>,
+    reduced_data: Mutex<Option<JsonValue>>,
+    dedupe_identical_renders: bool,
+    trust_incremental: bool,
+    /// render hash => the dir holding the targets built from it and the resolved target files,
+    /// cleared by every `run()`
+    dedupe_cache: Arc<Mutex<HashMap<String, DedupeEntry>>>,
+    result_cache: bool,
+    /// See `ResultCache::pending`, cleared by every `run()`
+    pending_results: Arc<Mutex<HashMap<usize, String>>>,
+    /// Set by Ctrl-C or by dropping the future of `run_async()`, reset by every `run()`
+    stop_flags: StopFlags,
+    cancelled_datas: CancelledDatas,
+    lazy_run_workspace_init: bool,
+    /// Run workspaces left to be initialized by their run worker, see `lazy_run_workspace_init`
+    pending_run_workspaces: Arc<Mutex<HashMap<PathBuf, WorkspaceInit>>>,
+    /// Manifest entries of the targets staged by `RunMethod::No`, cleared by every `run()`
+    staged_targets: Arc<Mutex<Vec<JsonValue>>>,
+    taken_datas: TakenDatas,
+    unprocessed_datas: Mutex<Vec<UnprocessedData>>,
+    /// Shared by the build and run workers of `RunMethod::Interleaved`
+    exclusive_gate: Arc<ExclusiveGate>,
+    dry_run: bool,
+    shell: ShellKind,
+    envs: HashMap<String, String>,
+    data_env_prefix: Option<String>,
+    record_timestamps: bool,
+    measure_memory: bool,
+    run_cwd: Option<PathBuf>,
+    warmup_runs: usize,
+    repeat_runs: usize,
+    metric_extractor: Option<MetricExtractor>,
+    verify_func: Option<VerifyFunc>,
+    extract_json: Option<String>,
+    templated_scripts: bool,
+    split_run_results: bool,
+}
+
+fn run_func_data_pre_(
+    workspace_path: &PathBuf,
+    run_script: &str,
+    data: &JsonValue,
+    _: &mut JsonValue,
+    stop_flag: &Arc<AtomicBool>,
+    context: &RunContext,
+) -> Result<(bool, JsonValue), Box<dyn Error>> {
+    let workspace_id = workspace_path
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .split('_')
+        .next_back()
+        .unwrap();
+    let workspace_index: usize = workspace_id.parse().unwrap();
+    let cuda_device = match &data[Parabuilder::CUDA_DEVICE_KEY] {
+        JsonValue::String(cuda_device) => Some(cuda_device.clone()),
+        JsonValue::Number(cuda_device) => Some(cuda_device.to_string()),
+        _ => get_cuda_device_uuid_by_id(
+            workspace_index,
+            &context.gpu_devices,
+            context.cuda_device_mode,
+        ),
+    };
+    let run_dir = match &context.run_cwd {
+        Some(run_cwd) => workspace_path.join(run_cwd),
+        None => PathBuf::from(workspace_path),
+    };
+    let target_dir = match &context.build_dir {
+        Some(build_dir) => workspace_path.join(build_dir),
+        None => PathBuf::from(workspace_path),
+    };
+    let command = |step: &str| {
+        let mut command = match &context.run_command {
+            Some(run_command) => {
+                let mut command = Command::new(target_dir.join(&run_command[0]));
+                command.args(&run_command[1..]);
+                command
             }
-            RunMethod::OutOfPlace(_) => {
-                spawn_run_workers();
-                let (_, compile_error_datas, mut processed_data_ids) =
-                    gather_build_handlers(build_handles);
-                let (run_datas, run_processed_data_ids) = gather_run_handlers(run_handles);
-                processed_data_ids.extend(run_processed_data_ids);
-                self.gather_data(run_datas, compile_error_datas, processed_data_ids)
+            None => context.shell.command(step),
+        };
+        command.envs(&context.envs);
+        if let Some(prefix) = &context.data_env_prefix {
+            command.envs(data_to_envs(prefix, data));
+        }
+        command.env("PARABUILD_ID", workspace_id);
+        if let Some(mig_uuid) = &cuda_device {
+            command.env("CUDA_VISIBLE_DEVICES", mig_uuid);
+        }
+        if let Some(cpus) = context.run_cpus.as_ref().filter(|cpus| !cpus.is_empty()) {
+            pin_command_to_cpu(&mut command, cpus[workspace_index % cpus.len()]);
+        }
+        command.current_dir(&run_dir);
+        command
+    };
+    let steps = match &context.run_command {
+        Some(_) => vec![run_script],
+        None => script_steps(run_script, &context.run_script_steps),
+    };
+    let run_with_retries = || {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let run_start = Instant::now();
+            let (output, timed_out, failed_step, max_rss_kb) = output_of_steps_with_max_rss(
+                steps.iter().map(|step| command(step)),
+                context.run_timeout,
+                Some(stop_flag),
+                context.run_stdin.as_deref().map(str::as_bytes),
+            )
+            .unwrap();
+            let run_ms = run_start.elapsed().as_millis() as u64;
+            if output.status.success()
+                || attempts > context.run_retries
+                || stop_flag.load(Ordering::Relaxed)
+            {
+                break (output, timed_out, failed_step, run_ms, attempts, max_rss_kb);
             }
+            std::thread::sleep(context.run_retry_backoff);
+        }
+    };
+    let started_at = Local::now();
+    let mut samples = Vec::new();
+    let mut repeats = 0;
+    let (output, timed_out, failed_step, run_ms, attempts, max_rss_kb) = loop {
+        repeats += 1;
+        let result = run_with_retries();
+        let (output, _, _, run_ms, _, _) = &result;
+        let sample = match &context.metric_extractor {
+            Some(metric_extractor) => metric_extractor(&String::from_utf8_lossy(&output.stdout)),
+            None => Some(*run_ms as f64),
+        };
+        samples.extend(sample);
+        // the last run or the first failed one is reported
+        if repeats >= context.repeat_runs
+            || !output.status.success()
+            || stop_flag.load(Ordering::Relaxed)
+        {
+            break result;
+        }
+    };
+    let finished_at = Local::now();
+    // invalid UTF-8 bytes are replaced with U+FFFD
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let mut this_data = json! {
+        {
+            "id": context.data_id,
+            "status": output.status.code().unwrap_or(-1),
+            "stdout": stdout,
+            "stderr": stderr,
+            "compile_ms": context.compile_ms,
+            "run_ms": run_ms,
+            "attempts": attempts,
+            "data": data
+        }
+    };
+    if timed_out {
+        this_data["timed_out"] = JsonValue::Bool(true);
+    }
+    if let (Some(failed_step), true) = (failed_step, steps.len() > 1) {
+        this_data["failed_step"] = json!(failed_step);
+    }
+    if let Some(label) = data.get(Parabuilder::LABEL_KEY) {
+        this_data["label"] = label.clone();
+    }
+    if let (true, Some(max_rss_kb)) = (context.measure_memory, max_rss_kb) {
+        this_data["max_rss_kb"] = json!(max_rss_kb);
+    }
+    if context.record_timestamps {
+        this_data["started_at"] = JsonValue::String(started_at.to_rfc3339());
+        this_data["finished_at"] = JsonValue::String(finished_at.to_rfc3339());
+    }
+    if context.repeat_runs > 1 || context.metric_extractor.is_some() {
+        this_data["repeats"] = json!(repeats);
+        this_data["stats"] = sample_stats(&samples);
+    }
+    if let Some(pointer) = &context.extract_json {
+        // the raw stdout is kept either way, `metric` is left out when it is not json
+        let metric = serde_json::from_str::<JsonValue>(this_data["stdout"].as_str().unwrap())
+            .ok()
+            .and_then(|stdout| stdout.pointer(pointer).cloned());
+        if let Some(metric) = metric {
+            this_data["metric"] = metric;
+        }
+    }
+    if let Some(verify_func) = &context.verify_func {
+        let verified = verify_func(data, &this_data);
+        this_data["verified"] = JsonValue::Bool(verified);
+    }
+    Ok((output.status.success(), this_data))
+}
+
+/// Execute `run_func` `warmup_runs` times on a scratch `run_data`, the outputs are dropped
+///
+/// A failed warmup run is not fatal, it ends the warmup and the recorded run reports the error
+fn warmup(
+    run_func: RunFunc,
+    warmup_runs: usize,
+    workspace_path: &PathBuf,
+    run_script: &str,
+    data: &JsonValue,
+    abort_flag: &Arc<AtomicBool>,
+    context: &RunContext,
+) {
+    for _ in 0..warmup_runs {
+        if abort_flag.load(Ordering::Relaxed) {
+            break;
+        }
+        if run_func(
+            workspace_path,
+            run_script,
+            data,
+            &mut JsonValue::Null,
+            abort_flag,
+            context,
+        )
+        .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Count a failed compile, and stop taking new datas once `max_compile_errors` is reached
+fn count_compile_error(
+    compile_error_count: &AtomicUsize,
+    max_compile_errors: usize,
+    stop_flag: &AtomicBool,
+    mpb: &MultiProgress,
+) {
+    let count = compile_error_count.fetch_add(1, Ordering::Relaxed) + 1;
+    if max_compile_errors > 0
+        && count >= max_compile_errors
+        && !stop_flag.swap(true, Ordering::Relaxed)
+    {
+        mpb.suspend(|| {
+            eprintln!(
+                "{} compile errors reached max_compile_errors, stopping",
+                count
+            )
+        });
+    }
+}
+
+/// Stop the other workers from taking and running datas, then panic,
+/// e.g. when `PANIC_ON_ERROR_DEFAULT_RUN_FUNC` meets a failed run
+fn abort_on_run_error(
+    stop_flag: &AtomicBool,
+    abort_flag: &AtomicBool,
+    data: &JsonValue,
+    e: Box<dyn Error>,
+) -> ! {
+    stop_flag.store(true, Ordering::Relaxed);
+    abort_flag.store(true, Ordering::Relaxed);
+    panic!("Run script failed in data: {:?}, {}", data, e);
+}
+
+fn run_func_data_post_(
+    this_data: JsonValue,
+    run_data: &mut JsonValue,
+) -> Result<JsonValue, Box<dyn Error>> {
+    if run_data.is_null() {
+        *run_data = JsonValue::Array(vec![this_data.clone()]);
+    } else {
+        run_data.as_array_mut().unwrap().push(this_data.clone());
+    }
+    Ok(this_data)
+}
+
+fn run_func_data_panic_on_error(
+    workspace_path: &PathBuf,
+    run_script: &str,
+    data: &JsonValue,
+    run_data: &mut JsonValue,
+    stop_flag: &Arc<AtomicBool>,
+    context: &RunContext,
+) -> Result<JsonValue, Box<dyn Error>> {
+    let (success, this_data) = run_func_data_pre_(
+        workspace_path,
+        run_script,
+        data,
+        run_data,
+        stop_flag,
+        context,
+    )?;
+    if stop_flag.load(Ordering::Relaxed) {
+        return Ok(JsonValue::Null);
+    }
+    if !success {
+        Err(format!("stderr: {}", this_data["stderr"]).as_str())?;
+    }
+    run_func_data_post_(this_data, run_data)
+}
+
+fn run_func_data_ignore_on_error(
+    workspace_path: &PathBuf,
+    run_script: &str,
+    data: &JsonValue,
+    run_data: &mut JsonValue,
+    stop_flag: &Arc<AtomicBool>,
+    context: &RunContext,
+) -> Result<JsonValue, Box<dyn Error>> {
+    let (_, this_data) = run_func_data_pre_(
+        workspace_path,
+        run_script,
+        data,
+        run_data,
+        stop_flag,
+        context,
+    )?;
+    if stop_flag.load(Ordering::Relaxed) {
+        Ok(JsonValue::Null)
+    } else {
+        run_func_data_post_(this_data, run_data)
+    }
+}
+
+type RunFunc = fn(
+    &PathBuf,
+    &str,
+    &JsonValue,
+    &mut JsonValue,
+    &Arc<AtomicBool>,
+    &RunContext,
+) -> Result<JsonValue, Box<dyn Error>>;
+
+/// (id, data, compile_ms, target files with the glob patterns resolved) of a built data
+type Executable = (usize, JsonValue, u64, Vec<PathBuf>);
+
+/// (dir holding the deduped targets, resolved target files)
+type DedupeEntry = (PathBuf, Vec<PathBuf>);
+
+/// (run_datas, compile_error_datas, processed_data_ids)
+type RunOutput = (JsonValue, Vec<JsonValue>, Vec<usize>);
+
+/// The typed result of `Parabuilder::run_report()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    /// Gathered outputs of the run function, `Null` when nothing is run
+    pub run_data: JsonValue,
+    /// Failed runs moved out of `run_data` by `split_run_errors()`
+    pub run_errors: Vec<JsonValue>,
+    /// Datas that failed to render or compile
+    pub compile_errors: Vec<JsonValue>,
+    /// Datas whose successful compile emitted warnings, see `Parabuilder::collect_warnings()`
+    pub warning_datas: Vec<JsonValue>,
+    /// Ids of all the processed datas, including the ones that failed to compile
+    pub processed_ids: Vec<usize>,
+    /// Datas left unprocessed with the reasons, empty when converted from a `RunOutput`
+    pub unprocessed: Vec<UnprocessedData>,
+}
+
+impl RunReport {
+    /// The run outputs that have a `status`, `None` when the run data has no status
+    fn statuses(&self) -> Option<Vec<Option<i64>>> {
+        let run_data: Vec<&JsonValue> = self
+            .run_data
+            .as_array()
+            .into_iter()
+            .flatten()
+            .chain(self.run_errors.iter())
+            .collect();
+        if run_data.is_empty() || run_data[0]["status"].is_null() {
+            return None;
+        }
+        Some(
+            run_data
+                .iter()
+                .map(|data| data["status"].as_i64())
+                .collect(),
+        )
+    }
+
+    /// Move the runs that exit with a non-zero status from `run_data` to `run_errors`,
+    /// like the compile errors are kept apart in `compile_errors`
+    pub fn split_run_errors(&mut self) {
+        if let JsonValue::Array(run_data) = &mut self.run_data {
+            let (run_errors, successes): (Vec<JsonValue>, Vec<JsonValue>) =
+                std::mem::take(run_data)
+                    .into_iter()
+                    .partition(|data| data["status"].as_i64().is_some_and(|status| status != 0));
+            *run_data = successes;
+            self.run_errors.extend(run_errors);
+        }
+    }
+
+    /// Number of runs that exit with status 0, `None` when the run data has no status
+    pub fn run_success(&self) -> Option<usize> {
+        self.statuses()
+            .map(|statuses| statuses.iter().filter(|status| **status == Some(0)).count())
+    }
+
+    /// Number of runs that exit with a non-zero status, `None` when the run data has no status
+    pub fn run_failed(&self) -> Option<usize> {
+        self.statuses()
+            .map(|statuses| statuses.iter().filter(|status| **status != Some(0)).count())
+    }
+
+    /// The run results that failed `Parabuilder::verify_func()`
+    pub fn verification_failures(&self) -> Vec<JsonValue> {
+        self.run_data
+            .as_array()
+            .map(|run_data| {
+                run_data
+                    .iter()
+                    .filter(|data| data["verified"] == JsonValue::Bool(false))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Number of datas that compiled
+    pub fn compile_success(&self) -> usize {
+        self.processed_ids.len() - self.compile_errors.len()
+    }
+
+    /// Number of compile errors per category of `Parabuilder::compile_error_categories()`,
+    /// `Parabuilder::TIMEOUT_CATEGORY` or `Parabuilder::RENDER_ERROR_CATEGORY`, the most frequent
+    /// first, the errors matching no category count as `"uncategorized"`
+    pub fn compile_error_categories(&self) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for compile_error in self.compile_errors.iter() {
+            let category = compile_error["category"]
+                .as_str()
+                .unwrap_or("uncategorized");
+            match counts.iter_mut().find(|(name, _)| name == category) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((category.to_string(), 1)),
+            }
+        }
+        // stable, ties keep the order of first appearance
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts
+    }
+}
+
+impl From<RunOutput> for RunReport {
+    fn from((run_data, compile_errors, processed_ids): RunOutput) -> Self {
+        Self {
+            run_data,
+            run_errors: vec![],
+            compile_errors,
+            warning_datas: vec![],
+            processed_ids,
+            unprocessed: vec![],
+        }
+    }
+}
+
+/// Default run function that panics when there is an error
+pub const PANIC_ON_ERROR_DEFAULT_RUN_FUNC: RunFunc = run_func_data_panic_on_error;
+
+/// Default run function that ignores when there is an error
+pub const IGNORE_ON_ERROR_DEFAULT_RUN_FUNC: RunFunc = run_func_data_ignore_on_error;
+
+/// Extra information passed to the `CompileFunc` besides the workspace, script and data
+#[derive(Clone, Default)]
+pub struct CompileContext {
+    /// Kill the compile script when it takes longer than this
+    pub compile_timeout: Option<Duration>,
+    /// Shell used to run the compile script
+    pub shell: ShellKind,
+    /// The commands set by `compile_bash_scripts()`, run one by one until the first failing one,
+    /// empty means running the compile script passed to the `CompileFunc` as a whole
+    pub compile_script_steps: Vec<String>,
+    /// Extra environment variables set by `envs()`
+    pub envs: HashMap<String, String>,
+    /// Expose each field of the data as an environment variable with this prefix
+    pub data_env_prefix: Option<String>,
+    /// `CPPFLAGS` of the current data, `None` if `enable_cppflags` is not set
+    pub cppflags: Option<String>,
+    /// Set as `PARABUILD_COMPILE_JOBS`
+    pub compile_jobs: usize,
+    /// Where the first template file of the current data is rendered, relative to the workspace,
+    /// set as `PARABUILD_TEMPLATE_OUTPUT`, see `template_output_path_template()`
+    pub template_output: Option<PathBuf>,
+    /// Run the compile script in this directory relative to the workspace, `None` means the workspace root
+    pub compile_cwd: Option<PathBuf>,
+}
+
+fn compile_func_default(
+    workspace_path: &PathBuf,
+    compile_script: &str,
+    data: &JsonValue,
+    stop_flag: &Arc<AtomicBool>,
+    context: &CompileContext,
+) -> Result<(bool, JsonValue), Box<dyn Error>> {
+    let steps = script_steps(compile_script, &context.compile_script_steps);
+    let commands = steps.iter().map(|step| {
+        let mut command = context.shell.command(step);
+        command
+            .envs(&context.envs)
+            .current_dir(match &context.compile_cwd {
+                Some(compile_cwd) => workspace_path.join(compile_cwd),
+                None => PathBuf::from(workspace_path),
+            });
+        if let Some(prefix) = &context.data_env_prefix {
+            command.envs(data_to_envs(prefix, data));
+        }
+        if let Some(cppflags) = &context.cppflags {
+            command.env("CPPFLAGS", cppflags);
+        }
+        command.env("PARABUILD_COMPILE_JOBS", context.compile_jobs.to_string());
+        if let Some(template_output) = &context.template_output {
+            command.env("PARABUILD_TEMPLATE_OUTPUT", template_output);
+        }
+        command
+    });
+    let (output, timed_out, failed_step) =
+        output_of_steps_with_timeout(commands, context.compile_timeout, Some(stop_flag), None)?;
+    let mut compile_data = json!({
+        "status": output.status.code().unwrap_or(-1),
+        "stdout": String::from_utf8_lossy(&output.stdout),
+        "stderr": String::from_utf8_lossy(&output.stderr),
+    });
+    if timed_out {
+        compile_data["timed_out"] = JsonValue::Bool(true);
+    }
+    if let (Some(failed_step), true) = (failed_step, steps.len() > 1) {
+        compile_data["failed_step"] = json!(failed_step);
+    }
+    Ok((output.status.success(), compile_data))
+}
+
+/// CPUs available to the process, 1 if unknown
+fn available_cpus() -> usize {
+    std::thread::available_parallelism().map_or(1, |cpus| cpus.get())
+}
+
+/// The warning when the jobs running at the same time are more than twice the `cpus`,
+/// the run workers of `RunMethod::Exclusive` only start after the builds
+fn oversubscription_warning(
+    build_workers: usize,
+    compile_jobs: usize,
+    run_method: RunMethod,
+    run_workers: usize,
+    cpus: usize,
+) -> Option<String> {
+    let build_jobs = build_workers * compile_jobs.max(1);
+    let (jobs, detail) = match run_method {
+        RunMethod::OutOfPlace(_) | RunMethod::Interleaved(_) => (
+            build_jobs + run_workers,
+            format!(
+                "{} build workers x {} compile jobs + {} run workers",
+                build_workers, compile_jobs, run_workers
+            ),
+        ),
+        _ => (
+            build_jobs.max(run_workers),
+            format!(
+                "{} build workers x {} compile jobs",
+                build_workers, compile_jobs
+            ),
+        ),
+    };
+    (jobs > 2 * cpus).then(|| {
+        format!(
+            "{} jobs ({}) may oversubscribe the {} available CPUs, see `build_workers_auto()`",
+            jobs, detail, cpus
+        )
+    })
+}
+
+/// The label of the first regex matching the `stderr` or `stdout` of `compile_data`
+fn classify_compile_error<'a>(
+    classifier: &'a [(Regex, String)],
+    compile_data: &JsonValue,
+) -> Option<&'a str> {
+    let outputs = ["stderr", "stdout"].map(|stream| compile_data[stream].as_str().unwrap_or(""));
+    classifier
+        .iter()
+        .find(|(regex, _)| outputs.iter().any(|output| regex.is_match(output)))
+        .map(|(_, label)| label.as_str())
+}
+
+/// A collected compile error, `category` is `null` when no `compile_error_categories()` matches
+fn compile_error_record(data: &JsonValue, category: Option<&str>) -> JsonValue {
+    json!({"data": data, "category": category})
+}
+
+/// Convert a compile error of an autosave written before every record had a `category`,
+/// a bare data, `{"data": ..., "timeout": true}` or `{"data": ..., "render_error": ...}`
+fn upgrade_compile_error_record(record: JsonValue) -> JsonValue {
+    if record.get("category").is_some() && record.get("data").is_some() {
+        return record;
+    }
+    match (record.get("data"), record.get("render_error")) {
+        (Some(data), _) if record["timeout"] == true => {
+            compile_error_record(data, Some(Parabuilder::TIMEOUT_CATEGORY))
+        }
+        (Some(data), Some(render_error)) => {
+            let mut upgraded = compile_error_record(data, Some(Parabuilder::RENDER_ERROR_CATEGORY));
+            upgraded["render_error"] = render_error.clone();
+            upgraded
+        }
+        _ => compile_error_record(&record, None),
+    }
+}
+
+/// (workspace_path, compile_script, data, stop_flag, context) => (success, compile_data)
+///
+/// `"timed_out": true` in `compile_data` marks a timeout, `"stderr"` is saved by
+/// `save_rendered_on_error`
+type CompileFunc = fn(
+    &PathBuf,
+    &str,
+    &JsonValue,
+    &Arc<AtomicBool>,
+    &CompileContext,
+) -> Result<(bool, JsonValue), Box<dyn Error>>;
+
+/// Default compile function that runs `compile_bash_script` with the shell, envs, `CPPFLAGS`
+/// and timeout of the `Parabuilder`
+pub const DEFAULT_COMPILE_FUNC: CompileFunc = compile_func_default;
+
+impl Parabuilder {
+    pub const TEMP_TARGET_PATH_DIR: &'static str = "targets";
+    /// Written to `TEMP_TARGET_PATH_DIR` by `RunMethod::No`, see `run_targets()`
+    pub const MANIFEST_FILE: &'static str = "manifest.json";
+    /// Reserved data key, a data with `"__cuda_device": "GPU-xxx"` (or an index like `1`) runs with
+    /// this `CUDA_VISIBLE_DEVICES` instead of the device of its workspace, see `gpu_devices()`
+    pub const CUDA_DEVICE_KEY: &'static str = "__cuda_device";
+    /// Reserved data key, a label like `{"N": 10, "__label": "baseline"}` (or a list of labels)
+    /// to pick the datas with `run_only_labels()`, copied to the run datas as `label` and left
+    /// out of `CPPFLAGS` and `data_env_prefix()`
+    pub const LABEL_KEY: &'static str = "__label";
+    /// Matches the warning lines of gcc, clang, nvcc and ptxas, see `warning_regex()`
+    pub const DEFAULT_WARNING_REGEX: &'static str = r"\bwarning\b";
+    /// Where `result_cache` keeps the results under `workspaces_path`
+    pub const RESULT_CACHE_DIR: &'static str = ".cache";
+    /// `category` of the collected compile errors that timed out, see `compile_timeout()`
+    pub const TIMEOUT_CATEGORY: &'static str = "timeout";
+    /// `category` of the collected datas that failed to render, next to their `render_error`
+    pub const RENDER_ERROR_CATEGORY: &'static str = "render_error";
+    /// Where the hash of the datas is saved under the autosave dir of a run
+    const DATAS_HASH_FILE: &'static str = "datas_hash.json";
+
+    /// `target_files` are files or directories relative to the workspace (or to `build_dir()`),
+    /// or glob patterns like `build/*.ptx` resolved in the build workspace after each compilation, the resolved files
+    /// must have distinct file names
+    ///
+    /// An empty `template_file` is the same as `no_template()`
+    pub fn new<P, Q, R, S>(
+        project_path: P,
+        workspaces_path: Q,
+        template_file: R,
+        target_files: &[S],
+    ) -> Self
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        R: AsRef<Path>,
+        S: AsRef<Path>,
+    {
+        let project_path = project_path.as_ref().to_path_buf();
+        let workspaces_path = workspaces_path.as_ref().to_path_buf();
+        let template_files: Vec<PathBuf> = Some(template_file.as_ref())
+            .filter(|template_file| !template_file.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .into_iter()
+            .collect();
+        let target_files: Vec<PathBuf> = target_files
+            .iter()
+            .map(|target_file| target_file.as_ref().to_path_buf())
+            .collect();
+
+        let default_run_bash_script = if !target_files.is_empty() {
+            format!(
+                r#"
+                ./{}
+                "#,
+                target_files[0].to_string_lossy()
+            )
+        } else {
+            "".to_string()
+        };
+
+        let temp_target_path_dir = workspaces_path.join(Self::TEMP_TARGET_PATH_DIR);
+        let init_bash_script = r#"
+        cmake -B build -S . -DPARABUILD=ON
+        "#;
+        let compile_bash_script = r#"
+        cmake --build build --target all -- -B
+        "#;
+        let build_workers = 1;
+        Self {
+            project_path,
+            workspaces_path,
+            template_files,
+            target_files,
+            build_dir: None,
+            compile_cwd: None,
+            init_bash_script: init_bash_script.to_string(),
+            compile_bash_script: compile_bash_script.to_string(),
+            run_bash_script: default_run_bash_script,
+            init_bash_script_steps: vec![],
+            compile_bash_script_steps: vec![],
+            run_bash_script_steps: vec![],
+            run_stdin_template: None,
+            run_args_template: None,
+            custom_run_bash_script: false,
+            compile_script_in_project: None,
+            run_script_in_project: None,
+            build_workers,
+            workspace_pool_size: None,
+            workspace_prefix: String::new(),
+            reset_after_failures: 0,
+            max_compile_errors: 0,
+            compile_error_count: Arc::new(AtomicUsize::new(0)),
+            run_method: RunMethod::Exclusive(1),
+            temp_target_path_dir,
+            run_func_data: IGNORE_ON_ERROR_DEFAULT_RUN_FUNC,
+            compile_func: DEFAULT_COMPILE_FUNC,
+            compile_jobs: 1,
+            compiler_cache_dir: None,
+            data_queue_receiver: None,
+            data_queue_capacity: None,
+            compile_error_categories: vec![],
+            collect_warnings: false,
+            warning_regex: Self::DEFAULT_WARNING_REGEX.to_string(),
+            warning_datas: Arc::new(Mutex::new(Vec::new())),
+            compilation_error_handling_method: CompliationErrorHandlingMethod::Collect,
+            auto_gather_array_data: true,
+            sort_output_by_id: false,
+            in_place_template: false,
+            template_delimiters: None,
+            template_output_path_template: None,
+            strict_templates: false,
+            disable_progress_bar: false,
+            progress_template: None,
+            progress_ascii: false,
+            mpb: MultiProgress::new(),
+            no_cache: false,
+            without_rsync: false,
+            rsync_args: vec![],
+            copy_excludes: vec![],
+            copy_strategy: CopyStrategy::Copy,
+            file_ready_wait: FileReadyWait::default(),
+            enable_cppflags: false,
+            parabuild_define: true,
+            autosave_interval: 0,
+            autosave_dir: PathBuf::from(".parabuild/autosave"),
+            autosave_compress: false,
+            autosave_generations: 1,
+            continue_from_start_time: None,
+            datas_hash: None,
+            continue_datas_hash: None,
+            continue_processed_data_ids: None,
+            resumed_data_count: 0,
+            gpu_devices: None,
+            pin_run_workers: false,
+            run_cpus: None,
+            cuda_device_mode: CudaDeviceMode::Uuid,
+            run_workers_auto_cuda: false,
+            data_schema: None,
+            run_only_labels: None,
+            save_rendered_on_error: None,
+            tee_build_logs: false,
+            run_timeout: None,
+            run_retries: 0,
+            run_retry_backoff: Duration::ZERO,
+            compile_timeout: None,
+            stream_output_writer: None,
+            event_socket_path: None,
+            event_socket: EventSocket::default(),
+            progress_callback: None,
+            on_workspace_ready: None,
+            worker_status: WorkerStatus::default(),
+            pause_flag: PauseFlag::default(),
+            reduce_func: None,
+            reduced_data: Mutex::new(None),
+            dedupe_identical_renders: false,
+            trust_incremental: false,
+            dedupe_cache: Arc::new(Mutex::new(HashMap::new())),
+            result_cache: false,
+            pending_results: Arc::new(Mutex::new(HashMap::new())),
+            stop_flags: StopFlags::default(),
+            cancelled_datas: CancelledDatas::default(),
+            lazy_run_workspace_init: false,
+            pending_run_workspaces: Arc::new(Mutex::new(HashMap::new())),
+            staged_targets: Arc::new(Mutex::new(Vec::new())),
+            taken_datas: TakenDatas::default(),
+            unprocessed_datas: Mutex::new(vec![]),
+            exclusive_gate: Arc::new(ExclusiveGate::default()),
+            dry_run: false,
+            shell: ShellKind::Bash,
+            envs: HashMap::new(),
+            data_env_prefix: None,
+            record_timestamps: false,
+            measure_memory: false,
+            run_cwd: None,
+            warmup_runs: 0,
+            repeat_runs: 1,
+            metric_extractor: None,
+            verify_func: None,
+            extract_json: None,
+            templated_scripts: false,
+            split_run_results: false,
+        }
+    }
+
+    /// Replace the template file passed to `new()` with several template files,
+    /// each of them is rendered with the same data, empty paths are ignored
+    pub fn template_files<S: AsRef<Path>>(mut self, template_files: &[S]) -> Self {
+        self.template_files = template_files
+            .iter()
+            .map(|template_file| template_file.as_ref().to_path_buf())
+            .filter(|template_file| !template_file.as_os_str().is_empty())
+            .collect();
+        self
+    }
+
+    /// Render nothing, the datas only reach the compile script through `enable_cppflags`,
+    /// `data_env_prefix` or `templated_scripts`, e.g. for a makefile project
+    pub fn no_template(mut self) -> Self {
+        self.template_files.clear();
+        self
+    }
+
+    pub fn init_bash_script(mut self, init_bash_script: &str) -> Self {
+        self.init_bash_script = init_bash_script.to_string();
+        self.init_bash_script_steps = vec![];
+        self
+    }
+
+    pub fn compile_bash_script(mut self, compile_bash_script: &str) -> Self {
+        self.compile_bash_script = compile_bash_script.to_string();
+        self.compile_bash_script_steps = vec![];
+        self.compile_script_in_project = None;
+        self
+    }
+
+    pub fn run_bash_script(mut self, run_bash_script: &str) -> Self {
+        self.run_bash_script = run_bash_script.to_string();
+        self.run_bash_script_steps = vec![];
+        self.custom_run_bash_script = true;
+        self.run_script_in_project = None;
+        self
+    }
+
+    /// Like `compile_bash_script()`, but read the script from `script_path` relative to
+    /// each workspace before each compile, e.g. `scripts/compile.sh` versioned with the project
+    ///
+    /// A workspace checked out at another revision compiles with its own script, a data whose
+    /// workspace lacks it fails like a render error. `run()` and `validate()` check it exists in
+    /// `project_path`, `render_data()` reads it from there. It is rendered like a script string
+    /// with `templated_scripts`
+    pub fn compile_script_in_project<P: AsRef<Path>>(mut self, script_path: P) -> Self {
+        self.compile_script_in_project = Some(script_path.as_ref().to_path_buf());
+        self.compile_bash_script_steps = vec![];
+        self
+    }
+
+    /// Like `run_bash_script()`, but read the script from `script_path` relative to the workspace
+    /// of each run, the build workspace or the out-of-place run workspace, like
+    /// `compile_script_in_project()`
+    pub fn run_script_in_project<P: AsRef<Path>>(mut self, script_path: P) -> Self {
+        self.run_script_in_project = Some(script_path.as_ref().to_path_buf());
+        self.run_bash_script_steps = vec![];
+        self.custom_run_bash_script = true;
+        self
+    }
+
+    /// The compile and run scripts, read from the project with `compile_script_in_project()`
+    /// or `run_script_in_project()`, the workers read them again from their workspaces
+    fn bash_scripts(&self) -> Result<(String, String), Box<dyn Error>> {
+        let read = |script_in_project: &Option<PathBuf>, script: &String| match script_in_project {
+            Some(script_path) => {
+                let script_path = self.project_path.join(script_path);
+                std::fs::read_to_string(&script_path)
+                    .map_err(|e| format!("Failed to read the script {:?}: {}", script_path, e))
+            }
+            None => Ok(script.clone()),
+        };
+        Ok((
+            read(&self.compile_script_in_project, &self.compile_bash_script)?,
+            read(&self.run_script_in_project, &self.run_bash_script)?,
+        ))
+    }
+
+    /// Like `init_bash_script()`, but run each command separately and stop at the first failing
+    /// one, which is named in the error
+    pub fn init_bash_scripts<S: AsRef<str>>(mut self, init_bash_scripts: &[S]) -> Self {
+        self.init_bash_script_steps = Self::script_steps(init_bash_scripts);
+        self.init_bash_script = self.init_bash_script_steps.join("\n");
+        self
+    }
+
+    /// Like `compile_bash_script()`, but run each command separately and stop at the first
+    /// failing one, whose index is `"failed_step"` of the compile output
+    ///
+    /// A custom `compile_func` receives the commands joined by newlines as the compile script,
+    /// and each of them in `CompileContext::compile_script_steps`
+    pub fn compile_bash_scripts<S: AsRef<str>>(mut self, compile_bash_scripts: &[S]) -> Self {
+        self.compile_bash_script_steps = Self::script_steps(compile_bash_scripts);
+        self.compile_bash_script = self.compile_bash_script_steps.join("\n");
+        self.compile_script_in_project = None;
+        self
+    }
+
+    /// Like `run_bash_script()`, but run each command separately and stop at the first failing
+    /// one, whose index is `"failed_step"` of the run data
+    ///
+    /// A custom `run_func` receives the commands joined by newlines as the run script,
+    /// and each of them in `RunContext::run_script_steps`
+    pub fn run_bash_scripts<S: AsRef<str>>(mut self, run_bash_scripts: &[S]) -> Self {
+        self.run_bash_script_steps = Self::script_steps(run_bash_scripts);
+        self.run_bash_script = self.run_bash_script_steps.join("\n");
+        self.custom_run_bash_script = true;
+        self.run_script_in_project = None;
+        self
+    }
+
+    /// There is always at least one step, an empty script does nothing
+    fn script_steps<S: AsRef<str>>(scripts: &[S]) -> Vec<String> {
+        if scripts.is_empty() {
+            return vec![String::new()];
+        }
+        scripts
+            .iter()
+            .map(|script| script.as_ref().to_string())
+            .collect()
+    }
+
+    pub fn build_workers(mut self, build_workers: usize) -> Self {
+        self.build_workers = build_workers;
+        self
+    }
+
+    /// One build worker per available CPU divided by `compile_jobs`, at least one,
+    /// so call it after `compile_jobs()`
+    ///
+    /// `run()` warns when the build workers, compile jobs and run workers are more than twice
+    /// the available CPUs
+    pub fn build_workers_auto(mut self) -> Self {
+        self.build_workers = (available_cpus() / self.compile_jobs.max(1)).max(1);
+        self
+    }
+
+    /// Create this many build workspaces instead of one per build worker, the build workers claim
+    /// a free workspace for each data, so the spare ones are pre-warmed and ready to take over
+    ///
+    /// Values smaller than `build_workers` are ignored
+    pub fn workspace_pool_size(mut self, workspace_pool_size: usize) -> Self {
+        self.workspace_pool_size = Some(workspace_pool_size);
+        self
+    }
+
+    /// Prefix the names of the workspaces and the targets dir under `workspaces_path`,
+    /// e.g. `job123` gives `job123_workspace_0`, `job123_workspace_exe_0` and `job123_targets`
+    ///
+    /// Two instances sharing a `workspaces_path` would otherwise build into the same workspaces
+    /// and overwrite each other's executables, give each of them a distinct prefix. Note that
+    /// `no_cache` still removes the whole `workspaces_path`, including the other instances'
+    pub fn workspace_prefix(mut self, workspace_prefix: &str) -> Self {
+        self.workspace_prefix = workspace_prefix.to_string();
+        self.temp_target_path_dir = self
+            .workspaces_path
+            .join(self.workspace_name(Self::TEMP_TARGET_PATH_DIR));
+        self
+    }
+
+    /// `name` under the `workspace_prefix`, the numeric id of a workspace stays the last `_`
+    /// separated segment, as `PARABUILD_ID` and the CUDA device are derived from it
+    fn workspace_name(&self, name: &str) -> String {
+        if self.workspace_prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", self.workspace_prefix, name)
+        }
+    }
+
+    /// Re-run `init_bash_script` in a build workspace after this many compile failures in a row
+    /// there, so a build directory broken by e.g. an OOM-killed compile does not fail every
+    /// following data, `0` (default) never resets
+    pub fn reset_after_failures(mut self, reset_after_failures: usize) -> Self {
+        self.reset_after_failures = reset_after_failures;
+        self
+    }
+
+    /// Stop taking new datas once this many compiles have failed across all build workers,
+    /// `run()` finishes the datas in flight and returns what it has, the rest are in
+    /// `unprocessed_datas()`, `0` (default) never stops
+    pub fn max_compile_errors(mut self, max_compile_errors: usize) -> Self {
+        self.max_compile_errors = max_compile_errors;
+        self
+    }
+
+    /// The number of build workspaces, at least `build_workers`
+    fn workspace_pool_len(&self) -> usize {
+        self.workspace_pool_size
+            .unwrap_or(0)
+            .max(self.build_workers)
+    }
+
+    pub fn run_workers(mut self, run_workers: isize) -> Self {
+        self.run_workers_auto_cuda = false;
+        if run_workers > 0 {
+            self.run_method = RunMethod::OutOfPlace(run_workers as usize);
+        } else if run_workers == 0 {
+            self.run_method = RunMethod::No;
+        } else if run_workers < 0 {
+            self.run_method = RunMethod::Exclusive(-run_workers as usize);
+        }
+        self
+    }
+
+    pub fn run_workers_exclusive(mut self, run_workers: isize) -> Self {
+        self.run_workers_auto_cuda = false;
+        self.run_method = RunMethod::Exclusive(run_workers as usize);
+        self
+    }
+
+    /// Run out of place with one run worker per CUDA device,
+    /// the devices are those set by `gpu_devices()` or the auto-detected CUDA devices
+    ///
+    /// `init_workspace()` and `run()` return an error if there is no device
+    pub fn run_workers_auto_cuda(mut self) -> Self {
+        self.run_workers_auto_cuda = true;
+        self.run_method = RunMethod::OutOfPlace(0);
+        self
+    }
+
+    /// Copy and initialize each run workspace (`workspace_exe_i`) when its run worker gets
+    /// the first data to run, instead of in `init_workspace()`
+    ///
+    /// Startup is faster when there are many run workers or few datas compile,
+    /// at the cost of a slower first run. A failed lazy init aborts `run()` with a panic
+    pub fn lazy_run_workspace_init(mut self, lazy_run_workspace_init: bool) -> Self {
+        self.lazy_run_workspace_init = lazy_run_workspace_init;
+        self
+    }
+
+    pub fn run_method(mut self, run_method: RunMethod) -> Self {
+        self.run_workers_auto_cuda = false;
+        self.run_method = run_method;
+        self
+    }
+
+    pub fn run_func(mut self, run_func: RunFunc) -> Self {
+        self.run_func_data = run_func;
+        self
+    }
+
+    /// Abort the whole job on the first failed run by using `PANIC_ON_ERROR_DEFAULT_RUN_FUNC`,
+    /// the other workers stop and `run()` panics, `false` restores `IGNORE_ON_ERROR_DEFAULT_RUN_FUNC`
+    ///
+    /// It replaces the run function set by `run_func()`
+    pub fn panic_on_run_error(mut self, panic_on_run_error: bool) -> Self {
+        self.run_func_data = if panic_on_run_error {
+            PANIC_ON_ERROR_DEFAULT_RUN_FUNC
+        } else {
+            IGNORE_ON_ERROR_DEFAULT_RUN_FUNC
+        };
+        self
+    }
+
+    /// Parallelism inside a single compile, passed to the compile script as the environment
+    /// variable `PARABUILD_COMPILE_JOBS`, e.g. `make -j$PARABUILD_COMPILE_JOBS`, default to 1
+    ///
+    /// Up to `build_workers * compile_jobs` jobs run at the same time, keep it around the number
+    /// of CPU cores to avoid oversubscription
+    pub fn compile_jobs(mut self, compile_jobs: usize) -> Self {
+        self.compile_jobs = compile_jobs;
+        self
+    }
+
+    /// Bound the data queue of `get_data_queue_sender()` to `capacity` datas, the senders block
+    /// while it is full, so a producer of millions of datas does not run ahead of the builds
+    ///
+    /// `set_datas()` always queues every data, as they are in memory already
+    pub fn data_queue_capacity(mut self, capacity: usize) -> Self {
+        self.data_queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Share one compiler cache between all the build workspaces, so a translation unit compiled
+    /// in one workspace is a cache hit in the others
+    ///
+    /// Sets `CCACHE_DIR` and `SCCACHE_DIR` to this directory, and `CCACHE_BASEDIR` to `workspaces_path`
+    /// so ccache hashes the paths relative to the workspace, unless they are already set by `envs()`.
+    /// The compile script must still invoke the cache itself,
+    /// e.g. `cmake -DCMAKE_CXX_COMPILER_LAUNCHER=ccache` in the init script
+    pub fn compiler_cache_dir<P: AsRef<Path>>(mut self, compiler_cache_dir: P) -> Self {
+        self.compiler_cache_dir = Some(compiler_cache_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Replace the default way to run `compile_bash_script`, see `DEFAULT_COMPILE_FUNC`
+    pub fn compile_func(mut self, compile_func: CompileFunc) -> Self {
+        self.compile_func = compile_func;
+        self
+    }
+
+    pub fn compilation_error_handling_method(
+        mut self,
+        compilation_error_handling_method: CompliationErrorHandlingMethod,
+    ) -> Self {
+        self.compilation_error_handling_method = compilation_error_handling_method;
+        self
+    }
+
+    pub fn auto_gather_array_data(mut self, auto_gather_array_data: bool) -> Self {
+        self.auto_gather_array_data = auto_gather_array_data;
+        self
+    }
+
+    /// Sort the gathered run datas by the `id` of each result, the index of its data in the datas,
+    /// instead of the order the runs finish in, so the outputs of two runs can be diffed
+    ///
+    /// Only applied when the run datas are gathered into one array, see `auto_gather_array_data()`
+    pub fn sort_output_by_id(mut self, sort_output_by_id: bool) -> Self {
+        self.sort_output_by_id = sort_output_by_id;
+        self
+    }
+
+    pub fn in_place_template(mut self, in_place_template: bool) -> Self {
+        self.in_place_template = in_place_template;
+        self
+    }
+
+    /// Write the template expressions as `<open>N<close>` instead of `{{N}}`, e.g. `("<<", ">>")`
+    /// for templates that contain `{{` like C++ brace-init, the `{{` are then kept as is
+    ///
+    /// The files pulled in by the `include` helper are not affected
+    pub fn template_delimiters(mut self, open: &str, close: &str) -> Self {
+        assert!(
+            !open.is_empty() && !close.is_empty(),
+            "template delimiters must not be empty"
+        );
+        self.template_delimiters = Some((open.to_string(), close.to_string()));
+        self
+    }
+
+    /// Render the first template file of each data to the path rendered from this template,
+    /// e.g. `"src/kernel_{{N}}.cu"`, instead of the template file without its `.template`
+    ///
+    /// The path is relative to the workspace and must stay inside it, the compile script finds it
+    /// in `PARABUILD_TEMPLATE_OUTPUT`. The files rendered for the earlier datas of a workspace
+    /// are left in place
+    pub fn template_output_path_template<S: Into<String>>(
+        mut self,
+        template_output_path_template: S,
+    ) -> Self {
+        self.template_output_path_template = Some(template_output_path_template.into());
+        self
+    }
+
+    /// The registry rendering `template_output_path_template` as `TEMPLATE_OUTPUT_PATH`,
+    /// `run()` returns an error for an invalid template
+    fn template_output_path_registry(&self) -> Result<Option<Handlebars<'static>>, Box<dyn Error>> {
+        let Some(template) = &self.template_output_path_template else {
+            return Ok(None);
+        };
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(self.strict_templates);
+        // paths are not HTML
+        handlebars.register_escape_fn(handlebars::no_escape);
+        register_helpers(&mut handlebars, &self.project_path);
+        let translated = match &self.template_delimiters {
+            Some((open, close)) => translate_delimiters(template, open, close),
+            None => template.clone(),
+        };
+        handlebars
+            .register_template_string(TEMPLATE_OUTPUT_PATH, translated)
+            .map_err(|e| format!("Invalid template output path {:?}: {}", template, e))?;
+        Ok(Some(handlebars))
+    }
+
+    /// Fail to render when the template uses a variable missing from the data, instead of
+    /// rendering it as an empty string, `{{default N 42}}` still works for optional fields
+    ///
+    /// A data that fails to render is handled like a compilation error, with `Collect` it is
+    /// reported as `{"data": data, "category": "render_error", "render_error": "..."}` in
+    /// `compile_error_datas`
+    pub fn strict_templates(mut self, strict_templates: bool) -> Self {
+        self.strict_templates = strict_templates;
+        self
+    }
+
+    pub fn disable_progress_bar(mut self, disable_progress_bar: bool) -> Self {
+        self.disable_progress_bar = disable_progress_bar;
+        self
+    }
+
+    /// The indicatif template of the building and running bars, e.g.
+    /// `"{elapsed} {wide_bar} {pos}/{len} {msg}"`, `run()` returns an error if it is invalid
+    pub fn progress_style<S: Into<String>>(mut self, template: S) -> Self {
+        self.progress_template = Some(template.into());
+        self
+    }
+
+    /// Draw the bars and spinners with ASCII characters only, e.g. for CI logs
+    pub fn progress_ascii(mut self, progress_ascii: bool) -> Self {
+        self.progress_ascii = progress_ascii;
+        self
+    }
+
+    pub fn no_cache(mut self, no_cache: bool) -> Self {
+        self.no_cache = no_cache;
+        self
+    }
+
+    pub fn without_rsync(mut self, without_rsync: bool) -> Self {
+        self.without_rsync = without_rsync;
+        self
+    }
+
+    /// Extra arguments of the `rsync -a` that copies the project into the workspaces,
+    /// e.g. `["--no-perms", "--no-owner"]` on NFS, or `["--delete"]` to mirror the project exactly
+    pub fn rsync_args<S: AsRef<str>>(mut self, rsync_args: &[S]) -> Self {
+        self.rsync_args = rsync_args
+            .iter()
+            .map(|arg| arg.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// Gitignore-style glob patterns of extra project files not copied into the workspaces,
+    /// e.g. `["tests/fixtures/", "*.bin"]` for large assets that are not git-ignored
+    ///
+    /// Applied by both the rsync and the non-rsync copies
+    pub fn copy_excludes<S: AsRef<str>>(mut self, copy_excludes: &[S]) -> Self {
+        self.copy_excludes = copy_excludes
+            .iter()
+            .map(|exclude| exclude.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// How the project files are placed into the workspaces, hard links and reflinks save time
+    /// and space for large projects
+    ///
+    /// The template outputs, the target files and the directories of the target files
+    /// (e.g. `build/`) are always copied, so they stay independent per workspace.
+    /// Other files must not be modified in place by the init/compile scripts when using `Hardlink`,
+    /// or the project is modified too
+    pub fn copy_strategy(mut self, copy_strategy: CopyStrategy) -> Self {
+        self.copy_strategy = copy_strategy;
+        self
+    }
+
+    /// How the run workers wait for a moved target file to be ready before running it,
+    /// to prevent `text file is busy`
+    ///
+    /// `FileReadyCheck::Lsof` (default) requires `lsof`, `FileReadyCheck::Stable` waits until
+    /// the size and mtime of the file stop changing instead
+    pub fn file_ready_check(mut self, file_ready_check: FileReadyCheck) -> Self {
+        self.file_ready_wait.check = file_ready_check;
+        self
+    }
+
+    /// Poll a target file up to `attempts` times `interval` apart before giving up,
+    /// 100 times 100ms by default
+    pub fn file_ready_poll(mut self, attempts: usize, interval: Duration) -> Self {
+        self.file_ready_wait.attempts = attempts;
+        self.file_ready_wait.interval = interval;
+        self
+    }
+
+    pub fn enable_cppflags(mut self, enable_cppflags: bool) -> Self {
+        self.enable_cppflags = enable_cppflags;
+        self
+    }
+
+    /// Start the `CPPFLAGS` of `enable_cppflags` with `-DPARABUILD=ON`, on by default,
+    /// turn it off for projects that reject unknown defines
+    pub fn parabuild_define(mut self, parabuild_define: bool) -> Self {
+        self.parabuild_define = parabuild_define;
+        self
+    }
+
+    /// Do not compile a data whose rendered template outputs, `CPPFLAGS` and data envs are
+    /// identical to those of a data already built in this `run()`, reuse its target files instead
+    ///
+    /// The target files of every distinct render are kept under `workspaces_path` until the next run
+    pub fn dedupe_identical_renders(mut self, dedupe_identical_renders: bool) -> Self {
+        self.dedupe_identical_renders = dedupe_identical_renders;
+        self
+    }
+
+    /// Skip the compile of a data whose render is identical to that of the last successful compile
+    /// in the same build workspace, keeping the target files already built there,
+    /// e.g. consecutive datas differing only in the fields used by the run script
+    ///
+    /// Staleness is checked by the content hash of the rendered template outputs, the compile script,
+    /// `CPPFLAGS` and data envs instead of mtimes, which are not monotonic. The compiles that do run
+    /// should still ignore timestamps, like the `-B` of the default compile script
+    pub fn trust_incremental(mut self, trust_incremental: bool) -> Self {
+        self.trust_incremental = trust_incremental;
+        self
+    }
+
+    /// Skip the datas whose result is already recorded by a prior run, even of another invocation,
+    /// and report the recorded result with `"cached": true` instead of compiling and running it
+    ///
+    /// The results of the runs exiting with status 0 are kept under `workspaces_path/.cache`,
+    /// keyed by the hash of the data, the rendered template outputs, the compile and run scripts,
+    /// `CPPFLAGS` and data envs. Anything else the result depends on, e.g. the files of the project
+    /// not rendered from a template, is not checked. `no_cache` removes the recorded results
+    /// with the workspaces, `RunMethod::No` neither reads nor records them
+    pub fn result_cache(mut self, result_cache: bool) -> Self {
+        self.result_cache = result_cache;
+        self
+    }
+
+    fn result_cache_of_run(&self) -> Option<ResultCache> {
+        (self.result_cache && !matches!(self.run_method, RunMethod::No)).then(|| ResultCache {
+            dir: self.workspaces_path.join(Self::RESULT_CACHE_DIR),
+            pending: self.pending_results.clone(),
+        })
+    }
+
+    pub fn autosave_interval(mut self, autosave_interval: u64) -> Self {
+        self.autosave_interval = autosave_interval;
+        self
+    }
+
+    pub fn autosave_dir<S: AsRef<Path>>(mut self, autosave_dir: S) -> Self {
+        self.autosave_dir = autosave_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Gzip the autosave files into `*.json.gz`, `autosave_load()` reads both kinds
+    pub fn autosave_compress(mut self, autosave_compress: bool) -> Self {
+        self.autosave_compress = autosave_compress;
+        self
+    }
+
+    /// Number of previous autosaves kept as backups, `1` by default
+    ///
+    /// Every file of an autosave keeps its last `autosave_generations` versions as
+    /// `.json.{timestamp}`, older ones are pruned after each successful save, `0` keeps none
+    pub fn autosave_generations(mut self, autosave_generations: usize) -> Self {
+        self.autosave_generations = autosave_generations;
+        self
+    }
+
+    /// Set GPU devices to use (can be UUIDs or indices)
+    ///
+    /// e.g. `vec!["0".to_string(), "1".to_string()]` or `vec!["GPU-xxx".to_string(), "GPU-yyy".to_string()]`
+    ///
+    /// Each workspace uses the device of its index. A data can force its run onto another device with
+    /// the reserved key `CUDA_DEVICE_KEY`, e.g. `{"N": 10, "__cuda_device": "1"}` to compare two GPUs,
+    /// the templates still see the key, but it is left out of `CPPFLAGS` and `data_env_prefix()`
+    pub fn gpu_devices(mut self, gpu_devices: Vec<String>) -> Self {
+        self.gpu_devices = Some(gpu_devices);
+        self
+    }
+
+    /// How the CUDA devices are auto-detected when `gpu_devices()` is not set
+    ///
+    /// `CudaDeviceMode::Uuid` (default) uses the MIG devices, or the full GPUs when there is
+    /// no MIG device, `CudaDeviceMode::Index` sets `CUDA_VISIBLE_DEVICES` to the GPU indices
+    pub fn cuda_device_mode(mut self, cuda_device_mode: CudaDeviceMode) -> Self {
+        self.cuda_device_mode = cuda_device_mode;
+        self
+    }
+
+    /// Pin the run script of each workspace to one CPU chosen by the workspace id, like
+    /// `CUDA_VISIBLE_DEVICES`, so run workspace `i` gets both the `i`-th GPU and the `i`-th CPU
+    ///
+    /// The CPUs are those set by `run_cpus()` or the CPUs parabuild is allowed to run on,
+    /// reused round-robin when there are more workspaces than CPUs, only supported on Linux
+    pub fn pin_run_workers(mut self, pin_run_workers: bool) -> Self {
+        self.pin_run_workers = pin_run_workers;
+        self
+    }
+
+    /// Set the CPUs to pin the run workers to, implies `pin_run_workers(true)`
+    ///
+    /// e.g. `vec![0, 2, 4, 6]` to keep away from the hyper-threading siblings
+    pub fn run_cpus(mut self, run_cpus: Vec<usize>) -> Self {
+        self.pin_run_workers = true;
+        self.run_cpus = Some(run_cpus);
+        self
+    }
+
+    /// Validate every data against the JSON Schema `data_schema` in `set_datas()`,
+    /// so typos like `{"NN": 10}` are caught before building, must be called before `set_datas()`
+    pub fn data_schema(mut self, data_schema: JsonValue) -> Self {
+        self.data_schema = Some(data_schema);
+        self
+    }
+
+    /// Only build and run the datas whose `LABEL_KEY` is one of `labels`, the datas without a
+    /// label are left out too, must be called before `set_datas()` or `set_datas_sampled()`
+    ///
+    /// The datas keep their ids in the whole datas, like `set_datas_sampled()`, the datas sent to
+    /// `get_data_queue_sender()` are not filtered
+    pub fn run_only_labels<S: AsRef<str>>(mut self, labels: &[S]) -> Self {
+        self.run_only_labels = Some(
+            labels
+                .iter()
+                .map(|label| label.as_ref().to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Whether the `LABEL_KEY` of `data`, a string or a list of strings, has one of `labels`
+    pub fn has_label<S: AsRef<str>>(data: &JsonValue, labels: &[S]) -> bool {
+        let has = |label: &JsonValue| {
+            label
+                .as_str()
+                .is_some_and(|label| labels.iter().any(|l| l.as_ref() == label))
+        };
+        match &data[Self::LABEL_KEY] {
+            JsonValue::Array(data_labels) => data_labels.iter().any(has),
+            label => has(label),
+        }
+    }
+
+    /// When a data fails to compile and is collected, copy its rendered template outputs to
+    /// `dir/data_<id>.<ext>` (`dir/data_<id>_<template_index>.<ext>` for multiple template files),
+    /// and the compiler stderr to `dir/data_<id>.log`
+    pub fn save_rendered_on_error<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.save_rendered_on_error = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Classify the datas failed to compile by `(regex, label)` pairs matched against the compile
+    /// stderr and stdout, the first matching one wins
+    ///
+    /// The collected compile errors are `{"data": data, "category": label}`, `null` when no
+    /// regex matches, see `RunReport::compile_error_categories()`. Timeouts are categorized as
+    /// `TIMEOUT_CATEGORY` without matching. `run()` returns an error for an invalid regex
+    ///
+    /// e.g. `[("no matching function", "overload"), ("ptxas error", "ptxas")]`
+    pub fn compile_error_categories<S: AsRef<str>, T: AsRef<str>>(
+        mut self,
+        compile_error_categories: &[(S, T)],
+    ) -> Self {
+        self.compile_error_categories = compile_error_categories
+            .iter()
+            .map(|(regex, label)| (regex.as_ref().to_string(), label.as_ref().to_string()))
+            .collect();
+        self
+    }
+
+    fn compile_error_classifier(&self) -> Result<Vec<(Regex, String)>, Box<dyn Error>> {
+        self.compile_error_categories
+            .iter()
+            .map(|(regex, label)| {
+                Regex::new(regex)
+                    .map(|regex| (regex, label.clone()))
+                    .map_err(|e| {
+                        format!("Invalid compile error category regex {:?}: {}", regex, e).into()
+                    })
+            })
+            .collect()
+    }
+
+    /// Collect the datas whose compile succeeded with warnings, i.e. stderr lines matching
+    /// `warning_regex`, as `{"id": id, "data": data, "warnings": [lines]}`, see `warning_datas()`
+    ///
+    /// The compiles skipped by `dedupe_identical_renders` or `trust_incremental` have no stderr
+    /// to scan, warnings do not fail the data either way
+    pub fn collect_warnings(mut self, collect_warnings: bool) -> Self {
+        self.collect_warnings = collect_warnings;
+        self
+    }
+
+    /// The regex matched against each stderr line by `collect_warnings`,
+    /// `DEFAULT_WARNING_REGEX` by default, `run()` returns an error for an invalid regex
+    pub fn warning_regex<S: Into<String>>(mut self, warning_regex: S) -> Self {
+        self.warning_regex = warning_regex.into();
+        self
+    }
+
+    fn warning_detector(&self) -> Result<Option<Regex>, Box<dyn Error>> {
+        if !self.collect_warnings {
+            return Ok(None);
+        }
+        Regex::new(&self.warning_regex)
+            .map(Some)
+            .map_err(|e| format!("Invalid warning regex {:?}: {}", self.warning_regex, e).into())
+    }
+
+    /// The datas whose compile emitted warnings in the last `run()` with `collect_warnings`,
+    /// sorted by id
+    pub fn warning_datas(&self) -> Vec<JsonValue> {
+        let mut warning_datas = self.warning_datas.lock().unwrap().clone();
+        warning_datas.sort_by_key(|warning_data| warning_data["id"].as_u64());
+        warning_datas
+    }
+
+    /// Append the stdout and stderr of every compile to `parabuild_compile.log` in the build
+    /// workspace, after a `==== data <id> ... ====` header, e.g. to `tail -f` one of the workers
+    pub fn tee_build_logs(mut self, tee_build_logs: bool) -> Self {
+        self.tee_build_logs = tee_build_logs;
+        self
+    }
+
+    /// Kill the run script when it takes longer than `run_timeout`
+    ///
+    /// The result of a killed run has `"status": -1` and `"timed_out": true`
+    pub fn run_timeout(mut self, run_timeout: Duration) -> Self {
+        self.run_timeout = Some(run_timeout);
+        self
+    }
+
+    /// Re-execute the run script up to `run_retries` times when it exits with a non-zero status,
+    /// only the last attempt is recorded, with the number of attempts in `"attempts"`
+    pub fn run_retries(mut self, run_retries: usize) -> Self {
+        self.run_retries = run_retries;
+        self
+    }
+
+    /// Wait `run_retry_backoff` before each retry set by `run_retries`
+    pub fn run_retry_backoff(mut self, run_retry_backoff: Duration) -> Self {
+        self.run_retry_backoff = run_retry_backoff;
+        self
+    }
+
+    /// Kill the compile script when it takes longer than `compile_timeout`
+    ///
+    /// The data is handled by `compilation_error_handling_method` like other compilation errors,
+    /// when collected, it is `{"data": data, "category": "timeout"}` in `compile_error_datas`
+    pub fn compile_timeout(mut self, compile_timeout: Duration) -> Self {
+        self.compile_timeout = Some(compile_timeout);
+        self
+    }
+
+    /// Write each run result as a single json line as soon as it finishes
+    ///
+    /// The aggregated result returned by `run()` is not affected
+    pub fn stream_output_writer(mut self, stream_output_writer: Box<dyn Write + Send>) -> Self {
+        self.stream_output_writer = Some(Arc::new(Mutex::new(stream_output_writer)));
+        self
+    }
+
+    /// Report progress through `progress_callback` instead of the progress bars,
+    /// the progress bars are hidden when it is set
+    pub fn progress_callback<F>(mut self, progress_callback: F) -> Self
+    where
+        F: Fn(ProgressEvent) + Send + Sync + 'static,
+    {
+        self.progress_callback = Some(Arc::new(progress_callback));
+        self
+    }
+
+    /// Call `on_workspace_ready` from the init thread of each workspace as soon as it is copied
+    /// and initialized, instead of only learning it when `init_workspace()` returns
+    ///
+    /// The build workspaces are numbered `0..`, and the run workspaces follow them. It is not called
+    /// for a workspace that fails to initialize, nor for the run workspaces left to the run workers
+    /// by `lazy_run_workspace_init`
+    pub fn on_workspace_ready<F>(mut self, on_workspace_ready: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_workspace_ready = Some(Arc::new(on_workspace_ready));
+        self
+    }
+
+    /// Also publish the progress events to the Unix socket at `event_socket`, one json line per event
+    /// like `{"event": "run_finished", "id": 3, "status": 0}`, see `ProgressEvent::to_json()`
+    ///
+    /// `run()` connects to the socket, so a monitor must be listening on it first,
+    /// e.g. `socat UNIX-LISTEN:/tmp/parabuild.sock -`. Failing to connect or write only prints a
+    /// warning, and the progress bars are kept
+    pub fn event_socket<P: AsRef<Path>>(mut self, event_socket: P) -> Self {
+        self.event_socket_path = Some(event_socket.as_ref().to_path_buf());
+        self
+    }
+
+    /// `progress_callback` followed by the event socket
+    fn progress_sink(&self) -> Option<ProgressCallback> {
+        if self.event_socket_path.is_none() {
+            return self.progress_callback.clone();
+        }
+        let progress_callback = self.progress_callback.clone();
+        let event_socket = self.event_socket.clone();
+        Some(Arc::new(move |event: ProgressEvent| {
+            event_socket.send(&event);
+            if let Some(progress_callback) = &progress_callback {
+                progress_callback(event);
+            }
+        }))
+    }
+
+    /// Reduce the gathered run datas into a summary, e.g. the data with the minimum latency,
+    /// get it by `reduced_data()` after `run()` or `gather_data()`
+    ///
+    /// Only applied when the gathered run datas is an array
+    pub fn reduce_func<F>(mut self, reduce_func: F) -> Self
+    where
+        F: Fn(&[JsonValue]) -> JsonValue + Send + Sync + 'static,
+    {
+        self.reduce_func = Some(Arc::new(reduce_func));
+        self
+    }
+
+    /// The output of `reduce_func` of the last `run()` or `gather_data()`
+    pub fn reduced_data(&self) -> Option<JsonValue> {
+        self.reduced_data.lock().unwrap().clone()
+    }
+
+    /// Stop starting new builds and runs until `resume()`, the in-flight ones finish, e.g. from
+    /// another thread to yield the machine to an urgent job without losing the state of the run
+    ///
+    /// `run()` also pauses on SIGUSR1 and resumes on SIGUSR2 on Unix, a pause by signal is only
+    /// lifted by SIGUSR2. The previous handlers of the signals are restored when `run()` returns.
+    /// Ctrl-C still stops a paused run
+    pub fn pause(&self) {
+        self.pause_flag.set(true);
+    }
+
+    /// Lift the pause of `pause()`
+    pub fn resume(&self) {
+        self.pause_flag.set(false);
+    }
+
+    /// Whether `pause()` or SIGUSR1 paused the run
+    pub fn is_paused(&self) -> bool {
+        self.pause_flag.is_set()
+    }
+
+    /// A handle to poll how many builds/runs are done and in flight while `run()` is executing,
+    /// e.g. from another thread for a dashboard
+    pub fn worker_status(&self) -> WorkerStatus {
+        self.worker_status.clone()
+    }
+
+    /// The datas left unprocessed by the last `run()` with the reasons, sorted by id,
+    /// e.g. the datas skipped by Ctrl-C that `--continue` runs again
+    ///
+    /// The datas still in the data queue when the run was stopped are taken out of it
+    pub fn unprocessed_datas(&self) -> Vec<UnprocessedData> {
+        self.unprocessed_datas.lock().unwrap().clone()
+    }
+
+    /// Collect the unprocessed datas of a finished `run()` from the taken datas,
+    /// the reason of the started ones is derived from the stop flags
+    fn collect_unprocessed_datas(&self, processed_data_ids: &[usize]) {
+        let processed_data_ids: HashSet<usize> = processed_data_ids.iter().copied().collect();
+        let StopFlags { stop, abort } = &self.stop_flags;
+        let reason = if abort.load(Ordering::Relaxed) {
+            UnprocessedReason::Aborted
+        } else {
+            UnprocessedReason::Stopped
+        };
+        let mut unprocessed_datas = std::mem::take(&mut *self.taken_datas.skipped.lock().unwrap());
+        unprocessed_datas.extend(
+            std::mem::take(&mut *self.taken_datas.started.lock().unwrap())
+                .into_iter()
+                .filter(|(id, _)| !processed_data_ids.contains(id))
+                .map(|(id, data)| UnprocessedData { id, data, reason }),
+        );
+        if stop.load(Ordering::Relaxed) {
+            unprocessed_datas.extend(
+                self.data_queue_receiver
+                    .iter()
+                    .flat_map(|receiver| receiver.try_iter())
+                    .map(|(id, data)| UnprocessedData {
+                        id,
+                        data,
+                        reason: UnprocessedReason::NotStarted,
+                    }),
+            );
+        }
+        unprocessed_datas.sort_by_key(|unprocessed_data| unprocessed_data.id);
+        *self.unprocessed_datas.lock().unwrap() = unprocessed_datas;
+    }
+
+    /// Only render the templates, do not compile or run
+    ///
+    /// `run()` then returns `run_datas` as `null`, and the datas failed to render as
+    /// `{"data": data, "category": "render_error", "render_error": "..."}` in place of
+    /// `compile_error_datas`
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Shell used to run the init/compile/run scripts, default to `ShellKind::Bash`
+    pub fn shell(mut self, shell: ShellKind) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    /// Extra environment variables for the init/compile/run scripts
+    ///
+    /// Variables set by parabuild itself (`PARABUILD_ID`, `CUDA_VISIBLE_DEVICES`, `CPPFLAGS`)
+    /// take precedence over them
+    pub fn envs(mut self, envs: HashMap<String, String>) -> Self {
+        self.envs = envs;
+        self
+    }
+
+    /// Expose each field of the data to the compile/run scripts as an environment variable
+    ///
+    /// e.g. with prefix `PARA_`, data `{"N": 10, "MODE": "fast"}` gives `PARA_N=10` and `PARA_MODE=fast`,
+    /// nested values are serialized as json strings
+    pub fn data_env_prefix(mut self, data_env_prefix: &str) -> Self {
+        self.data_env_prefix = Some(data_env_prefix.to_string());
+        self
+    }
+
+    /// Add `started_at` and `finished_at` RFC 3339 timestamps of the run script to the run datas,
+    /// e.g. to find idle gaps of the run workers
+    pub fn record_timestamps(mut self, record_timestamps: bool) -> Self {
+        self.record_timestamps = record_timestamps;
+        self
+    }
+
+    /// Add the peak resident set size of the run script in KiB to the run datas as `max_rss_kb`,
+    /// read through `wait4` when it exits, only on Unix
+    ///
+    /// It is the largest of the run script and the descendants it waited for, not their sum,
+    /// and comes from the reported run when `repeat_runs` is set
+    pub fn measure_memory(mut self, measure_memory: bool) -> Self {
+        self.measure_memory = measure_memory;
+        self
+    }
+
+    /// The directory the compile script puts the artifacts in, relative to the workspace,
+    /// e.g. the CMake binary dir of an out-of-tree build
+    ///
+    /// `target_files` are resolved relative to it, and the run script runs in it unless `run_cwd()` is set
+    pub fn build_dir<P: AsRef<Path>>(mut self, build_dir: P) -> Self {
+        self.build_dir = Some(build_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// `target_files` relative to the workspace
+    fn target_paths(&self) -> Vec<PathBuf> {
+        match &self.build_dir {
+            Some(build_dir) => self
+                .target_files
+                .iter()
+                .map(|target_file| build_dir.join(target_file))
+                .collect(),
+            None => self.target_files.clone(),
+        }
+    }
+
+    /// Run the run script in `run_cwd` relative to the workspace instead of the workspace root
+    /// (or `build_dir()`), e.g. `build`, in both the in-place and the out-of-place run workspaces
+    pub fn run_cwd<P: AsRef<Path>>(mut self, run_cwd: P) -> Self {
+        self.run_cwd = Some(run_cwd.as_ref().to_path_buf());
+        self
+    }
+
+    /// Run the compile script in `compile_cwd` relative to the workspace instead of the workspace root,
+    /// e.g. for a project configured in a subdirectory, `PARABUILD_TEMPLATE_OUTPUT` and the
+    /// `target_files` stay relative to the workspace root
+    pub fn compile_cwd<P: AsRef<Path>>(mut self, compile_cwd: P) -> Self {
+        self.compile_cwd = Some(compile_cwd.as_ref().to_path_buf());
+        self
+    }
+
+    /// Execute the run script `warmup_runs` extra times before the recorded run of each data,
+    /// discarding their outputs, e.g. to warm up the caches when benchmarking
+    pub fn warmup_runs(mut self, warmup_runs: usize) -> Self {
+        self.warmup_runs = warmup_runs;
+        self
+    }
+
+    /// Run the run script `repeat_runs` times per data with the default run functions,
+    /// the result of the last run gets `"repeats"` and
+    /// `"stats": {"samples", "min", "max", "mean", "stddev"}` of the samples
+    ///
+    /// The samples are the `run_ms` of each run, or the metric of `metric_extractor()`.
+    /// The repeats stop at the first failed run, which is the one reported
+    pub fn repeat_runs(mut self, repeat_runs: usize) -> Self {
+        self.repeat_runs = repeat_runs;
+        self
+    }
+
+    /// Extract the sample of each run from its stdout for the `stats` of `repeat_runs()`,
+    /// runs returning `None` are left out of the samples
+    ///
+    /// e.g. `|stdout| stdout.trim().parse().ok()` for a program printing its latency
+    pub fn metric_extractor<F>(mut self, metric_extractor: F) -> Self
+    where
+        F: Fn(&str) -> Option<f64> + Send + Sync + 'static,
+    {
+        self.metric_extractor = Some(Arc::new(metric_extractor));
+        self
+    }
+
+    /// Parse the stdout of each run as json and lift the value at this JSON Pointer into the result
+    /// as `metric`, e.g. `/result/latency`, nothing is added when the stdout is not json or has no such value
+    pub fn extract_json<S: Into<String>>(mut self, pointer: S) -> Self {
+        self.extract_json = Some(pointer.into());
+        self
+    }
+
+    /// Check the result of each run, e.g. compare its stdout with the value expected for the data,
+    /// the outcome is added to the result as `"verified": bool`, see `RunReport::verification_failures()`
+    ///
+    /// It is called with the data and the result of the run in the run worker after every run
+    /// of the default run functions, a custom `run_func` can call `RunContext::verify_func` itself
+    pub fn verify_func<F>(mut self, verify_func: F) -> Self
+    where
+        F: Fn(&JsonValue, &JsonValue) -> bool + Send + Sync + 'static,
+    {
+        self.verify_func = Some(Arc::new(verify_func));
+        self
+    }
+
+    /// Return the runs that exit with a non-zero status in `RunReport::run_errors` of `run_report()`,
+    /// apart from the successful ones left in `RunReport::run_data`, the output of `run()` is unchanged
+    pub fn split_run_results(mut self, split_run_results: bool) -> Self {
+        self.split_run_results = split_run_results;
+        self
+    }
+
+    /// Render the compile and run scripts as handlebars templates against each data before
+    /// running them, e.g. `cmake --build build --target {{target}}`
+    ///
+    /// The values are not escaped or quoted, the rendered scripts run in the shell as they are,
+    /// so only use datas you trust. Render errors are handled like those of the template files,
+    /// the scripts always use the `{{ }}` delimiters
+    pub fn templated_scripts(mut self, templated_scripts: bool) -> Self {
+        self.templated_scripts = templated_scripts;
+        self
+    }
+
+    /// Render this handlebars template against each data and write it to the stdin of the run script,
+    /// e.g. for a program reading its configuration from stdin, the stdin is empty by default
+    ///
+    /// It is rendered like `templated_scripts()`, without escaping and with the `{{ }}` delimiters
+    pub fn run_stdin_template<S: Into<String>>(mut self, run_stdin_template: S) -> Self {
+        self.run_stdin_template = Some(run_stdin_template.into());
+        self
+    }
+
+    /// Render each element as a handlebars template against each data and, unless
+    /// `run_bash_script()` or `run_bash_scripts()` is set, execute `./<first target> <args>..`
+    /// directly instead of through the shell, e.g. `&["--size", "{{N}}"]`
+    ///
+    /// Each rendered element is passed as a single argument, spaces and quotes included,
+    /// it is rendered like `templated_scripts()`, without escaping and with the `{{ }}` delimiters
+    pub fn run_args_template<S: AsRef<str>>(mut self, run_args_template: &[S]) -> Self {
+        self.run_args_template = Some(
+            run_args_template
+                .iter()
+                .map(|arg| arg.as_ref().to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// `./<first target>` followed by `run_args_template`, when it replaces the run script
+    fn run_command_template(&self) -> Option<Vec<String>> {
+        if self.custom_run_bash_script {
+            return None;
+        }
+        let run_args_template = self.run_args_template.as_ref()?;
+        let target_file = self.target_files.first()?;
+        let mut run_command = vec![format!("./{}", target_file.to_string_lossy())];
+        run_command.extend(run_args_template.iter().cloned());
+        Some(run_command)
+    }
+
+    fn progress_bar_disabled(&self) -> bool {
+        self.disable_progress_bar || self.progress_callback.is_some()
+    }
+
+    /// Every combination of the values of `params`, the values of the last parameter change first
+    ///
+    /// e.g. `[("N", [1, 2]), ("B", [32, 64])]` gives `{"N": 1, "B": 32}`, `{"N": 1, "B": 64}`,
+    /// `{"N": 2, "B": 32}` and `{"N": 2, "B": 64}`
+    pub fn cartesian_datas<I, K>(params: I) -> Vec<JsonValue>
+    where
+        I: IntoIterator<Item = (K, Vec<JsonValue>)>,
+        K: Into<String>,
+    {
+        let mut datas = vec![serde_json::Map::new()];
+        for (key, values) in params {
+            let key = key.into();
+            let mut next_datas = Vec::with_capacity(datas.len() * values.len());
+            for data in datas.iter() {
+                for value in values.iter() {
+                    let mut data = data.clone();
+                    data.insert(key.clone(), value.clone());
+                    next_datas.push(data);
+                }
+            }
+            datas = next_datas;
+        }
+        datas.into_iter().map(JsonValue::Object).collect()
+    }
+
+    /// Set datas to be rendered into the template
+    pub fn set_datas(&mut self, datas: Vec<JsonValue>) -> Result<(), Box<dyn Error>> {
+        if self.data_queue_receiver.is_some() {
+            return Err("Data queue receiver is already initialized".into());
+        }
+        self.validate_datas(&datas)?;
+        self.check_datas_hash(&datas)?;
+        let processed_data_ids_set = self.continue_processed_data_ids.take().unwrap_or_default();
+        self.feed_datas(datas, &processed_data_ids_set, None);
+        Ok(())
+    }
+
+    /// Set datas to be rendered into the template
+    pub fn set_datas_with_processed_data_ids_set(
+        &mut self,
+        datas: Vec<JsonValue>,
+        processed_data_ids_set: HashSet<usize>,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.data_queue_receiver.is_some() {
+            return Err("Data queue receiver is already initialized".into());
+        }
+        self.validate_datas(&datas)?;
+        self.check_datas_hash(&datas)?;
+        self.feed_datas(datas, &processed_data_ids_set, None);
+        Ok(())
+    }
+
+    /// `set_datas()` that only builds and runs the datas picked by `sample`,
+    /// e.g. for a quick sanity pass over a huge dataset
+    ///
+    /// The datas keep their ids in the whole `datas`, so the results, the autosaves and
+    /// `--continue` stay consistent with a later full run
+    pub fn set_datas_sampled(
+        &mut self,
+        datas: Vec<JsonValue>,
+        sample: DataSample,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.data_queue_receiver.is_some() {
+            return Err("Data queue receiver is already initialized".into());
+        }
+        let sampled_ids: HashSet<usize> = sample.sample_ids(datas.len())?.into_iter().collect();
+        self.validate_datas(&datas)?;
+        self.check_datas_hash(&datas)?;
+        let processed_data_ids_set = self.continue_processed_data_ids.take().unwrap_or_default();
+        self.feed_datas(datas, &processed_data_ids_set, Some(&sampled_ids));
+        Ok(())
+    }
+
+    /// Send the datas not processed yet into a new data queue, only the `sampled_ids` if given
+    /// and those with the `run_only_labels`
+    fn feed_datas(
+        &mut self,
+        datas: Vec<JsonValue>,
+        processed_data_ids_set: &HashSet<usize>,
+        sampled_ids: Option<&HashSet<usize>>,
+    ) {
+        let (data_queue_sender, data_queue_receiver) = unbounded();
+        self.data_queue_receiver = Some(data_queue_receiver);
+        self.resumed_data_count = 0;
+        for id_data in datas.into_iter().enumerate() {
+            if sampled_ids.is_some_and(|sampled_ids| !sampled_ids.contains(&id_data.0)) {
+                continue;
+            }
+            if let Some(labels) = &self.run_only_labels {
+                if !Self::has_label(&id_data.1, labels) {
+                    continue;
+                }
+            }
+            if !processed_data_ids_set.contains(&id_data.0) {
+                data_queue_sender.send(id_data).unwrap();
+            } else {
+                self.resumed_data_count += 1;
+            }
+        }
+    }
+
+    /// Remember the hash of `datas` for autosave, and check it against the one loaded by
+    /// `autosave_load()`
+    fn check_datas_hash(&mut self, datas: &[JsonValue]) -> Result<(), Box<dyn Error>> {
+        let hash = datas_hash(datas);
+        if let Some(continue_datas_hash) = &self.continue_datas_hash {
+            if *continue_datas_hash != hash {
+                return Err(
+                    "Datas changed since the autosave, refusing to continue with different datas"
+                        .into(),
+                );
+            }
+        }
+        self.datas_hash = Some(hash);
+        Ok(())
+    }
+
+    /// Check `datas` against `data_schema`, the error lists every invalid data with its index
+    fn validate_datas(&self, datas: &[JsonValue]) -> Result<(), Box<dyn Error>> {
+        let data_schema = match &self.data_schema {
+            Some(data_schema) => data_schema,
+            None => return Ok(()),
+        };
+        let validator = jsonschema::validator_for(data_schema)
+            .map_err(|e| format!("Invalid data schema: {}", e))?;
+        let mut errors = vec![];
+        for (i, data) in datas.iter().enumerate() {
+            for error in validator.iter_errors(data) {
+                errors.push(format!(
+                    "data {} at \"{}\": {}",
+                    i, error.instance_path, error
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Data validation failed:\n{}", errors.join("\n")).into())
+        }
+    }
+
+    /// A sender to control the datas while running, e.g. `ControlMsg::Cancel(id)` skips the
+    /// data `id` if no build worker has taken it yet
+    ///
+    /// The cancelled datas are not in the `processed_data_ids` returned by `run()`, so they are
+    /// run by `--continue`
+    pub fn get_control_sender(&mut self) -> Sender<ControlMsg> {
+        let (control_sender, control_receiver) = unbounded();
+        self.cancelled_datas.receiver = Some(control_receiver);
+        control_sender
+    }
+
+    /// The sender of a new data queue, bounded by `data_queue_capacity()` if set, so the datas can be
+    /// produced while running, `run()` returns once every sender is dropped
+    pub fn get_data_queue_sender(&mut self) -> Result<Sender<(usize, JsonValue)>, Box<dyn Error>> {
+        if self.data_queue_receiver.is_some() {
+            return Err("Data queue receiver is already initialized".into());
+        }
+        let (data_queue_sender, data_queue_receiver) = match self.data_queue_capacity {
+            Some(capacity) => bounded(capacity),
+            None => unbounded(),
+        };
+        self.data_queue_receiver = Some(data_queue_receiver);
+        Ok(data_queue_sender)
+    }
+
+    /// Drop the data queue, the senders of `get_data_queue_sender()` then fail instead of
+    /// blocking on a full bounded queue, e.g. after a stopped `run()`
+    pub fn close_data_queue(&mut self) {
+        self.data_queue_receiver = None;
+    }
+
+    /// Number of run workers of `OutOfPlace`, `Exclusive` and `Interleaved`
+    fn out_of_place_run_workers(&self) -> Result<usize, Box<dyn Error>> {
+        match self.run_method {
+            RunMethod::OutOfPlace(_) if self.run_workers_auto_cuda => {
+                let device_count = match &self.gpu_devices {
+                    Some(devices) => devices.len(),
+                    None => detected_cuda_devices(self.cuda_device_mode).len(),
+                };
+                if device_count == 0 {
+                    return Err("No CUDA device found for `run_workers_auto_cuda`".into());
+                }
+                Ok(device_count)
+            }
+            RunMethod::OutOfPlace(run_workers) => Ok(run_workers),
+            RunMethod::Exclusive(run_workers) => Ok(run_workers),
+            RunMethod::Interleaved(run_workers) => Ok(run_workers),
+            _ => Ok(0),
+        }
+    }
+
+    /// `copy_excludes`, plus `workspaces_path` when it is inside `project_path`, so the workspaces
+    /// are not copied into every new workspace, whether copied with rsync or not
+    ///
+    /// `workspaces_path` must exist
+    fn workspace_copy_excludes(&self) -> Vec<String> {
+        let mut copy_excludes = self.copy_excludes.clone();
+        if let (Ok(project_path), Ok(workspaces_path)) = (
+            std::fs::canonicalize(&self.project_path),
+            std::fs::canonicalize(&self.workspaces_path),
+        ) {
+            if let Ok(nested) = workspaces_path.strip_prefix(&project_path) {
+                if !nested.as_os_str().is_empty() {
+                    // anchored to the project root, for both rsync and the ignore overrides
+                    copy_excludes.push(format!("/{}", nested.to_string_lossy()));
+                }
+            }
+        }
+        copy_excludes
+    }
+
+    /// Paths that are always copied by `copy_strategy`, as they are written per workspace
+    fn copy_independent_paths(&self) -> Vec<PathBuf> {
+        let mut independent_paths = vec![];
+        for template_file in self.template_files.iter() {
+            independent_paths.push(template_file.clone());
+            independent_paths.push(template_file.with_extension(""));
+        }
+        for target_file in self.target_paths().iter() {
+            independent_paths.push(target_file.clone());
+            if let Some(parent) = target_file.parent() {
+                if !parent.as_os_str().is_empty() {
+                    independent_paths.push(parent.to_path_buf());
+                }
+            }
+        }
+        independent_paths
+    }
+
+    /// Check the configuration before the workspaces are copied, returning every problem found
+    /// instead of the first error of `init_workspace()` or `run()`
+    ///
+    /// Checks that `project_path` and the template files exist, that there is a target file unless
+    /// `RunMethod::InPlace`, that the shell, rsync (unless `without_rsync`) and lsof
+    /// (with `FileReadyCheck::Lsof`) are installed, that `autosave_dir` is writable,
+    /// and the regexes, progress style and run workers checked by `run()`
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if !self.project_path.is_dir() {
+            problems.push(format!(
+                "Project path {:?} is not a directory",
+                self.project_path
+            ));
+        } else {
+            for template_file in self.template_files.iter() {
+                let template_path = self.project_path.join(template_file);
+                if !template_path.is_file() {
+                    problems.push(format!("Template file {:?} does not exist", template_path));
+                }
+            }
+        }
+        if self.target_files.is_empty() && !matches!(self.run_method, RunMethod::InPlace) {
+            problems.push("No target file, which only `RunMethod::InPlace` allows".to_string());
+        }
+        let mut commands = vec![self.shell.program()];
+        if !self.without_rsync {
+            commands.push("rsync");
+        }
+        if self.file_ready_wait.check == FileReadyCheck::Lsof {
+            commands.push("lsof");
+        }
+        for command in commands {
+            if !is_command_installed(command) {
+                problems.push(format!("{} is not installed", command));
+            }
+        }
+        // the autosave dir is created by the first autosave
+        let autosave_root = self
+            .autosave_dir
+            .ancestors()
+            .find(|path| path.exists())
+            .unwrap_or(Path::new("."));
+        if let Err(e) = tempfile::tempfile_in(autosave_root) {
+            problems.push(format!(
+                "Autosave dir {:?} is not writable: {}",
+                self.autosave_dir, e
+            ));
+        }
+        if let Err(e) = self.out_of_place_run_workers() {
+            problems.push(e.to_string());
+        }
+        if let Err(e) = self.compile_error_classifier() {
+            problems.push(e.to_string());
+        }
+        if let Err(e) = self.warning_detector() {
+            problems.push(e.to_string());
+        }
+        if let Err(e) = self.template_output_path_registry() {
+            problems.push(e.to_string());
+        }
+        if let Err(e) = self.bash_scripts() {
+            problems.push(e.to_string());
+        }
+        if let Err(e) = self.progress_bar_style() {
+            problems.push(format!("Invalid progress style: {}", e));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Initialize workspaces
+    pub fn init_workspace(&self) -> Result<(), Box<dyn Error>> {
+        if !is_command_installed("rsync") && !self.without_rsync {
+            return Err("rsync is not installed, set `without_rsync` to true to ignore".into());
+        }
+        let out_of_place_run_workers = self.out_of_place_run_workers()?;
+        let workspaces_path = if self.workspaces_path.is_absolute() {
+            self.workspaces_path.clone()
+        } else {
+            env::current_dir().unwrap().join(&self.workspaces_path)
+        };
+        if self.no_cache && self.workspaces_path.exists() {
+            std::fs::remove_dir_all(&self.workspaces_path).unwrap();
+        }
+        std::fs::create_dir_all(&workspaces_path).unwrap();
+        let copy_excludes = self.workspace_copy_excludes();
+        let mut project_path = self.project_path.clone();
+        let move_to_temp_dir = workspaces_path
+            .starts_with(std::fs::canonicalize(&self.project_path).unwrap())
+            && self.without_rsync;
+        let independent_paths = self.copy_independent_paths();
+        let mut build_handles = vec![];
+        if move_to_temp_dir {
+            let pb = Self::add_copy_progress_bar2(
+                self.progress_bar_disabled(),
+                self.progress_ascii,
+                &self.mpb,
+                "copying to temp dir",
+            );
+            project_path = tempdir().unwrap().into_path();
+            copy_dir_with_ignore(&self.project_path, &project_path, &copy_excludes, &pb).unwrap();
+            pb.finish_and_clear();
+        }
+        let workspace_init = WorkspaceInit {
+            source: project_path,
+            plain_copy: move_to_temp_dir,
+            without_rsync: self.without_rsync,
+            rsync_args: self.rsync_args.clone(),
+            copy_excludes,
+            copy_strategy: self.copy_strategy,
+            independent_paths,
+            shell: self.shell,
+            init_bash_script: self.init_bash_script.clone(),
+            init_bash_script_steps: self.init_bash_script_steps.clone(),
+            envs: self.envs.clone(),
+        };
+        for (i, destination) in (0..self.workspace_pool_len())
+            .map(|i| (i, self.workspace_name(&format!("workspace_{}", i))))
+        {
+            let destination = self.workspaces_path.join(destination);
+            let workspace_init = workspace_init.clone();
+            let mpb = self.mpb.clone();
+            let disable_progress_bar = self.progress_bar_disabled();
+            let progress_ascii = self.progress_ascii;
+            let on_workspace_ready = self.on_workspace_ready.clone();
+            let handle = std::thread::spawn(move || {
+                let label = format!("init workspace {}", i);
+                let sp = Self::add_copy_progress_bar2(
+                    disable_progress_bar,
+                    progress_ascii,
+                    &mpb,
+                    format!("{}: copying", label),
+                );
+                let initialized =
+                    workspace_init.init(&format!("workspace_{}", i), &destination, &sp, &label);
+                if let (Ok(()), Some(on_workspace_ready)) = (&initialized, &on_workspace_ready) {
+                    on_workspace_ready(i);
+                }
+                initialized
+            });
+            build_handles.push(handle);
+        }
+        let mut run_handles = vec![];
+        self.pending_run_workspaces.lock().unwrap().clear();
+        if out_of_place_run_workers > 0 {
+            // only compile to executable when run_workers = 0
+            std::fs::create_dir_all(&self.temp_target_path_dir).unwrap();
+            for (i, destination) in (0..out_of_place_run_workers)
+                .map(|i| (i, self.workspace_name(&format!("workspace_exe_{}", i))))
+            {
+                let destination = self.workspaces_path.join(destination);
+                if self.lazy_run_workspace_init {
+                    self.pending_run_workspaces
+                        .lock()
+                        .unwrap()
+                        .insert(destination, workspace_init.clone());
+                    continue;
+                }
+                let workspace_init = workspace_init.clone();
+                let mpb = self.mpb.clone();
+                let disable_progress_bar = self.progress_bar_disabled();
+                let progress_ascii = self.progress_ascii;
+                let on_workspace_ready = self.on_workspace_ready.clone();
+                let workspace_index = self.workspace_pool_len() + i;
+                let handle = std::thread::spawn(move || {
+                    let label = format!("init workspace_run {}", i);
+                    let sp = Self::add_copy_progress_bar2(
+                        disable_progress_bar,
+                        progress_ascii,
+                        &mpb,
+                        format!("{}: copying", label),
+                    );
+                    let initialized = workspace_init.init(
+                        &format!("workspace_exe_{}", i),
+                        &destination,
+                        &sp,
+                        &label,
+                    );
+                    if let (Ok(()), Some(on_workspace_ready)) = (&initialized, &on_workspace_ready)
+                    {
+                        on_workspace_ready(workspace_index);
+                    }
+                    initialized
+                });
+                run_handles.push(handle);
+            }
+        }
+
+        let init_errors: Vec<String> = build_handles
+            .into_iter()
+            .chain(run_handles)
+            .filter_map(|handle| handle.join().unwrap().err())
+            .collect();
+        if !init_errors.is_empty() {
+            return Err(init_errors.join("\n").into());
+        }
+
+        std::fs::create_dir_all(&self.temp_target_path_dir).unwrap();
+
+        Ok(())
+    }
+
+    /// `(path, last modified time)` of the directories in `dir`
+    fn folders<P: AsRef<Path>>(dir: P) -> Vec<(PathBuf, SystemTime)> {
+        if !dir.as_ref().exists() {
+            return vec![];
+        }
+        let mut folders = vec![];
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let metadata = entry.metadata().unwrap();
+            if metadata.is_dir() {
+                folders.push((entry.path(), metadata.modified().unwrap()));
+            }
+        }
+        folders
+    }
+
+    fn latest_folder<P: AsRef<Path>>(dir: P) -> Option<PathBuf> {
+        Self::folders(dir)
+            .into_iter()
+            .max_by_key(|(_, time)| *time)
+            .map(|(path, _)| path)
+    }
+
+    /// The build and run workspaces under `workspaces_path`, ordered by name and id,
+    /// without touching them
+    pub fn list_workspaces<P: AsRef<Path>>(workspaces_path: P) -> Vec<WorkspaceInfo> {
+        let mut workspaces: Vec<WorkspaceInfo> = Self::folders(workspaces_path)
+            .into_iter()
+            .filter(|(path, _)| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.rsplit_once('_'))
+                    .is_some_and(|(name, id)| {
+                        name.ends_with("workspace")
+                            || name.ends_with("workspace_exe")
+                            || name.ends_with("workspace_one")
+                    } && id.parse::<usize>().is_ok())
+            })
+            .map(|(path, modified)| WorkspaceInfo {
+                has_build_dir: path.join("build").is_dir(),
+                path,
+                modified,
+            })
+            .collect();
+        workspaces.sort_by_key(|workspace| {
+            let name = workspace.path.file_name().unwrap().to_string_lossy();
+            let (name, id) = name.rsplit_once('_').unwrap();
+            (name.to_string(), id.parse::<usize>().unwrap())
+        });
+        workspaces
+    }
+
+    /// Load autosave data (run_datas, compile_error_datas, processed_data_ids)
+    pub fn autosave_load(&mut self, start_time: String) -> RunOutput {
+        let autosave_dir = if start_time.is_empty() {
+            let latest = Self::latest_folder(&self.autosave_dir);
+            if latest.is_none() {
+                panic!("No autosave data found, consider running without --continue");
+            }
+            latest.unwrap()
+        } else {
+            self.autosave_dir.join(start_time)
+        };
+        println!("Loading autosave data from {:?}", autosave_dir);
+        self.continue_from_start_time = Some(
+            autosave_dir
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+        let datas_hash_file = autosave_dir.join(Self::DATAS_HASH_FILE);
+        if datas_hash_file.exists() {
+            let datas_hash: JsonValue =
+                serde_json::from_reader(std::fs::File::open(&datas_hash_file).unwrap()).unwrap();
+            self.continue_datas_hash = datas_hash["hash"].as_str().map(|hash| hash.to_string());
+        }
+        let datas = std::fs::read_dir(&autosave_dir).unwrap().fold(
+            (vec![], vec![], vec![]),
+            |(mut run_datas_array, mut compile_error_datas_array, mut processed_data_ids_array),
+             entry| {
+                let entry = entry.unwrap();
+                let path = entry.path();
+                if !path.is_dir() {
+                    return (
+                        run_datas_array,
+                        compile_error_datas_array,
+                        processed_data_ids_array,
+                    );
+                }
+                let run_datas: JsonValue =
+                    serde_json::from_reader(Autosaver::open(&path, "run_datas")).unwrap();
+                let compile_error_datas: Vec<JsonValue> =
+                    serde_json::from_reader(Autosaver::open(&path, "compile_error_datas")).unwrap();
+                let compile_error_datas = compile_error_datas
+                    .into_iter()
+                    .map(upgrade_compile_error_record);
+                let processed_data_ids: Vec<usize> =
+                    serde_json::from_reader(Autosaver::open(&path, "processed_data_ids")).unwrap();
+                run_datas_array.push(run_datas);
+                compile_error_datas_array.extend(compile_error_datas);
+                processed_data_ids_array.extend(processed_data_ids);
+                (
+                    run_datas_array,
+                    compile_error_datas_array,
+                    processed_data_ids_array,
+                )
+            },
+        );
+        self.continue_processed_data_ids = Some(datas.2.iter().copied().collect());
+        self.gather_data(datas.0, datas.1, datas.2).unwrap()
+    }
+
+    /// The autosaver of a new worker of the run started at `start_time`
+    fn autosaver(&self, start_time: &str) -> Autosaver {
+        Autosaver {
+            autosave_dir: self.autosave_dir.clone(),
+            start_time: start_time.to_string(),
+            worker_id: Uuid::new_v4(),
+            datas_hash: self.datas_hash.clone(),
+            compress: self.autosave_compress,
+            generations: self.autosave_generations,
+        }
+    }
+
+    /// Like `run()`, but run in a background thread and resolve when it is done
+    ///
+    /// Dropping the future before it resolves cancels the run like pressing Ctrl-C twice,
+    /// the in-flight datas are aborted and the progress is autosaved
+    #[cfg(feature = "tokio")]
+    pub fn run_async(
+        self,
+    ) -> impl std::future::Future<Output = Result<RunOutput, Box<dyn Error + Send + Sync>>> {
+        // abort the run when dropped before the run is done
+        struct CancelOnDrop(Option<StopFlags>);
+        impl CancelOnDrop {
+            fn disarm(&mut self) {
+                self.0 = None;
+            }
+        }
+        impl Drop for CancelOnDrop {
+            fn drop(&mut self) {
+                if let Some(stop_flags) = self.0.take() {
+                    stop_flags.stop.store(true, Ordering::Relaxed);
+                    stop_flags.abort.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        let mut cancel_on_drop = CancelOnDrop(Some(self.stop_flags.clone()));
+        let (result_sender, result_receiver) = tokio::sync::oneshot::channel();
+        std::thread::spawn(move || {
+            let result = self.run().map_err(|e| e.to_string());
+            let _ = result_sender.send(result);
+        });
+        async move {
+            let result = result_receiver.await;
+            cancel_on_drop.disarm();
+            match result {
+                Ok(result) => result.map_err(|e| e.into()),
+                Err(_) => Err("The run thread panicked".into()),
+            }
+        }
+    }
+
+    /// Where `dedupe_identical_renders` keeps the targets of every distinct render
+    fn dedupe_cache_root(&self) -> PathBuf {
+        self.workspaces_path
+            .join(self.workspace_name("dedupe_cache"))
+    }
+
+    /// Like `run()`, but return a `RunReport`, whose failed runs are in `run_errors`
+    /// with `split_run_results`
+    pub fn run_report(&self) -> Result<RunReport, Box<dyn Error>> {
+        let mut report = RunReport::from(self.run()?);
+        report.unprocessed = self.unprocessed_datas();
+        report.warning_datas = self.warning_datas();
+        if self.split_run_results {
+            report.split_run_errors();
+        }
+        Ok(report)
+    }
+
+    /// run the build system
+    pub fn run(&self) -> Result<RunOutput, Box<dyn Error>> {
+        let start_time = if let Some(start_time) = &self.continue_from_start_time {
+            start_time.clone()
+        } else {
+            Local::now().format("%Y-%m-%d_%H-%M-%S").to_string()
+        };
+        println!("Start from: {}", start_time);
+        if self.data_queue_receiver.is_none() {
+            return Err("Data queue receiver is not initialized".into());
+        }
+        if !is_command_installed(self.shell.program()) {
+            return Err(format!("{} is not installed", self.shell.program()).into());
+        }
+        if self.file_ready_wait.check == FileReadyCheck::Lsof && !is_command_installed("lsof") {
+            return Err("lsof is not installed, which may lead to strange problems that are difficult to reproduce, use `file_ready_check(FileReadyCheck::Stable)` instead".into());
+        }
+        let run_workers = self.out_of_place_run_workers()?;
+        if let Some(warning) = oversubscription_warning(
+            self.build_workers,
+            self.compile_jobs,
+            self.run_method,
+            run_workers,
+            available_cpus(),
+        ) {
+            eprintln!("Warning: {}", warning);
+        }
+        self.compile_error_classifier()?;
+        self.warning_detector()?;
+        self.template_output_path_registry()?;
+        self.bash_scripts()?;
+        self.progress_bar_style()
+            .map_err(|e| format!("Invalid progress style: {}", e))?;
+        self.dedupe_cache.lock().unwrap().clear();
+        self.warning_datas.lock().unwrap().clear();
+        self.pending_results.lock().unwrap().clear();
+        self.compile_error_count.store(0, Ordering::Relaxed);
+        self.worker_status.reset();
+        self.taken_datas.started.lock().unwrap().clear();
+        self.taken_datas.skipped.lock().unwrap().clear();
+        self.staged_targets.lock().unwrap().clear();
+        if let Some(event_socket_path) = &self.event_socket_path {
+            self.event_socket.connect(event_socket_path);
+        }
+        if self.dedupe_cache_root().exists() {
+            std::fs::remove_dir_all(self.dedupe_cache_root())?;
+        }
+        let mut build_handles = vec![];
+        let mut run_handles = Vec::new();
+        let (executable_queue_sender, executable_queue_receiver) = unbounded();
+        // the bars cover the whole datas, the resumed ones are already done
+        let data_size = self.data_queue_receiver.as_ref().unwrap().len() as u64;
+        let total = data_size + self.resumed_data_count;
+        let build_pb = self.add_progress_bar("Building", total, "All builds done");
+        let run_pb = if !matches!(self.run_method, RunMethod::No) && !self.dry_run {
+            if matches!(self.run_method, RunMethod::Exclusive(_)) {
+                self.add_progress_bar("Waiting to run (exclusive)", total, "All runs done")
+            } else {
+                self.add_progress_bar("Running", total, "All runs done")
+            }
+        } else {
+            ProgressBar::hidden()
+        };
+        // the first Ctrl-C stops taking new datas and lets in-flight ones finish,
+        // the second one aborts the in-flight datas too
+        let stop_flags = self.stop_flags.clone();
+        stop_flags.stop.store(false, Ordering::Relaxed);
+        stop_flags.abort.store(false, Ordering::Relaxed);
+        if !cfg!(test) {
+            let handler = ctrlc::set_handler({
+                let StopFlags {
+                    stop: stop_flag,
+                    abort: abort_flag,
+                } = stop_flags.clone();
+                move || {
+                    if !stop_flag.swap(true, Ordering::Relaxed) {
+                        println!(
+                            "Ctrl-C received, waiting for in-flight datas, press Ctrl-C again to abort..."
+                        );
+                    } else {
+                        println!("Ctrl-C received again, aborting...");
+                        abort_flag.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+            // the handler of an earlier `run()` in this process keeps serving its own datas
+            if let Err(e) = handler {
+                if !matches!(e, ctrlc::Error::MultipleHandlers) {
+                    panic!("Error setting Ctrl-C handler: {}", e);
+                }
+            }
+        }
+        #[cfg(unix)]
+        let _pause_signal_handlers = PauseSignalHandlers::install();
+        build_pb.tick();
+        run_pb.tick();
+        let workspace_pool = WorkspacePool::new((0..self.workspace_pool_len()).map(|i| {
+            self.workspaces_path
+                .join(self.workspace_name(&format!("workspace_{}", i)))
+        }));
+        let spawn_build_workers = || {
+            for _ in 0..self.build_workers {
+                let build_handle = self.build_worker(
+                    workspace_pool.clone(),
+                    executable_queue_sender.clone(),
+                    build_pb.clone(),
+                    run_pb.clone(),
+                    stop_flags.clone(),
+                    start_time.clone(),
+                );
+                build_handles.push(build_handle);
+            }
+            drop(executable_queue_sender);
+        };
+        let spawn_run_workers = || {
+            for i in 0..run_workers {
+                let workspace_path = self
+                    .workspaces_path
+                    .join(self.workspace_name(&format!("workspace_exe_{}", i)));
+                let run_handle = self.run_worker(
+                    workspace_path,
+                    executable_queue_receiver.clone(),
+                    self.temp_target_path_dir.clone(),
+                    run_pb.clone(),
+                    stop_flags.clone(),
+                    start_time.clone(),
+                );
+                run_handles.push(run_handle);
+            }
+            drop(executable_queue_receiver);
+        };
+        let gather_build_handlers = |build_handles: Vec<JoinHandle<RunOutput>>| {
+            build_handles.into_iter().fold(
+                (vec![], vec![], vec![]),
+                |(
+                    mut run_datas_array,
+                    mut compile_error_datas_array,
+                    mut processed_data_ids_array,
+                ),
+                 handle| {
+                    let (run_datas, compile_error_datas, processed_data_ids) =
+                        handle.join().unwrap();
+                    run_datas_array.push(run_datas);
+                    compile_error_datas_array.extend(compile_error_datas);
+                    processed_data_ids_array.extend(processed_data_ids);
+                    (
+                        run_datas_array,
+                        compile_error_datas_array,
+                        processed_data_ids_array,
+                    )
+                },
+            )
+        };
+        let gather_run_handlers = |run_handles: Vec<JoinHandle<(JsonValue, Vec<usize>)>>| {
+            run_handles.into_iter().fold(
+                (vec![], vec![]),
+                |(mut run_datas_array, mut processed_data_ids_array), handle| {
+                    let (run_datas, processed_data_ids) = handle.join().unwrap();
+                    run_datas_array.push(run_datas);
+                    processed_data_ids_array.extend(processed_data_ids);
+                    (run_datas_array, processed_data_ids_array)
+                },
+            )
+        };
+        spawn_build_workers();
+        drop(build_pb);
+        let run_output = match self.run_method {
+            RunMethod::No => {
+                let (run_datas, compile_error_datas, processed_data_ids) =
+                    gather_build_handlers(build_handles);
+                if !self.dry_run {
+                    self.write_manifest()?;
+                }
+                self.gather_data(run_datas, compile_error_datas, processed_data_ids)
+            }
+            RunMethod::InPlace => {
+                let (run_datas, compile_error_datas, processed_data_ids) =
+                    gather_build_handlers(build_handles);
+                self.gather_data(run_datas, compile_error_datas, processed_data_ids)
+            }
+            RunMethod::Exclusive(_) => {
+                // the build workers only report the cached results
+                let (cached_run_datas, compile_error_datas, mut processed_data_ids) =
+                    gather_build_handlers(build_handles);
+                run_pb.set_message("Running");
+                spawn_run_workers();
+                let (mut run_datas, run_processed_data_ids) = gather_run_handlers(run_handles);
+                run_datas.extend(cached_run_datas);
+                processed_data_ids.extend(run_processed_data_ids);
+                self.gather_data(run_datas, compile_error_datas, processed_data_ids)
+            }
+            RunMethod::OutOfPlace(_) | RunMethod::Interleaved(_) => {
+                spawn_run_workers();
+                let (cached_run_datas, compile_error_datas, mut processed_data_ids) =
+                    gather_build_handlers(build_handles);
+                let (mut run_datas, run_processed_data_ids) = gather_run_handlers(run_handles);
+                run_datas.extend(cached_run_datas);
+                processed_data_ids.extend(run_processed_data_ids);
+                self.gather_data(run_datas, compile_error_datas, processed_data_ids)
+            }
+        }?;
+        self.event_socket.disconnect();
+        self.worker_status.settle();
+        self.collect_unprocessed_datas(&run_output.2);
+        Ok(run_output)
+    }
+
+    /// Merge the targets staged by this `run()` into `MANIFEST_FILE`, e.g. with the ones staged
+    /// before a `continue_from`, sorted by id
+    fn write_manifest(&self) -> Result<(), Box<dyn Error>> {
+        let manifest_path = self.temp_target_path_dir.join(Self::MANIFEST_FILE);
+        let mut entries: Vec<JsonValue> = if manifest_path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&manifest_path)?)?
+        } else {
+            vec![]
+        };
+        let staged_targets = std::mem::take(&mut *self.staged_targets.lock().unwrap());
+        entries.retain(|entry| {
+            !staged_targets
+                .iter()
+                .any(|staged| staged["id"] == entry["id"])
+        });
+        entries.extend(staged_targets);
+        entries.sort_by_key(|entry| entry["id"].as_u64());
+        std::fs::write(&manifest_path, serde_json::to_string_pretty(&entries)?)?;
+        Ok(())
+    }
+
+    /// Run the targets staged by an earlier `run()` with `RunMethod::No` without compiling them,
+    /// e.g. to compile on one machine and benchmark on another
+    ///
+    /// `manifest` is the `MANIFEST_FILE` in the targets dir of that run, its artifacts are copied,
+    /// so they can be run again. It needs an out-of-place run method and `init_workspace()`,
+    /// the datas are those of the manifest and the compile errors are always empty
+    pub fn run_targets<P: AsRef<Path>>(&self, manifest: P) -> Result<RunOutput, Box<dyn Error>> {
+        let manifest = manifest.as_ref();
+        let run_workers = self.out_of_place_run_workers()?;
+        if run_workers == 0 {
+            return Err("run_targets() needs an out-of-place run method".into());
+        }
+        self.progress_bar_style()
+            .map_err(|e| format!("Invalid progress style: {}", e))?;
+        let entries: Vec<JsonValue> = serde_json::from_str(&std::fs::read_to_string(manifest)?)
+            .map_err(|e| format!("Invalid manifest {:?}: {}", manifest, e))?;
+        let artifacts_dir = manifest.parent().unwrap_or(Path::new(""));
+        let targets_dir = self
+            .workspaces_path
+            .join(self.workspace_name("staged_targets"));
+        if targets_dir.exists() {
+            std::fs::remove_dir_all(&targets_dir)?;
+        }
+        std::fs::create_dir_all(&targets_dir)?;
+        let (executable_queue_sender, executable_queue_receiver) = unbounded();
+        for entry in entries.iter() {
+            let invalid = || format!("Invalid manifest entry: {}", entry);
+            let id = entry["id"].as_u64().ok_or_else(invalid)? as usize;
+            let target_files: Vec<PathBuf> =
+                serde_json::from_value(entry["target_files"].clone()).map_err(|_| invalid())?;
+            let artifacts: Vec<String> =
+                serde_json::from_value(entry["artifacts"].clone()).map_err(|_| invalid())?;
+            for (target_file, artifact) in target_files.iter().zip(artifacts.iter()) {
+                // the run workers take the targets by the name the build workers give them
+                let staged = format!(
+                    "{}_{}",
+                    target_file.file_name().unwrap().to_string_lossy(),
+                    id
+                );
+                copy_target(&artifacts_dir.join(artifact), &targets_dir.join(staged))?;
+            }
+            let compile_ms = entry["compile_ms"].as_u64().unwrap_or(0);
+            executable_queue_sender.send((id, entry["data"].clone(), compile_ms, target_files))?;
+        }
+        drop(executable_queue_sender);
+        let start_time = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+        let stop_flags = self.stop_flags.clone();
+        stop_flags.stop.store(false, Ordering::Relaxed);
+        stop_flags.abort.store(false, Ordering::Relaxed);
+        self.worker_status.reset();
+        let run_pb = self.add_progress_bar("Running", entries.len() as u64, "All runs done");
+        let run_handles: Vec<_> = (0..run_workers)
+            .map(|i| {
+                self.run_worker(
+                    self.workspaces_path
+                        .join(self.workspace_name(&format!("workspace_exe_{}", i))),
+                    executable_queue_receiver.clone(),
+                    targets_dir.clone(),
+                    run_pb.clone(),
+                    stop_flags.clone(),
+                    start_time.clone(),
+                )
+            })
+            .collect();
+        let mut run_datas = vec![];
+        let mut processed_data_ids = vec![];
+        for handle in run_handles {
+            let (run_data, ids) = handle.join().unwrap();
+            run_datas.push(run_data);
+            processed_data_ids.extend(ids);
+        }
+        self.worker_status.settle();
+        self.gather_data(run_datas, vec![], processed_data_ids)
+    }
+
+    /// Render the templates against `data` without any workspace, e.g. to debug the templates
+    ///
+    /// Returns the template outputs (relative to a workspace) with their rendered contents,
+    /// followed by `<compile script>` and `<run script>` when `templated_scripts` is set,
+    /// `<run stdin>` when `run_stdin_template` is set, and `<run command>` (its elements joined by
+    /// newlines) when `run_args_template` replaces the run script
+    pub fn render_data(&self, data: &JsonValue) -> Result<Vec<(PathBuf, String)>, Box<dyn Error>> {
+        self.bash_scripts()?;
+        let (handlebars, template_outputs) = self.register_templates(Path::new(""));
+        let template_outputs = place_template_outputs(
+            &template_outputs,
+            self.template_output_path_registry()?.as_ref(),
+            data,
+        )
+        .map_err(|(template_output_path, e)| {
+            format!("Failed to render {:?}: {}", template_output_path, e)
+        })?;
+        let mut rendered = Vec::new();
+        for (template_name, template_output_path) in template_outputs {
+            let content = handlebars
+                .render(&template_name, data)
+                .map_err(|e| format!("Failed to render {:?}: {}", template_output_path, e))?;
+            rendered.push((template_output_path, content));
+        }
+        if self.templated_scripts {
+            let script_templates = self.script_templates();
+            let scripts = script_templates
+                .for_data(data)
+                .map_err(|e| format!("Failed to render the scripts: {}", e))?;
+            rendered.push((PathBuf::from("<compile script>"), scripts.compile.clone()));
+            rendered.push((PathBuf::from("<run script>"), scripts.run.clone()));
+        }
+        if self.run_stdin_template.is_some() {
+            let run_stdin = self
+                .script_templates()
+                .for_data(data)
+                .map_err(|e| format!("Failed to render the run stdin: {}", e))?
+                .run_stdin
+                .clone()
+                .unwrap();
+            rendered.push((PathBuf::from("<run stdin>"), run_stdin));
+        }
+        if self.run_command_template().is_some() {
+            let run_command = self
+                .script_templates()
+                .for_data(data)
+                .map_err(|e| format!("Failed to render the run arguments: {}", e))?
+                .run_command
+                .clone()
+                .unwrap();
+            rendered.push((PathBuf::from("<run command>"), run_command.join("\n")));
+        }
+        Ok(rendered)
+    }
+
+    /// Render, compile and run a single `data` in the current thread, returns the same result
+    /// as an item of the run datas returned by `run()`, `Err` if it fails to init, render or compile
+    ///
+    /// A fresh workspace is initialized under `workspaces_path` every time,
+    /// so neither `init_workspace()` nor `set_datas()` is needed
+    pub fn run_one(&self, data: JsonValue) -> Result<JsonValue, Box<dyn Error>> {
+        self.bash_scripts()?;
+        let workspace_path = self
+            .workspaces_path
+            .join(self.workspace_name("workspace_one_0"));
+        if workspace_path.exists() {
+            std::fs::remove_dir_all(&workspace_path)?;
+        }
+        std::fs::create_dir_all(&self.workspaces_path)?;
+        let copy_excludes = self.workspace_copy_excludes();
+        let mut project_path = self.project_path.clone();
+        if std::fs::canonicalize(&self.workspaces_path)?
+            .starts_with(std::fs::canonicalize(&self.project_path)?)
+        {
+            project_path = tempdir()?.into_path();
+            copy_dir_with_ignore(
+                &self.project_path,
+                &project_path,
+                &copy_excludes,
+                &ProgressBar::hidden(),
+            )?;
+        }
+        copy_dir_with_strategy(
+            &project_path,
+            &workspace_path,
+            self.copy_strategy,
+            &self.copy_independent_paths(),
+            &copy_excludes,
+            &ProgressBar::hidden(),
+        )?;
+        if project_path != self.project_path {
+            std::fs::remove_dir_all(&project_path)?;
+        }
+        let (output, failed_step) = init_script_output(
+            self.shell,
+            &self.init_bash_script,
+            &self.init_bash_script_steps,
+            &self.envs,
+            &workspace_path,
+        )?;
+        if !output.status.success() {
+            return Err(format!(
+                "Init bash script failed{}: {:?}",
+                failed_step_message(&self.init_bash_script_steps, failed_step),
+                output
+            )
+            .into());
+        }
+
+        let (handlebars, template_output_files) = self.register_templates(Path::new(""));
+        let template_output_files = place_template_outputs(
+            &template_output_files,
+            self.template_output_path_registry()?.as_ref(),
+            &data,
+        )
+        .and_then(|template_output_files| {
+            let template_outputs: Vec<(String, PathBuf)> = template_output_files
+                .iter()
+                .map(|(template_name, template_output_file)| {
+                    (
+                        template_name.clone(),
+                        workspace_path.join(template_output_file),
+                    )
+                })
+                .collect();
+            render_templates(&handlebars, &template_outputs, &data)?;
+            Ok(template_output_files)
+        })
+        .map_err(|(template_output_path, e)| {
+            format!("Failed to render {:?}: {}", template_output_path, e)
+        })?;
+        let script_templates = self.script_templates();
+        let scripts = script_templates
+            .for_data_in(&workspace_path, &data)
+            .map_err(|e| format!("Failed to render the scripts: {}", e))?;
+
+        let abort_flag = Arc::new(AtomicBool::new(false));
+        let mut compile_context = self.compile_context();
+        compile_context
+            .compile_script_steps
+            .clone_from(&scripts.compile_steps);
+        if self.enable_cppflags {
+            compile_context.cppflags = Some(data_to_cppflags(&data, self.parabuild_define));
+        }
+        compile_context.template_output = template_output_files
+            .first()
+            .map(|(_, template_output_file)| template_output_file.clone());
+        let compile_start = Instant::now();
+        let (compile_success, compile_data) = (self.compile_func)(
+            &workspace_path,
+            &scripts.compile,
+            &data,
+            &abort_flag,
+            &compile_context,
+        )?;
+        if !compile_success {
+            return Err(format!("Compilation script failed with output: {}", compile_data).into());
+        }
+
+        let mut context = self.run_context();
+        context.run_script_steps.clone_from(&scripts.run_steps);
+        context.run_stdin.clone_from(&scripts.run_stdin);
+        context.run_command.clone_from(&scripts.run_command);
+        context.compile_ms = Some(compile_start.elapsed().as_millis() as u64);
+        let mut run_data = JsonValue::Null;
+        (self.run_func_data)(
+            &std::fs::canonicalize(&workspace_path)?,
+            &scripts.run,
+            &data,
+            &mut run_data,
+            &abort_flag,
+            &context,
+        )
+    }
+
+    /// Register the template files, returns the registry and `(template name, template output path)`
+    /// under `workspace_path`
+    fn register_templates(
+        &self,
+        workspace_path: &Path,
+    ) -> (Handlebars<'static>, Vec<(String, PathBuf)>) {
+        let mut handlebars = Handlebars::new();
+        if self.template_files.is_empty() {
+            return (handlebars, Vec::new());
+        }
+        handlebars.set_strict_mode(self.strict_templates);
+        register_helpers(&mut handlebars, &self.project_path);
+        let mut template_outputs = Vec::new();
+        for (template_id, template_file) in self.template_files.iter().enumerate() {
+            let template_path = self.project_path.join(template_file);
+            if template_path.exists() && template_path.is_file() {
+                let template_name = format!("tpl_{}", template_id);
+                let mut template = std::fs::read_to_string(&template_path).unwrap();
+                if let Some((open, close)) = &self.template_delimiters {
+                    template = translate_delimiters(&template, open, close);
+                }
+                handlebars
+                    .register_template_string(&template_name, template)
+                    .unwrap();
+                let template_output_file = if self.in_place_template {
+                    template_file.clone()
+                } else {
+                    template_file.with_extension("")
+                };
+                template_outputs.push((template_name, workspace_path.join(template_output_file)));
+            }
+        }
+        (handlebars, template_outputs)
+    }
+
+    /// The compile and run scripts, with a registry to render them when `templated_scripts`,
+    /// `run_stdin_template` or `run_args_template` is set
+    fn script_templates(&self) -> ScriptTemplates {
+        let run_command = self.run_command_template();
+        let templated =
+            self.templated_scripts || self.run_stdin_template.is_some() || run_command.is_some();
+        let handlebars = templated.then(|| {
+            let mut handlebars = Handlebars::new();
+            handlebars.set_strict_mode(self.strict_templates);
+            // scripts are not HTML, e.g. `&&` must stay as is
+            handlebars.register_escape_fn(handlebars::no_escape);
+            register_helpers(&mut handlebars, &self.project_path);
+            handlebars
+        });
+        // validated by `run()`, `run_one()` and `render_data()`
+        let (compile, run) = self.bash_scripts().unwrap();
+        ScriptTemplates {
+            scripts: Scripts {
+                compile,
+                compile_steps: self.compile_bash_script_steps.clone(),
+                run,
+                run_steps: self.run_bash_script_steps.clone(),
+                run_stdin: self.run_stdin_template.clone(),
+                run_command,
+            },
+            compile_in_project: self.compile_script_in_project.clone(),
+            run_in_project: self.run_script_in_project.clone(),
+            handlebars,
+            templated_scripts: self.templated_scripts,
+        }
+    }
+
+    fn compile_context(&self) -> CompileContext {
+        let mut envs = self.envs.clone();
+        if let Some(compiler_cache_dir) = &self.compiler_cache_dir {
+            let absolute = |path: &Path| {
+                if path.is_absolute() {
+                    path.to_path_buf()
+                } else {
+                    env::current_dir().unwrap().join(path)
+                }
+            };
+            let compiler_cache_dir = absolute(compiler_cache_dir).to_string_lossy().into_owned();
+            for key in ["CCACHE_DIR", "SCCACHE_DIR"] {
+                envs.entry(key.to_string())
+                    .or_insert_with(|| compiler_cache_dir.clone());
+            }
+            envs.entry("CCACHE_BASEDIR".to_string()).or_insert_with(|| {
+                absolute(&self.workspaces_path)
+                    .to_string_lossy()
+                    .into_owned()
+            });
+        }
+        CompileContext {
+            compile_timeout: self.compile_timeout,
+            shell: self.shell,
+            envs,
+            data_env_prefix: self.data_env_prefix.clone(),
+            compile_script_steps: self.compile_bash_script_steps.clone(),
+            cppflags: None,
+            compile_jobs: self.compile_jobs,
+            template_output: None,
+            compile_cwd: self.compile_cwd.clone(),
+        }
+    }
+
+    fn run_context(&self) -> RunContext {
+        RunContext {
+            gpu_devices: self.gpu_devices.clone(),
+            cuda_device_mode: self.cuda_device_mode,
+            run_cpus: self
+                .pin_run_workers
+                .then(|| self.run_cpus.clone().unwrap_or_else(allowed_cpus)),
+            run_timeout: self.run_timeout,
+            run_retries: self.run_retries,
+            run_retry_backoff: self.run_retry_backoff,
+            shell: self.shell,
+            envs: self.envs.clone(),
+            data_env_prefix: self.data_env_prefix.clone(),
+            record_timestamps: self.record_timestamps,
+            measure_memory: self.measure_memory,
+            run_script_steps: self.run_bash_script_steps.clone(),
+            run_cwd: self.run_cwd.clone().or_else(|| self.build_dir.clone()),
+            build_dir: self.build_dir.clone(),
+            repeat_runs: self.repeat_runs,
+            metric_extractor: self.metric_extractor.clone(),
+            verify_func: self.verify_func.clone(),
+            extract_json: self.extract_json.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn build_worker(
+        &self,
+        workspace_pool: WorkspacePool,
+        executable_queue_sender: Sender<Executable>,
+        build_pb: ProgressBar,
+        run_pb: ProgressBar,
+        stop_flags: StopFlags,
+        start_time: String,
+    ) -> std::thread::JoinHandle<RunOutput> {
+        let StopFlags {
+            stop: stop_flag,
+            abort: abort_flag,
+        } = stop_flags;
+        let target_files = self.target_paths();
+        let script_templates = self.script_templates();
+        let init_bash_script = self.init_bash_script.clone();
+        let init_bash_script_steps = self.init_bash_script_steps.clone();
+        let reset_after_failures = self.reset_after_failures;
+        let max_compile_errors = self.max_compile_errors;
+        let compile_error_count = self.compile_error_count.clone();
+        let dedupe_identical_renders = self.dedupe_identical_renders;
+        let trust_incremental = self.trust_incremental;
+        let dedupe_cache = self.dedupe_cache.clone();
+        let result_cache = self.result_cache_of_run();
+        let exclusive_gate = matches!(self.run_method, RunMethod::Interleaved(_))
+            .then(|| self.exclusive_gate.clone());
+        let dedupe_cache_root = self.dedupe_cache_root();
+        let cancelled_datas = self.cancelled_datas.clone();
+        let taken_datas = self.taken_datas.clone();
+        let temp_target_path_dir = self.temp_target_path_dir.clone();
+        let staged_targets = self.staged_targets.clone();
+        let data_queue_receiver = self.data_queue_receiver.as_ref().unwrap().clone();
+        let run_method = self.run_method;
+        let run_func = self.run_func_data;
+        let compilation_error_handling_method = self.compilation_error_handling_method;
+
+        // template outputs relative to the workspace, joined with the claimed one for each data
+        let (handlebars, template_output_files) = self.register_templates(Path::new(""));
+        let mut run_data = JsonValue::Null;
+        let mut compile_error_datas = Vec::new();
+        let warmup_runs = self.warmup_runs;
+        let compile_func = self.compile_func;
+        let mut compile_context = self.compile_context();
+        let dry_run = self.dry_run;
+        let save_rendered_on_error = self.save_rendered_on_error.clone();
+        // validated by `run()`
+        let compile_error_classifier = self.compile_error_classifier().unwrap();
+        let warning_detector = self.warning_detector().unwrap();
+        let template_output_path = self.template_output_path_registry().unwrap();
+        let warning_datas = self.warning_datas.clone();
+        let tee_build_logs = self.tee_build_logs;
+        let enable_cppflags = self.enable_cppflags;
+        let parabuild_define = self.parabuild_define;
+        let disable_progress_bar = self.progress_bar_disabled();
+        let progress_ascii = self.progress_ascii;
+        let mpb = self.mpb.clone();
+        let autosaver = self.autosaver(&start_time);
+        let autosave_interval = self.autosave_interval;
+        let stream_output_writer = self.stream_output_writer.clone();
+        let progress_callback = self.progress_sink();
+        let worker_status = self.worker_status.clone();
+        let pause_flag = self.pause_flag.clone();
+        let mut context = self.run_context();
+        std::thread::spawn(move || {
+            let mut processed_data_ids = Vec::new();
+            let sp = Self::add_spinner2(
+                disable_progress_bar || !matches!(run_method, RunMethod::InPlace),
+                progress_ascii,
+                &mpb,
+                serde_json::to_string_pretty(&JsonValue::Null).unwrap(),
+            );
+            let mut autosave_last_time = Instant::now();
+            for (i, data) in data_queue_receiver.iter() {
+                pause_flag.wait(&stop_flag);
+                if stop_flag.load(Ordering::Relaxed) {
+                    // the data is not processed and will be resumed by `continue_from`
+                    taken_datas.skip(i, data, UnprocessedReason::NotStarted);
+                    autosaver.save(&run_data, &compile_error_datas, &processed_data_ids);
+                    break;
+                }
+                if cancelled_datas.contains(i) {
+                    taken_datas.skip(i, data, UnprocessedReason::Cancelled);
+                    build_pb.dec_length(1);
+                    run_pb.dec_length(1);
+                    continue;
+                }
+                taken_datas.started.lock().unwrap().push((i, data.clone()));
+                let mut workspace_path = workspace_pool.claim();
+                let (data_template_output_files, placed) = match place_template_outputs(
+                    &template_output_files,
+                    template_output_path.as_ref(),
+                    &data,
+                ) {
+                    Ok(files) => (Cow::Owned(files), Ok(())),
+                    Err(e) => (Cow::Borrowed(&template_output_files), Err(e)),
+                };
+                let template_outputs: Vec<(String, PathBuf)> = data_template_output_files
+                    .iter()
+                    .map(|(template_name, template_output_file)| {
+                        (
+                            template_name.clone(),
+                            workspace_path.join(template_output_file),
+                        )
+                    })
+                    .collect();
+                emit_progress_event(
+                    &progress_callback,
+                    &worker_status,
+                    ProgressEvent::BuildStarted { id: i },
+                );
+                let mut render_error = None;
+                let rendered = placed
+                    .and_then(|()| render_templates(&handlebars, &template_outputs, &data))
+                    .and_then(|()| {
+                        script_templates
+                            .for_data_in(&workspace_path, &data)
+                            .map_err(|e| (PathBuf::from("<scripts>"), e))
+                    });
+                let scripts = match rendered {
+                    Ok(scripts) => scripts,
+                    Err((template_output_path, e)) => {
+                        if !dry_run {
+                            emit_progress_event(
+                                &progress_callback,
+                                &worker_status,
+                                ProgressEvent::BuildFinished {
+                                    id: i,
+                                    success: false,
+                                },
+                            );
+                            processed_data_ids.push(i);
+                            build_pb.inc(1);
+                            if !matches!(run_method, RunMethod::No) {
+                                run_pb.inc(1);
+                            }
+                            if compilation_error_handling_method
+                                != CompliationErrorHandlingMethod::Panic
+                            {
+                                count_compile_error(
+                                    &compile_error_count,
+                                    max_compile_errors,
+                                    &stop_flag,
+                                    &mpb,
+                                );
+                            }
+                            match compilation_error_handling_method {
+                                CompliationErrorHandlingMethod::Collect => {
+                                    let mut record = compile_error_record(
+                                        &data,
+                                        Some(Parabuilder::RENDER_ERROR_CATEGORY),
+                                    );
+                                    record["render_error"] = json!(e.to_string());
+                                    compile_error_datas.push(record);
+                                    continue;
+                                }
+                                CompliationErrorHandlingMethod::Ignore => {
+                                    continue;
+                                }
+                                CompliationErrorHandlingMethod::Panic => {
+                                    panic!("Failed to render {:?}: {}", template_output_path, e);
+                                }
+                            }
+                        }
+                        render_error = Some(e.to_string());
+                        Cow::Borrowed(&script_templates.scripts)
+                    }
+                };
+                if dry_run {
+                    // nothing is compiled, the build finishes with the render
+                    emit_progress_event(
+                        &progress_callback,
+                        &worker_status,
+                        ProgressEvent::BuildFinished {
+                            id: i,
+                            success: render_error.is_none(),
+                        },
+                    );
+                    if let Some(render_error) = render_error {
+                        let mut record =
+                            compile_error_record(&data, Some(Parabuilder::RENDER_ERROR_CATEGORY));
+                        record["render_error"] = json!(render_error);
+                        compile_error_datas.push(record);
+                    }
+                    processed_data_ids.push(i);
+                    build_pb.inc(1);
+                    continue;
+                }
+                compile_context.cppflags = if enable_cppflags {
+                    Some(data_to_cppflags(&data, parabuild_define))
+                } else {
+                    None
+                };
+                compile_context.template_output = data_template_output_files
+                    .first()
+                    .map(|(_, template_output_file)| template_output_file.clone());
+                compile_context
+                    .compile_script_steps
+                    .clone_from(&scripts.compile_steps);
+                let compile_start = Instant::now();
+                let render_hash =
+                    if dedupe_identical_renders || trust_incremental || result_cache.is_some() {
+                        render_hash(&template_outputs, &scripts.compile, &compile_context, &data)
+                            .ok()
+                    } else {
+                        None
+                    };
+                if let (Some(result_cache), Some(render_hash)) = (&result_cache, &render_hash) {
+                    let result_hash = result_hash(render_hash, &data, &scripts);
+                    if let Some(mut result) = result_cache.get(&result_hash) {
+                        // recorded by a prior run, nothing to compile or run
+                        result["id"] = json!(i);
+                        result["cached"] = JsonValue::Bool(true);
+                        emit_progress_event(
+                            &progress_callback,
+                            &worker_status,
+                            ProgressEvent::BuildFinished {
+                                id: i,
+                                success: true,
+                            },
+                        );
+                        build_pb.inc(1);
+                        write_stream_output(&stream_output_writer, &result);
+                        emit_progress_event(
+                            &progress_callback,
+                            &worker_status,
+                            ProgressEvent::RunFinished {
+                                id: i,
+                                status: result["status"].as_i64(),
+                            },
+                        );
+                        run_pb.inc(1);
+                        run_func_data_post_(result, &mut run_data).unwrap();
+                        processed_data_ids.push(i);
+                        continue;
+                    }
+                    result_cache.expect(i, result_hash);
+                }
+                // the targets of the same render are still in the workspace
+                let up_to_date_target_files = render_hash
+                    .as_ref()
+                    .filter(|render_hash| {
+                        trust_incremental
+                            && workspace_path.built_render_hash.as_ref() == Some(*render_hash)
+                    })
+                    .and_then(|_| resolve_target_files(&workspace_path, &target_files).ok());
+                let dedupe_cache_entry = render_hash
+                    .as_ref()
+                    .filter(|_| dedupe_identical_renders && up_to_date_target_files.is_none())
+                    .and_then(|render_hash| dedupe_cache.lock().unwrap().get(render_hash).cloned());
+                let (compile_success, compile_data, resolved_target_files) =
+                    if let Some(up_to_date_target_files) = up_to_date_target_files {
+                        (
+                            true,
+                            json!({"status": 0, "up_to_date": true}),
+                            up_to_date_target_files,
+                        )
+                    } else if let Some((dedupe_cache_dir, cached_target_files)) =
+                        &dedupe_cache_entry
+                    {
+                        // reuse the targets of an identical render
+                        match cached_target_files.iter().try_for_each(|target_file| {
+                            copy_target(
+                                &dedupe_cache_dir.join(target_file.file_name().unwrap()),
+                                &workspace_path.join(target_file),
+                            )
+                        }) {
+                            Ok(()) => (
+                                true,
+                                json!({"status": 0, "deduped": true}),
+                                cached_target_files.clone(),
+                            ),
+                            Err(e) => (false, json!({"error": e.to_string()}), vec![]),
+                        }
+                    } else {
+                        let exclusive_guard = exclusive_gate.as_ref().map(|gate| gate.build());
+                        let (compile_success, compile_data) = compile_func(
+                            &workspace_path,
+                            &scripts.compile,
+                            &data,
+                            &abort_flag,
+                            &compile_context,
+                        )
+                        .unwrap_or_else(|e| (false, json!({"error": e.to_string()})));
+                        drop(exclusive_guard);
+                        if compile_success {
+                            match resolve_target_files(&workspace_path, &target_files) {
+                                // the targets are copied out of the workspace unless run in place
+                                Ok(resolved) => match resolved
+                                    .iter()
+                                    .map(|target_file| workspace_path.join(target_file))
+                                    .filter(|_| !matches!(run_method, RunMethod::InPlace))
+                                    .find(|target_path| !target_path.exists())
+                                {
+                                    // e.g. the compile script built another target
+                                    Some(missing_target_path) => {
+                                        let error = format!(
+                                        "Target file {:?} is missing after a successful compile",
+                                        missing_target_path
+                                    );
+                                        let mut compile_data = compile_data;
+                                        if compile_data.is_object() {
+                                            compile_data["error"] = json!(error);
+                                        } else {
+                                            compile_data = json!({"error": error});
+                                        }
+                                        (false, compile_data, vec![])
+                                    }
+                                    None => (true, compile_data, resolved),
+                                },
+                                Err(e) => (false, json!({"error": e.to_string()}), vec![]),
+                            }
+                        } else {
+                            (false, compile_data, vec![])
+                        }
+                    };
+                workspace_path.built_render_hash = render_hash
+                    .clone()
+                    .filter(|_| trust_incremental && compile_success);
+                if let (Some(render_hash), None, true, true) = (
+                    &render_hash,
+                    &dedupe_cache_entry,
+                    compile_success,
+                    dedupe_identical_renders,
+                ) {
+                    let mut dedupe_cache = dedupe_cache.lock().unwrap();
+                    if !dedupe_cache.contains_key(render_hash) {
+                        let dir = dedupe_cache_root.join(render_hash);
+                        let cached = resolved_target_files.iter().try_for_each(|target_file| {
+                            std::fs::create_dir_all(&dir)?;
+                            copy_target(
+                                &workspace_path.join(target_file),
+                                &dir.join(target_file.file_name().unwrap()),
+                            )
+                        });
+                        if cached.is_ok() {
+                            dedupe_cache
+                                .insert(render_hash.clone(), (dir, resolved_target_files.clone()));
+                        }
+                    }
+                }
+                let compile_timed_out = compile_data["timed_out"] == true;
+                let compile_ms = compile_start.elapsed().as_millis() as u64;
+                if tee_build_logs {
+                    append_build_log(&workspace_path, i, &data, &compile_data);
+                }
+                if let (true, Some(warning_detector)) = (compile_success, &warning_detector) {
+                    let warnings: Vec<&str> = compile_data["stderr"]
+                        .as_str()
+                        .unwrap_or("")
+                        .lines()
+                        .filter(|line| warning_detector.is_match(line))
+                        .collect();
+                    if !warnings.is_empty() {
+                        warning_datas.lock().unwrap().push(json!({
+                            "id": i,
+                            "data": data,
+                            "warnings": warnings,
+                        }));
+                    }
+                }
+                emit_progress_event(
+                    &progress_callback,
+                    &worker_status,
+                    ProgressEvent::BuildFinished {
+                        id: i,
+                        success: compile_success,
+                    },
+                );
+                build_pb.inc(1);
+                if compile_success {
+                    workspace_path.consecutive_failures = 0;
+                } else if !abort_flag.load(Ordering::Relaxed) {
+                    workspace_path.consecutive_failures += 1;
+                    if reset_after_failures > 0
+                        && workspace_path.consecutive_failures >= reset_after_failures
+                    {
+                        mpb.suspend(|| {
+                            eprintln!(
+                                "{} compile failures in a row in {:?}, resetting it",
+                                workspace_path.consecutive_failures, *workspace_path
+                            )
+                        });
+                        if let Err(e) = init_script_output(
+                            compile_context.shell,
+                            &init_bash_script,
+                            &init_bash_script_steps,
+                            &compile_context.envs,
+                            &workspace_path,
+                        ) {
+                            eprintln!("Failed to reset {:?}: {}", *workspace_path, e);
+                        }
+                        workspace_path.consecutive_failures = 0;
+                    }
+                }
+                if !compile_success {
+                    if abort_flag.load(Ordering::Relaxed) {
+                        // current data should be saved, ignore here
+                    } else {
+                        processed_data_ids.push(i);
+                        if compilation_error_handling_method
+                            == CompliationErrorHandlingMethod::Panic
+                        {
+                            if compile_timed_out {
+                                panic!("Compilation script timed out in data: {:?}", data);
+                            } else {
+                                panic!(
+                                    "Compilation script failed in data: {:?} with output: {}",
+                                    data, compile_data
+                                );
+                            }
+                        } else {
+                            if !matches!(run_method, RunMethod::No) {
+                                run_pb.inc(1);
+                            }
+                            count_compile_error(
+                                &compile_error_count,
+                                max_compile_errors,
+                                &stop_flag,
+                                &mpb,
+                            );
+                            match compilation_error_handling_method {
+                                CompliationErrorHandlingMethod::Collect => {
+                                    if let Some(dir) = &save_rendered_on_error {
+                                        let log = match &compile_data["stderr"] {
+                                            JsonValue::String(stderr) => stderr.clone(),
+                                            _ => compile_data.to_string(),
+                                        };
+                                        save_rendered(dir, i, &template_outputs, &log);
+                                    }
+                                    let category = if compile_timed_out {
+                                        Some(Parabuilder::TIMEOUT_CATEGORY)
+                                    } else {
+                                        classify_compile_error(
+                                            &compile_error_classifier,
+                                            &compile_data,
+                                        )
+                                    };
+                                    compile_error_datas.push(compile_error_record(&data, category));
+                                    continue;
+                                }
+                                CompliationErrorHandlingMethod::Ignore => {
+                                    continue;
+                                }
+                                CompliationErrorHandlingMethod::Panic => {
+                                    panic!("Compilation script failed in data: {:?}", data);
+                                }
+                            }
+                        }
+                    }
+                }
+                if abort_flag.load(Ordering::Relaxed) {
+                    autosaver.save(&run_data, &compile_error_datas, &processed_data_ids);
+                    break;
+                }
+                match run_method {
+                    RunMethod::InPlace => {
+                        // run
+                        context.compile_ms = Some(compile_ms);
+                        context.data_id = Some(i);
+                        context.run_script_steps.clone_from(&scripts.run_steps);
+                        context.run_stdin.clone_from(&scripts.run_stdin);
+                        context.run_command.clone_from(&scripts.run_command);
+                        let run_workspace_path = std::fs::canonicalize(&*workspace_path).unwrap();
+                        warmup(
+                            run_func,
+                            warmup_runs,
+                            &run_workspace_path,
+                            &scripts.run,
+                            &data,
+                            &abort_flag,
+                            &context,
+                        );
+                        emit_progress_event(
+                            &progress_callback,
+                            &worker_status,
+                            ProgressEvent::RunStarted { id: i },
+                        );
+                        let last_data = run_func(
+                            &run_workspace_path,
+                            &scripts.run,
+                            &data,
+                            &mut run_data,
+                            &abort_flag,
+                            &context,
+                        )
+                        .unwrap_or_else(|e| abort_on_run_error(&stop_flag, &abort_flag, &data, e));
+                        if let Some(result_cache) = &result_cache {
+                            result_cache.store(i, &last_data);
+                        }
+                        if !last_data.is_null() {
+                            write_stream_output(&stream_output_writer, &last_data);
+                            emit_progress_event(
+                                &progress_callback,
+                                &worker_status,
+                                ProgressEvent::RunFinished {
+                                    id: i,
+                                    status: last_data["status"].as_i64(),
+                                },
+                            );
+                        }
+                        sp.set_message(serde_json::to_string_pretty(&last_data).unwrap());
+                        run_pb.inc(1);
+                    }
+                    RunMethod::No
+                    | RunMethod::Exclusive(_)
+                    | RunMethod::OutOfPlace(_)
+                    | RunMethod::Interleaved(_) => {
+                        let mut artifacts = vec![];
+                        for target_file in resolved_target_files.iter() {
+                            let target_path = workspace_path.join(target_file);
+                            let to_target_executable_path_file = format!(
+                                "{}_{}",
+                                target_file.file_name().unwrap().to_string_lossy(),
+                                i
+                            );
+                            let to_target_executable_path =
+                                temp_target_path_dir.join(&to_target_executable_path_file);
+                            copy_target(&target_path, &to_target_executable_path).unwrap();
+                            artifacts.push(to_target_executable_path_file);
+                        }
+                        match run_method {
+                            RunMethod::No => {
+                                let to_metadata_path =
+                                    temp_target_path_dir.join(format!("data_{}.json", i));
+                                std::fs::write(&to_metadata_path, data.to_string()).unwrap();
+                                staged_targets.lock().unwrap().push(json!({
+                                    "id": i,
+                                    "data": data,
+                                    "compile_ms": compile_ms,
+                                    "target_files": resolved_target_files,
+                                    "artifacts": artifacts,
+                                }));
+                            }
+                            RunMethod::OutOfPlace(_)
+                            | RunMethod::Exclusive(_)
+                            | RunMethod::Interleaved(_) => {
+                                executable_queue_sender
+                                    .send((i, data.clone(), compile_ms, resolved_target_files))
+                                    .unwrap();
+                            }
+                            _ => panic!("Unexpected run method"),
+                        }
+                    }
+                }
+                if abort_flag.load(Ordering::Relaxed) {
+                    autosaver.save(&run_data, &compile_error_datas, &processed_data_ids);
+                    break;
+                }
+                match run_method {
+                    RunMethod::InPlace | RunMethod::No => {
+                        processed_data_ids.push(i);
+                    }
+                    _ => {}
+                }
+                if autosave_interval > 0
+                    && autosave_last_time.elapsed().as_secs() > autosave_interval
+                {
+                    autosaver.save(&run_data, &compile_error_datas, &processed_data_ids);
+                    autosave_last_time = Instant::now();
+                }
+            }
+            (run_data, compile_error_datas, processed_data_ids)
+        })
+    }
+
+    /// Run the executables, taking their targets out of `targets_dir`
+    fn run_worker(
+        &self,
+        workspace_path: PathBuf,
+        executable_queue_receiver: Receiver<Executable>,
+        targets_dir: PathBuf,
+        run_pb: ProgressBar,
+        stop_flags: StopFlags,
+        start_time: String,
+    ) -> std::thread::JoinHandle<(JsonValue, Vec<usize>)> {
+        let StopFlags {
+            stop: stop_flag,
+            abort: abort_flag,
+        } = stop_flags;
+        let run_func = self.run_func_data;
+        let mut run_data = JsonValue::Null;
+        let disable_progress_bar = self.progress_bar_disabled();
+        let progress_ascii = self.progress_ascii;
+        let mpb = self.mpb.clone();
+        let script_templates = self.script_templates();
+        let warmup_runs = self.warmup_runs;
+        let file_ready_wait = self.file_ready_wait;
+        let exclusive_gate = matches!(self.run_method, RunMethod::Interleaved(_))
+            .then(|| self.exclusive_gate.clone());
+        let autosaver = self.autosaver(&start_time);
+        let autosave_interval = self.autosave_interval;
+        let stream_output_writer = self.stream_output_writer.clone();
+        let progress_callback = self.progress_sink();
+        let worker_status = self.worker_status.clone();
+        let pause_flag = self.pause_flag.clone();
+        let mut context = self.run_context();
+        let pending_run_workspaces = self.pending_run_workspaces.clone();
+        let result_cache = self.result_cache_of_run();
+        std::thread::spawn(move || {
+            let mut processed_data_ids = Vec::new();
+            let mut autosave_last_time = Instant::now();
+            let sp = Self::add_spinner2(
+                disable_progress_bar,
+                progress_ascii,
+                &mpb,
+                serde_json::to_string_pretty(&JsonValue::Null).unwrap(),
+            );
+            for (i, data, compile_ms, target_files) in executable_queue_receiver.iter() {
+                pause_flag.wait(&stop_flag);
+                if stop_flag.load(Ordering::Relaxed) {
+                    autosaver.save(&run_data, &vec![], &processed_data_ids);
+                    break;
+                }
+                let workspace_init = pending_run_workspaces
+                    .lock()
+                    .unwrap()
+                    .remove(&workspace_path);
+                if let Some(workspace_init) = workspace_init {
+                    let workspace = workspace_path.file_name().unwrap().to_string_lossy();
+                    let label = format!("init {}", workspace);
+                    sp.set_message(format!("{}: copying", label));
+                    if let Err(e) = workspace_init.init(
+                        &workspace,
+                        &workspace_path,
+                        &ProgressBar::hidden(),
+                        &label,
+                    ) {
+                        stop_flag.store(true, Ordering::Relaxed);
+                        abort_flag.store(true, Ordering::Relaxed);
+                        panic!("{}", e);
+                    }
+                }
+                let targets_path: Vec<PathBuf> = target_files
+                    .iter()
+                    .map(|target_file| workspace_path.join(target_file))
+                    .collect();
+                for (target_path, target_file) in targets_path.iter().zip(target_files.iter()) {
+                    let to_target_path_file = format!(
+                        "{}_{}",
+                        target_file.file_name().unwrap().to_string_lossy(),
+                        i
+                    );
+                    let to_target_executable_path = targets_dir.join(&to_target_path_file);
+                    // the directories of globbed target files may not exist in the run workspace
+                    if let Some(parent) = target_path.parent() {
+                        std::fs::create_dir_all(parent).unwrap();
+                    }
+                    move_target(&to_target_executable_path, target_path).unwrap();
+                }
+                for target_path in targets_path.iter() {
+                    wait_until_file_ready(target_path, file_ready_wait).unwrap();
+                }
+                context.compile_ms = Some(compile_ms);
+                context.data_id = Some(i);
+                // the build worker has rendered the same scripts without an error,
+                // only a run script in project can still be missing from this workspace
+                let scripts = script_templates
+                    .for_run_in(&workspace_path, &data)
+                    .unwrap_or_else(|e| {
+                        abort_on_run_error(&stop_flag, &abort_flag, &data, e.into())
+                    });
+                context.run_script_steps.clone_from(&scripts.run_steps);
+                context.run_stdin.clone_from(&scripts.run_stdin);
+                context.run_command.clone_from(&scripts.run_command);
+                let exclusive_guard = exclusive_gate.as_ref().map(|gate| gate.run());
+                let run_workspace_path = std::fs::canonicalize(&workspace_path).unwrap();
+                warmup(
+                    run_func,
+                    warmup_runs,
+                    &run_workspace_path,
+                    &scripts.run,
+                    &data,
+                    &abort_flag,
+                    &context,
+                );
+                emit_progress_event(
+                    &progress_callback,
+                    &worker_status,
+                    ProgressEvent::RunStarted { id: i },
+                );
+                let last_data = run_func(
+                    &run_workspace_path,
+                    &scripts.run,
+                    &data,
+                    &mut run_data,
+                    &abort_flag,
+                    &context,
+                )
+                .unwrap_or_else(|e| abort_on_run_error(&stop_flag, &abort_flag, &data, e));
+                drop(exclusive_guard);
+                if abort_flag.load(Ordering::Relaxed) {
+                    autosaver.save(&run_data, &vec![], &processed_data_ids);
+                    break;
+                }
+                if let Some(result_cache) = &result_cache {
+                    result_cache.store(i, &last_data);
+                }
+                write_stream_output(&stream_output_writer, &last_data);
+                emit_progress_event(
+                    &progress_callback,
+                    &worker_status,
+                    ProgressEvent::RunFinished {
+                        id: i,
+                        status: last_data["status"].as_i64(),
+                    },
+                );
+                sp.set_message(serde_json::to_string_pretty(&last_data).unwrap());
+                run_pb.inc(1);
+                processed_data_ids.push(i);
+                if autosave_interval > 0
+                    && autosave_last_time.elapsed().as_secs() > autosave_interval
+                {
+                    autosaver.save(&run_data, &vec![], &processed_data_ids);
+                    autosave_last_time = Instant::now();
+                }
+            }
+            (run_data, processed_data_ids)
+        })
+    }
+
+    pub fn gather_data(
+        &self,
+        run_data_array: Vec<JsonValue>,
+        compile_error_datas: Vec<JsonValue>,
+        processed_data_ids: Vec<usize>,
+    ) -> Result<RunOutput, Box<dyn Error>> {
+        let run_data_array: Vec<JsonValue> = run_data_array
+            .into_iter()
+            .filter(|item| !item.is_null())
+            .collect();
+        let run_datas = if self.run_method == RunMethod::No || self.dry_run {
+            JsonValue::Null
+        } else if self.auto_gather_array_data && run_data_array.iter().all(|item| item.is_array()) {
+            let mut run_data = Vec::new();
+            for run_data_item in run_data_array {
+                run_data.extend(run_data_item.as_array().unwrap().iter().cloned());
+            }
+            if self.sort_output_by_id {
+                // stable, the results without an id keep their order at the front
+                run_data.sort_by_key(|item| item["id"].as_u64());
+            }
+            JsonValue::Array(run_data)
+        } else {
+            // just return array json
+            JsonValue::Array(run_data_array)
+        };
+        if let (Some(reduce_func), JsonValue::Array(run_datas)) = (&self.reduce_func, &run_datas) {
+            *self.reduced_data.lock().unwrap() = Some(reduce_func(run_datas));
+        }
+        Ok((run_datas, compile_error_datas, processed_data_ids))
+    }
+
+    const DEFAULT_PROGRESS_TEMPLATE: &'static str =
+        "[{elapsed_precise}  ETA: {eta_precise}] [{per_sec}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}";
+    const ASCII_PROGRESS_CHARS: &'static str = "#>-";
+    const ASCII_TICK_CHARS: &'static str = "|/-\\ ";
+
+    /// The style of the building and running bars
+    fn progress_bar_style(&self) -> Result<ProgressStyle, indicatif::style::TemplateError> {
+        let template = self
+            .progress_template
+            .as_deref()
+            .unwrap_or(Self::DEFAULT_PROGRESS_TEMPLATE);
+        let sty = ProgressStyle::with_template(template)?;
+        Ok(if self.progress_ascii {
+            sty.progress_chars(Self::ASCII_PROGRESS_CHARS)
+        } else {
+            sty
+        })
+    }
+
+    fn add_progress_bar<S: Into<String>, F: Into<Cow<'static, str>>>(
+        &self,
+        message: S,
+        total: u64,
+        finish_message: F,
+    ) -> ProgressBar {
+        if self.progress_bar_disabled() {
+            return ProgressBar::hidden();
+        }
+        // validated by `run()`
+        let sty = self.progress_bar_style().unwrap();
+        let pb = self.mpb.add(
+            ProgressBar::new(total)
+                .with_message(message.into())
+                .with_style(sty)
+                .with_finish(ProgressFinish::AbandonWithMessage(finish_message.into())),
+        );
+        // start from the resume point, the ETA only counts the datas of this run
+        pb.set_position(self.resumed_data_count);
+        pb.reset_eta();
+        pb
+    }
+
+    fn add_spinner2<S: Into<String>>(
+        disable_progress_bar: bool,
+        ascii: bool,
+        mpb: &MultiProgress,
+        message: S,
+    ) -> ProgressBar {
+        if disable_progress_bar {
+            return ProgressBar::hidden();
+        }
+        let mut sp = ProgressBar::new_spinner().with_message(message.into());
+        if ascii {
+            sp = sp.with_style(ProgressStyle::default_spinner().tick_chars(Self::ASCII_TICK_CHARS));
+        }
+        let sp = mpb.add(sp);
+        sp.enable_steady_tick(Duration::from_millis(100));
+        sp
+    }
+
+    /// A spinner with a bytes progress bar, used when copying workspaces
+    fn add_copy_progress_bar2<S: Into<String>>(
+        disable_progress_bar: bool,
+        ascii: bool,
+        mpb: &MultiProgress,
+        message: S,
+    ) -> ProgressBar {
+        if disable_progress_bar {
+            return ProgressBar::hidden();
+        }
+        let mut sty = ProgressStyle::with_template(
+            "{spinner} {msg} {bar:40.cyan/blue} {bytes}/{total_bytes} ({percent}%)",
+        )
+        .unwrap();
+        if ascii {
+            sty = sty
+                .tick_chars(Self::ASCII_TICK_CHARS)
+                .progress_chars(Self::ASCII_PROGRESS_CHARS);
+        }
+        let sp = mpb.add(
+            ProgressBar::new(0)
+                .with_style(sty)
+                .with_message(message.into()),
+        );
+        sp.enable_steady_tick(Duration::from_millis(100));
+        sp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // use std::time::Instant;
+    use serde_json::json;
+
+    const EXAMPLE_PROJECT: &str = crate::test_constants::EXAMPLE_CMAKE_PROJECT_PATH;
+    const EXAMPLE_TEMPLATE_FILE: &str = "src/main.cpp.template";
+    const EXAMPLE_IN_PLACE_TEMPLATE_FILE: &str = "src/main.cpp";
+    const EXAMPLE_TARGET_EXECUTABLE_FILE: &str = "build/main";
+    const EXAMPLE_INIT_BASH_SCRIPT: &str = r#"
+        cmake -B build -S .
+        "#;
+    const EXAMPLE_IN_PLACE_INIT_BASH_SCRIPT: &str = r#"
+        cmake -B build -S . -DPARABUILD=ON
+        "#;
+    const EXAMPLE_COMPILE_BASH_SCRIPT: &str = r#"
+        cmake --build build --target all -- -B
+        "#;
+
+    #[test]
+    fn test_workspaces_under_project_path() {
+        let example_project_path = std::fs::canonicalize(EXAMPLE_PROJECT).unwrap();
+        let workspaces_path = PathBuf::from("workspaces_under_project_path");
+        let parabuilder = Parabuilder::new(
+            ".",
+            &workspaces_path,
+            example_project_path.join("src/main.cpp.template"),
+            &["build/main"],
+        )
+        .init_bash_script(
+            format!(
+                r#"
+                cmake -B build -S {}
+                "#,
+                EXAMPLE_PROJECT
+            )
+            .as_str(),
+        )
+        .compile_bash_script(
+            r#"
+            cmake --build build --target all -- -B
+            "#,
+        )
+        .disable_progress_bar(true)
+        .no_cache(true);
+        parabuilder.init_workspace().unwrap();
+        assert!(workspaces_path
+            .join(format!(
+                "workspace_0/{}/src/main.cpp.template",
+                EXAMPLE_PROJECT
+            ))
+            .exists());
+        assert!(workspaces_path.join("workspace_0/build").exists());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    const SINGLETHREADED_N: i64 = 20;
+    const MULTITHREADED_N: i64 = 100;
+
+    fn parabuild_tester(
+        name: &str,
+        size: i64,
+        build_workers: usize,
+        run_method: RunMethod,
+        in_place_template: bool,
+        is_makefile_project: bool,
+    ) {
+        let project = if !is_makefile_project {
+            EXAMPLE_PROJECT
+        } else {
+            crate::test_constants::EXAMPLE_MAKEFILE_PROJECT_PATH
+        };
+        let mut datas = (1..=size)
+            .map(|i| json!({"N": i}))
+            .collect::<Vec<JsonValue>>();
+        if size == 0 {
+            datas.push(json!({}));
+        }
+        let error_data = json!({"N": "a"});
+        datas.push(error_data.clone());
+        let workspaces_path = PathBuf::from(format!("tests/workspaces_{}", name));
+        let mut parabuilder = Parabuilder::new(
+            project,
+            &workspaces_path,
+            if !is_makefile_project {
+                if in_place_template {
+                    EXAMPLE_IN_PLACE_TEMPLATE_FILE
+                } else {
+                    EXAMPLE_TEMPLATE_FILE
+                }
+            } else {
+                ""
+            },
+            if !is_makefile_project {
+                &[EXAMPLE_TARGET_EXECUTABLE_FILE]
+            } else {
+                &["main"]
+            },
+        )
+        .init_bash_script(if !is_makefile_project {
+            if in_place_template {
+                EXAMPLE_IN_PLACE_INIT_BASH_SCRIPT
+            } else {
+                EXAMPLE_INIT_BASH_SCRIPT
+            }
+        } else {
+            ""
+        })
+        .compile_bash_script(if !is_makefile_project {
+            EXAMPLE_COMPILE_BASH_SCRIPT
+        } else {
+            "make -B"
+        })
+        .build_workers(build_workers)
+        .run_method(run_method)
+        .run_func(PANIC_ON_ERROR_DEFAULT_RUN_FUNC)
+        .disable_progress_bar(true)
+        .compilation_error_handling_method(CompliationErrorHandlingMethod::Collect)
+        .in_place_template(in_place_template)
+        .auto_gather_array_data(true)
+        .enable_cppflags(is_makefile_project);
+
+        parabuilder.set_datas(datas).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, processed_data_ids) = parabuilder.run().unwrap();
+        assert!(
+            compile_error_datas == vec![json!({"data": error_data, "category": null})],
+            "got: {:?} {:?}",
+            run_data,
+            compile_error_datas
+        );
+        assert!(
+            processed_data_ids.len() == if size == 0 { 2 } else { size as usize + 1 }, // default
+            "got: {:?}",
+            processed_data_ids
+        );
+        if matches!(run_method, RunMethod::No) {
+            assert!(run_data.is_null(), "got: {}", run_data);
+            for i in 0..size {
+                assert!(workspaces_path
+                    .join(format!("{}/main_{}", Parabuilder::TEMP_TARGET_PATH_DIR, i))
+                    .exists());
+                assert!(workspaces_path
+                    .join(format!(
+                        "{}/data_{}.json",
+                        Parabuilder::TEMP_TARGET_PATH_DIR,
+                        i
+                    ))
+                    .exists());
+            }
+        } else {
+            let ground_truth = if size > 0 {
+                (1..=size).sum::<i64>()
+            } else {
+                42
+            };
+            assert!(run_data.is_array());
+            let sum = run_data.as_array().unwrap().iter().fold(0, |acc, item| {
+                acc + item["stdout"]
+                    .as_str()
+                    .unwrap()
+                    .trim()
+                    .parse::<i64>()
+                    .unwrap()
+            });
+            assert!(
+                sum == ground_truth,
+                "expected: {}, got: {}, run_data: {}",
+                ground_truth,
+                sum,
+                run_data
+            );
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_singlethreaded_parabuild_without_run() {
+        parabuild_tester(
+            "test_singlethreaded_parabuild_without_run",
+            SINGLETHREADED_N,
+            1,
+            RunMethod::No,
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_singlethreaded_parabuild_in_place_run() {
+        parabuild_tester(
+            "test_singlethreaded_parabuild_in_place_run",
+            SINGLETHREADED_N,
+            1,
+            RunMethod::InPlace,
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_without_run() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_without_run",
+            MULTITHREADED_N,
+            4,
+            RunMethod::No,
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_in_place_run() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_in_place_run",
+            MULTITHREADED_N,
+            4,
+            RunMethod::InPlace,
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_out_of_place_single_run() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_out_of_place_single_run",
+            MULTITHREADED_N,
+            4,
+            RunMethod::OutOfPlace(1),
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_out_of_place_run() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_out_of_place_run",
+            MULTITHREADED_N,
+            4,
+            RunMethod::OutOfPlace(2),
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_exclusive_run() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_exclusive_run",
+            MULTITHREADED_N,
+            4,
+            RunMethod::Exclusive(2),
+            false,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_out_of_place_run_in_place_template() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_out_of_place_run_in_place_template",
+            MULTITHREADED_N,
+            4,
+            RunMethod::OutOfPlace(2),
+            true,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_out_of_place_run_default_template() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_out_of_place_run_default_template",
+            0,
+            4,
+            RunMethod::OutOfPlace(2),
+            true,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_multithreaded_parabuild_makefile_project() {
+        parabuild_tester(
+            "test_multithreaded_parabuild_makefile_project",
+            10,
+            4,
+            RunMethod::OutOfPlace(2),
+            true,
+            true,
+        );
+    }
+
+    fn makefile_parabuilder(workspaces_path: &Path, run_method: RunMethod) -> Parabuilder {
+        Parabuilder::new(
+            crate::test_constants::EXAMPLE_MAKEFILE_PROJECT_PATH,
+            workspaces_path,
+            "",
+            &["main"],
+        )
+        .init_bash_script("")
+        .compile_bash_script("make -B")
+        .build_workers(2)
+        .run_method(run_method)
+        .disable_progress_bar(true)
+        .without_rsync(true)
+        .no_cache(true)
+        .enable_cppflags(true)
+    }
+
+    #[test]
+    fn test_compile_and_run_timing() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_compile_and_run_timing");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method);
+            parabuilder
+                .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            for item in run_data.as_array().unwrap() {
+                assert!(item["compile_ms"].is_u64(), "got: {}", item);
+                assert!(item["run_ms"].is_u64(), "got: {}", item);
+            }
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_timeout() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_run_timeout");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .run_bash_script("sleep 10")
+            .run_timeout(Duration::from_millis(200));
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["status"], -1);
+        assert_eq!(run_data[0]["timed_out"], true);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_run_retries() {
+        // fails on the first two attempts
+        let run_bash_script = r#"
+            n=$(cat attempts 2>/dev/null || echo 0)
+            n=$((n + 1))
+            echo $n > attempts
+            [ $n -ge 3 ]
+        "#;
+        for (name, run_method) in [
+            ("in_place", RunMethod::InPlace),
+            ("out_of_place", RunMethod::OutOfPlace(1)),
+        ] {
+            for (run_retries, status, attempts) in [(1, 1, 2), (3, 0, 3)] {
+                let workspaces_path = PathBuf::from(format!(
+                    "tests/workspaces_test_run_retries_{}_{}",
+                    name, run_retries
+                ));
+                let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                    .run_bash_script(run_bash_script)
+                    .run_retries(run_retries)
+                    .run_retry_backoff(Duration::from_millis(10));
+                parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+                parabuilder.init_workspace().unwrap();
+                let (run_data, _, _) = parabuilder.run().unwrap();
+                assert_eq!(run_data[0]["status"], status, "got: {}", run_data);
+                assert_eq!(run_data[0]["attempts"], attempts, "got: {}", run_data);
+                std::fs::remove_dir_all(workspaces_path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_workers_auto_cuda() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_run_workers_auto_cuda");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .gpu_devices(vec!["0".to_string(), "1".to_string(), "2".to_string()])
+            .run_workers_auto_cuda();
+        parabuilder
+            .set_datas((0..4).map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        for i in 0..3 {
+            assert!(workspaces_path
+                .join(format!("workspace_exe_{}", i))
+                .exists());
+        }
+        assert!(!workspaces_path.join("workspace_exe_3").exists());
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data.as_array().unwrap().len(), 4);
+        std::fs::remove_dir_all(&workspaces_path).unwrap();
+
+        let parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .gpu_devices(vec![])
+            .run_workers_auto_cuda();
+        assert!(parabuilder.init_workspace().is_err());
+    }
+
+    #[test]
+    fn test_cuda_device_key() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_cuda_device_key");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .gpu_devices(vec!["0".to_string()])
+            .run_bash_script("echo -n $CUDA_VISIBLE_DEVICES")
+            .sort_output_by_id(true);
+        parabuilder
+            .set_datas(vec![
+                json!({"N": 1, "__cuda_device": "GPU-xxx"}),
+                json!({"N": 2, "__cuda_device": 3}),
+                json!({"N": 3}),
+            ])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        let stdouts: Vec<&str> = run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["stdout"].as_str().unwrap())
+            .collect();
+        assert_eq!(stdouts, vec!["GPU-xxx", "3", "0"]);
+        let data = json!({"N": 1, "__cuda_device": "GPU-xxx"});
+        assert_eq!(data_to_cppflags(&data, false), "-DN=1 ");
+        assert_eq!(
+            data_to_envs("PARA_", &data),
+            vec![("PARA_N".to_string(), "1".to_string())]
+        );
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        let parabuilder = makefile_parabuilder(
+            Path::new("tests/workspaces_test_validate"),
+            RunMethod::OutOfPlace(1),
+        );
+        assert_eq!(parabuilder.validate(), Ok(()));
+
+        let autosave_file = tempdir().unwrap().into_path().join("file");
+        std::fs::write(&autosave_file, "").unwrap();
+        let problems = Parabuilder::new(
+            "tests/no_such_project",
+            "tests/workspaces_test_validate",
+            "src/main.cpp",
+            &[] as &[&str],
+        )
+        .run_method(RunMethod::OutOfPlace(1))
+        .without_rsync(true)
+        .autosave_dir(autosave_file.join("autosave"))
+        .warning_regex("(")
+        .collect_warnings(true)
+        .validate()
+        .unwrap_err();
+        assert_eq!(problems.len(), 4, "got: {:?}", problems);
+        assert!(problems[0].contains("no_such_project"));
+        assert!(problems[1].contains("No target file"));
+        assert!(problems[2].contains("Autosave dir"));
+        assert!(problems[3].contains("Invalid warning regex"));
+
+        let problems = Parabuilder::new(
+            crate::test_constants::EXAMPLE_MAKEFILE_PROJECT_PATH,
+            "tests/workspaces_test_validate",
+            "src/missing.cpp",
+            &["main"],
+        )
+        .without_rsync(true)
+        .validate()
+        .unwrap_err();
+        assert_eq!(problems.len(), 1, "got: {:?}", problems);
+        assert!(problems[0].contains("missing.cpp"));
+        assert!(!Path::new("tests/workspaces_test_validate").exists());
+    }
+
+    #[test]
+    fn test_data_schema() {
+        let mut parabuilder =
+            Parabuilder::new(".", "workspaces", "", &["main"]).data_schema(json!({
+                "type": "object",
+                "properties": {"N": {"type": "integer"}},
+                "required": ["N"],
+                "additionalProperties": false
+            }));
+        let err = parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"NN": 1}), json!({"N": "1"})])
+            .unwrap_err()
+            .to_string();
+        assert!(!err.contains("data 0"), "got: {}", err);
+        assert!(err.contains("data 1"), "got: {}", err);
+        assert!(err.contains("data 2 at \"/N\""), "got: {}", err);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_compile_timeout() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_compile_timeout");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .compile_bash_script("sleep 10")
+            .compile_timeout(Duration::from_millis(200));
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data, json!([]));
+        assert_eq!(
+            compile_error_datas,
+            vec![json!({"data": {"N": 1}, "category": "timeout"})]
+        );
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_multiple_template_files() {
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(project_path.join("a.txt.template"), "{{N}}").unwrap();
+        std::fs::write(project_path.join("b.txt.template"), "{{M}}").unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_multiple_template_files");
+        let mut parabuilder = Parabuilder::new(&project_path, &workspaces_path, "", &["out"])
+            .template_files(&["a.txt.template", "b.txt.template"])
+            .init_bash_script("")
+            .compile_bash_script("cat a.txt b.txt > out")
+            .run_bash_script("cat out")
+            .run_method(RunMethod::InPlace)
+            .disable_progress_bar(true)
+            .without_rsync(true)
+            .no_cache(true);
+        parabuilder
+            .set_datas(vec![json!({"N": 1, "M": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["stdout"], "12");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn test_save_rendered_on_error() {
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(project_path.join("main.cpp.template"), "{{N}}").unwrap();
+        let save_dir = tempdir().unwrap().into_path();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_save_rendered_on_error");
+        let mut parabuilder = Parabuilder::new(
+            &project_path,
+            &workspaces_path,
+            "main.cpp.template",
+            &["main.cpp"],
+        )
+        .init_bash_script("")
+        .compile_bash_script(r#"if [ "$(cat main.cpp)" = 2 ]; then echo bad >&2; exit 1; fi"#)
+        .run_bash_script("")
+        .run_method(RunMethod::InPlace)
+        .disable_progress_bar(true)
+        .without_rsync(true)
+        .no_cache(true)
+        .save_rendered_on_error(&save_dir);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (_, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert_eq!(
+            compile_error_datas,
+            vec![json!({"data": {"N": 2}, "category": null})]
+        );
+        assert_eq!(
+            std::fs::read_to_string(save_dir.join("data_1.cpp")).unwrap(),
+            "2"
+        );
+        assert_eq!(
+            std::fs::read_to_string(save_dir.join("data_1.log")).unwrap(),
+            "bad\n"
+        );
+        assert!(!save_dir.join("data_0.cpp").exists());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
+        std::fs::remove_dir_all(save_dir).unwrap();
+    }
+
+    #[test]
+    fn test_compile_func() {
+        fn warnings_as_errors(
+            workspace_path: &PathBuf,
+            compile_script: &str,
+            data: &JsonValue,
+            stop_flag: &Arc<AtomicBool>,
+            context: &CompileContext,
+        ) -> Result<(bool, JsonValue), Box<dyn Error>> {
+            let (success, compile_data) =
+                DEFAULT_COMPILE_FUNC(workspace_path, compile_script, data, stop_flag, context)?;
+            let has_warning = compile_data["stderr"]
+                .as_str()
+                .is_some_and(|stderr| stderr.contains("warning"));
+            Ok((success && !has_warning, compile_data))
+        }
+
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(project_path.join("main.cpp.template"), "{{N}}").unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_compile_func");
+        let mut parabuilder = Parabuilder::new(
+            &project_path,
+            &workspaces_path,
+            "main.cpp.template",
+            &["main.cpp"],
+        )
+        .init_bash_script("")
+        .compile_bash_script(r#"if [ "$(cat main.cpp)" = 2 ]; then echo "warning: N is 2" >&2; fi"#)
+        .run_bash_script("cat main.cpp")
+        .run_method(RunMethod::InPlace)
+        .disable_progress_bar(true)
+        .without_rsync(true)
+        .no_cache(true)
+        .compile_func(warnings_as_errors);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert_eq!(
+            compile_error_datas,
+            vec![json!({"data": {"N": 2}, "category": null})]
+        );
+        assert_eq!(run_data.as_array().unwrap().len(), 1);
+        assert_eq!(run_data[0]["stdout"], "1");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn test_autosave_datas_hash() {
+        let autosave_dir = tempdir().unwrap().into_path();
+        let datas: Vec<JsonValue> = (0..4).map(|i| json!({"N": i})).collect();
+        Autosaver {
+            autosave_dir: autosave_dir.clone(),
+            start_time: "start".to_string(),
+            worker_id: Uuid::new_v4(),
+            datas_hash: Some(datas_hash(&datas)),
+            compress: false,
+            generations: 1,
+        }
+        .save(&json!([]), &vec![], &vec![0, 2]);
+
+        let mut parabuilder =
+            Parabuilder::new(".", "workspaces", "", &["main"]).autosave_dir(&autosave_dir);
+        let (_, _, processed_data_ids) = parabuilder.autosave_load("start".to_string());
+        assert_eq!(processed_data_ids, vec![0, 2]);
+        parabuilder.set_datas(datas.clone()).unwrap();
+        let remaining_ids: Vec<usize> = parabuilder
+            .data_queue_receiver
+            .as_ref()
+            .unwrap()
+            .try_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(remaining_ids, vec![1, 3]);
+        assert_eq!(parabuilder.resumed_data_count, 2);
+        parabuilder
+            .mpb
+            .set_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let pb = parabuilder.add_progress_bar("Building", 4, "done");
+        assert_eq!(pb.length(), Some(4));
+        assert_eq!(pb.position(), 2);
+
+        let mut parabuilder =
+            Parabuilder::new(".", "workspaces", "", &["main"]).autosave_dir(&autosave_dir);
+        parabuilder.autosave_load("start".to_string());
+        let mut changed_datas = datas;
+        changed_datas[1] = json!({"N": 10});
+        assert!(parabuilder.set_datas(changed_datas).is_err());
+        std::fs::remove_dir_all(autosave_dir).unwrap();
+    }
+
+    #[test]
+    fn test_autosave_generations() {
+        let autosave_dir = tempdir().unwrap().into_path();
+        let mut autosaver = Autosaver {
+            autosave_dir: autosave_dir.clone(),
+            start_time: "start".to_string(),
+            worker_id: Uuid::new_v4(),
+            datas_hash: None,
+            compress: false,
+            generations: 3,
+        };
+        for i in 0..5 {
+            autosaver.save(&json!([i]), &vec![], &vec![i]);
+        }
+        let worker_dir = autosave_dir
+            .join("start")
+            .join(autosaver.worker_id.to_string());
+        let read = |path: &Path| -> JsonValue {
+            serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap()
+        };
+        let generations = || -> Vec<JsonValue> {
+            Autosaver::generations(&worker_dir, "run_datas", "json")
+                .iter()
+                .map(|path| read(path))
+                .collect()
+        };
+        assert_eq!(read(&worker_dir.join("run_datas.json")), json!([4]));
+        assert_eq!(generations(), vec![json!([1]), json!([2]), json!([3])]);
+        autosaver.generations = 1;
+        autosaver.save(&json!([5]), &vec![], &vec![5]);
+        assert_eq!(generations(), vec![json!([4])]);
+        autosaver.generations = 0;
+        autosaver.save(&json!([6]), &vec![], &vec![6]);
+        assert!(generations().is_empty());
+        assert_eq!(read(&worker_dir.join("run_datas.json")), json!([6]));
+        std::fs::remove_dir_all(autosave_dir).unwrap();
+    }
+
+    #[test]
+    fn test_autosave_compress() {
+        let autosave_dir = tempdir().unwrap().into_path();
+        let autosaver = Autosaver {
+            autosave_dir: autosave_dir.clone(),
+            start_time: "start".to_string(),
+            worker_id: Uuid::new_v4(),
+            datas_hash: None,
+            compress: true,
+            generations: 1,
+        };
+        autosaver.save(&json!([{"stdout": "1"}]), &vec![json!({"N": 3})], &vec![0]);
+        autosaver.save(
+            &json!([{"stdout": "1"}, {"stdout": "2"}]),
+            &vec![json!({"N": 3})],
+            &vec![0, 1],
+        );
+        let worker_dir = autosave_dir
+            .join("start")
+            .join(autosaver.worker_id.to_string());
+        assert!(worker_dir.join("run_datas.json.gz").exists());
+        let generation_paths = Autosaver::generations(&worker_dir, "run_datas", "json.gz");
+        assert_eq!(generation_paths.len(), 1);
+        assert!(Autosaver::generations(&worker_dir, "run_datas", "json").is_empty());
+        assert!(!worker_dir.join("run_datas.json").exists());
+        assert!(!worker_dir.join("run_datas.json.gz.tmp").exists());
+        let backup: JsonValue = serde_json::from_reader(GzDecoder::new(
+            std::fs::File::open(&generation_paths[0]).unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(backup, json!([{"stdout": "1"}]));
+
+        let mut parabuilder =
+            Parabuilder::new(".", "workspaces", "", &["main"]).autosave_dir(&autosave_dir);
+        let (run_datas, compile_error_datas, processed_data_ids) =
+            parabuilder.autosave_load("start".to_string());
+        assert_eq!(run_datas.as_array().unwrap().len(), 2);
+        // the bare datas of older autosaves are upgraded
+        assert_eq!(
+            compile_error_datas,
+            vec![json!({"data": {"N": 3}, "category": null})]
+        );
+        assert_eq!(processed_data_ids, vec![0, 1]);
+        std::fs::remove_dir_all(autosave_dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_strategy_hardlink() {
+        // on the same filesystem as the workspaces, so hard links work
+        let project_path = PathBuf::from("tests/project_test_copy_strategy_hardlink");
+        std::fs::create_dir_all(&project_path).unwrap();
+        std::fs::write(project_path.join("main.cpp.template"), "{{N}}").unwrap();
+        std::fs::write(project_path.join("shared.h"), "#define SHARED").unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_copy_strategy_hardlink");
+        let mut parabuilder = Parabuilder::new(
+            &project_path,
+            &workspaces_path,
+            "main.cpp.template",
+            &["main.cpp"],
+        )
+        .init_bash_script("")
+        .compile_bash_script("")
+        .run_bash_script("cat main.cpp")
+        .run_method(RunMethod::InPlace)
+        .disable_progress_bar(true)
+        .without_rsync(true)
+        .no_cache(true)
+        .copy_strategy(CopyStrategy::Hardlink);
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["stdout"], "1");
+        assert_eq!(
+            std::fs::read_to_string(project_path.join("main.cpp.template")).unwrap(),
+            "{{N}}"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let nlink = |path: PathBuf| std::fs::metadata(path).unwrap().nlink();
+            assert!(nlink(project_path.join("shared.h")) > 1);
+            assert_eq!(nlink(project_path.join("main.cpp.template")), 1);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn test_compiler_cache_dir() {
+        let parabuilder = Parabuilder::new(".", "workspaces", "", &["main"])
+            .envs(HashMap::from([(
+                "SCCACHE_DIR".to_string(),
+                "/tmp/sccache".to_string(),
+            )]))
+            .compiler_cache_dir("cache");
+        let envs = parabuilder.compile_context().envs;
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(PathBuf::from(&envs["CCACHE_DIR"]), cwd.join("cache"));
+        assert_eq!(envs["SCCACHE_DIR"], "/tmp/sccache");
+        assert_eq!(
+            PathBuf::from(&envs["CCACHE_BASEDIR"]),
+            cwd.join("workspaces")
+        );
+        assert!(Parabuilder::new(".", "workspaces", "", &["main"])
+            .compile_context()
+            .envs
+            .is_empty());
+    }
+
+    #[test]
+    fn test_compile_jobs() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_compile_jobs");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .compile_bash_script("echo -n $PARABUILD_COMPILE_JOBS > jobs")
+            .run_bash_script("cat jobs")
+            .compile_jobs(4);
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["stdout"], "4");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_run_one() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_run_one");
+        let parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace);
+        let this_data = parabuilder.run_one(json!({"N": 3})).unwrap();
+        assert_eq!(this_data["status"], 0);
+        assert_eq!(this_data["data"], json!({"N": 3}));
+        assert!(this_data["stdout"].as_str().unwrap().contains('3'));
+
+        let parabuilder = parabuilder.compile_bash_script("exit 1");
+        assert!(parabuilder.run_one(json!({"N": 3})).is_err());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_workspace_pool_size() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_workspace_pool_size");
+        let mut parabuilder =
+            makefile_parabuilder(&workspaces_path, RunMethod::InPlace).workspace_pool_size(3);
+        parabuilder
+            .set_datas((1..=6).map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        assert!(workspaces_path.join("workspace_2").exists());
+        assert!(!workspaces_path.join("workspace_3").exists());
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(compile_error_datas.is_empty());
+        let run_data = run_data.as_array().unwrap();
+        assert_eq!(run_data.len(), 6);
+        for item in run_data {
+            assert_eq!(item["status"], 0, "got: {}", item);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_reset_after_failures() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_reset_after_failures");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .build_workers(1)
+            .init_bash_script("echo init >> init.log")
+            .compile_bash_script("exit 1")
+            .reset_after_failures(2);
+        parabuilder
+            .set_datas((1..=5).map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (_, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert_eq!(compile_error_datas.len(), 5);
+        // the initial init and one reset after every 2 failures
+        let init_log =
+            std::fs::read_to_string(workspaces_path.join("workspace_0/init.log")).unwrap();
+        assert_eq!(init_log.lines().count(), 3);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_workspace_prefix() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_workspace_prefix");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .workspace_prefix("job123")
+            .run_bash_script("echo $PARABUILD_ID");
+        parabuilder
+            .set_datas((1..=3).map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        assert!(workspaces_path.join("job123_workspace_0").exists());
+        assert!(workspaces_path.join("job123_workspace_exe_0").exists());
+        assert!(workspaces_path.join("job123_targets").exists());
+        assert!(!workspaces_path.join("workspace_0").exists());
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        let run_data = run_data.as_array().unwrap();
+        assert_eq!(run_data.len(), 3);
+        for item in run_data {
+            assert_eq!(item["stdout"], "0\n", "got: {}", item);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_record_timestamps() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_record_timestamps");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .record_timestamps(true);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        for item in run_data.as_array().unwrap() {
+            let started_at =
+                chrono::DateTime::parse_from_rfc3339(item["started_at"].as_str().unwrap()).unwrap();
+            let finished_at =
+                chrono::DateTime::parse_from_rfc3339(item["finished_at"].as_str().unwrap())
+                    .unwrap();
+            assert!(started_at <= finished_at, "got: {}", item);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_measure_memory() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_measure_memory");
+        let mut parabuilder =
+            makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1)).measure_memory(true);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        for item in run_data.as_array().unwrap() {
+            assert!(item["max_rss_kb"].as_u64().unwrap() > 0, "got: {}", item);
+        }
+
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1));
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert!(run_data[0].get("max_rss_kb").is_none());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_run_report() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_run_report");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .run_bash_script(r#"[ "$(./main)" != 2 ]"#);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2}), json!({"N": 3})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let report = parabuilder.run_report().unwrap();
+        assert_eq!(report.compile_success(), 3);
+        assert_eq!(report.run_success(), Some(2));
+        assert_eq!(report.run_failed(), Some(1));
+        assert!(report.compile_errors.is_empty());
+        assert!(report.run_errors.is_empty());
+        let mut processed_ids = report.processed_ids.clone();
+        processed_ids.sort();
+        assert_eq!(processed_ids, vec![0, 1, 2]);
+
+        let mut split_report = report.clone();
+        split_report.split_run_errors();
+        assert_eq!(split_report.run_data.as_array().unwrap().len(), 2);
+        assert_eq!(split_report.run_errors.len(), 1);
+        assert_eq!(split_report.run_errors[0]["data"], json!({"N": 2}));
+        assert_eq!(split_report.run_success(), Some(2));
+        assert_eq!(split_report.run_failed(), Some(1));
+
+        let report = RunReport::from((JsonValue::Null, vec![json!({"N": 1})], vec![0]));
+        assert_eq!(report.compile_success(), 0);
+        assert_eq!(report.run_success(), None);
+        assert_eq!(report.run_failed(), None);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_split_run_results() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_split_run_results");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .run_bash_script(r#"[ "$(./main)" != 2 ]"#)
+            .split_run_results(true);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2}), json!({"N": 3})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let report = parabuilder.run_report().unwrap();
+        assert!(report
+            .run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|data| data["status"] == 0));
+        assert_eq!(report.run_data.as_array().unwrap().len(), 2);
+        assert_eq!(report.run_errors.len(), 1);
+        assert_eq!(report.run_errors[0]["data"], json!({"N": 2}));
+        assert_eq!(report.run_failed(), Some(1));
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_run_cwd() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_run_cwd");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .init_bash_script("mkdir -p run")
+                .run_cwd("run")
+                .run_bash_script("echo $PARABUILD_ID $(basename $PWD) $(../main)");
+            parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            let stdout = run_data[0]["stdout"].as_str().unwrap();
+            assert!(stdout.ends_with(" run 1\n"), "got: {}", stdout);
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compile_cwd() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_compile_cwd");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .init_bash_script("mkdir -p sub")
+            .compile_cwd("sub")
+            .compile_bash_script("make -B -C .. && basename $PWD > ../compile_cwd.txt");
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(
+            compile_error_datas.is_empty(),
+            "got: {:?}",
+            compile_error_datas
+        );
+        assert_eq!(run_data[0]["stdout"], "1\n");
+        assert_eq!(
+            std::fs::read_to_string(workspaces_path.join("workspace_0/compile_cwd.txt")).unwrap(),
+            "sub\n"
+        );
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_pause() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_pause");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1));
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        parabuilder.pause();
+        assert!(parabuilder.is_paused());
+        let worker_status = parabuilder.worker_status();
+        let (run_data, _, _) = std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(500));
+                // nothing is started while paused
+                assert_eq!(worker_status.builds_completed(), 0);
+                assert_eq!(worker_status.in_flight(), 0);
+                parabuilder.resume();
+            });
+            parabuilder.run().unwrap()
+        });
+        assert!(!parabuilder.is_paused());
+        assert_eq!(run_data.as_array().unwrap().len(), 2);
+        assert_eq!(worker_status.runs_completed(), 2);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pause_signal() {
+        // the default action of SIGUSR1 terminates the process, keep the handlers installed
+        // between the runs of the other tests
+        let pause_signal_handlers = PauseSignalHandlers::install();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_pause_signal");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1));
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        // nothing starts before the signal arrives
+        parabuilder.pause();
+        let worker_status = parabuilder.worker_status();
+        let (run_data, _, _) = std::thread::scope(|s| {
+            s.spawn(|| {
+                unsafe {
+                    libc::kill(libc::getpid(), libc::SIGUSR1);
+                }
+                parabuilder.resume();
+                std::thread::sleep(Duration::from_millis(300));
+                // only SIGUSR2 lifts a pause by signal
+                assert!(parabuilder.is_paused());
+                assert_eq!(worker_status.builds_completed(), 0);
+                unsafe {
+                    libc::kill(libc::getpid(), libc::SIGUSR2);
+                }
+            });
+            parabuilder.run().unwrap()
+        });
+        assert!(!parabuilder.is_paused());
+        assert_eq!(run_data.as_array().unwrap().len(), 2);
+        drop(pause_signal_handlers);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_scripts_in_project() {
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(project_path.join("main.txt.template"), "{{N}}").unwrap();
+        std::fs::create_dir(project_path.join("scripts")).unwrap();
+        std::fs::write(
+            project_path.join("scripts/compile.sh"),
+            "cp main.txt out.txt\necho {{N}} >> out.txt\n",
+        )
+        .unwrap();
+        std::fs::write(project_path.join("scripts/run.sh"), "cat out.txt\n").unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_scripts_in_project");
+        let parabuilder = Parabuilder::new(
+            &project_path,
+            &workspaces_path,
+            "main.txt.template",
+            &["out.txt"],
+        )
+        .init_bash_script("")
+        .compile_script_in_project("scripts/compile.sh")
+        .run_script_in_project("scripts/run.sh")
+        .templated_scripts(true)
+        .disable_progress_bar(true)
+        .without_rsync(true)
+        .no_cache(true);
+        let rendered = parabuilder.render_data(&json!({"N": 1})).unwrap();
+        assert_eq!(
+            rendered[1],
+            (
+                PathBuf::from("<compile script>"),
+                "cp main.txt out.txt\necho 1 >> out.txt\n".to_string()
+            )
+        );
+        let problems = Parabuilder::new(&project_path, &workspaces_path, "", &["out.txt"])
+            .compile_script_in_project("scripts/missing.sh")
+            .without_rsync(true)
+            .validate()
+            .unwrap_err();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("missing.sh"), "got: {:?}", problems);
+        let mut parabuilder = parabuilder;
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(compile_error_datas.is_empty());
+        let mut stdouts: Vec<&str> = run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["stdout"].as_str().unwrap())
+            .collect();
+        stdouts.sort();
+        assert_eq!(stdouts, vec!["11\n", "22\n"]);
+
+        // each workspace runs its own copy of the scripts
+        std::fs::write(
+            workspaces_path.join("workspace_0/scripts/compile.sh"),
+            "cp main.txt out.txt\necho {{N}}{{N}} >> out.txt\n",
+        )
+        .unwrap();
+        std::fs::write(
+            workspaces_path.join("workspace_exe_0/scripts/run.sh"),
+            "echo run\ncat out.txt\n",
+        )
+        .unwrap();
+        parabuilder.data_queue_receiver = None;
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(compile_error_datas.is_empty());
+        assert_eq!(run_data[0]["stdout"], "run\n111\n");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn test_oversubscription_warning() {
+        assert_eq!(
+            oversubscription_warning(8, 1, RunMethod::OutOfPlace(8), 8, 8),
+            None
+        );
+        assert_eq!(
+            oversubscription_warning(64, 1, RunMethod::No, 0, 8).unwrap(),
+            "64 jobs (64 build workers x 1 compile jobs) may oversubscribe the 8 available CPUs, see `build_workers_auto()`"
+        );
+        assert!(oversubscription_warning(8, 2, RunMethod::OutOfPlace(1), 1, 8).is_some());
+        // the exclusive runs do not overlap the builds
+        assert_eq!(
+            oversubscription_warning(16, 1, RunMethod::Exclusive(16), 16, 8),
+            None
+        );
+        let parabuilder = Parabuilder::new(".", "workspaces", "", &["main"])
+            .compile_jobs(available_cpus() + 1)
+            .build_workers_auto();
+        assert_eq!(parabuilder.build_workers, 1);
+        let parabuilder = Parabuilder::new(".", "workspaces", "", &["main"]).build_workers_auto();
+        assert_eq!(parabuilder.build_workers, available_cpus());
+    }
+
+    #[test]
+    fn test_warmup_runs() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_warmup_runs");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .warmup_runs(2)
+                .run_bash_script("echo x >> runs.log && ./main && wc -l < runs.log");
+            parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            assert_eq!(run_data.as_array().unwrap().len(), 1);
+            assert_eq!(run_data[0]["stdout"], "1\n3\n");
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_failed_warmup_runs() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_failed_warmup_runs");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .warmup_runs(2)
+                .panic_on_run_error(true)
+                .run_bash_script("test -f warmed || { touch warmed; exit 1; }; ./main");
+            parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            assert_eq!(run_data.as_array().unwrap().len(), 1);
+            assert_eq!(run_data[0]["stdout"], "1\n");
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_cpus() {
+        let cpus = allowed_cpus();
+        let cpu = *cpus.last().unwrap();
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_run_cpus");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .run_cpus(vec![cpu])
+                .run_bash_script("grep Cpus_allowed_list /proc/self/status");
+            parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            let stdout = run_data[0]["stdout"].as_str().unwrap();
+            assert_eq!(
+                stdout.split_whitespace().last(),
+                Some(cpu.to_string().as_str()),
+                "got: {}",
+                stdout
+            );
+            std::fs::remove_dir_all(workspaces_path).unwrap();
         }
     }
 
-    fn build_worker(
-        &self,
-        workspace_path: PathBuf,
-        executable_queue_sender: Sender<(usize, JsonValue)>,
-        build_pb: ProgressBar,
-        run_pb: ProgressBar,
-        stop_flag: Arc<AtomicBool>,
-        start_time: String,
-    ) -> std::thread::JoinHandle<(JsonValue, Vec<JsonValue>, Vec<usize>)> {
-        let template_path = self.project_path.join(&self.template_file);
-        let targets_path: Vec<PathBuf> = self
-            .target_files
-            .iter()
-            .map(|target_file| workspace_path.join(target_file).to_path_buf())
-            .collect();
-        let compile_bash_script = self.compile_bash_script.clone();
-        let template_output_file = if self.in_place_template {
-            self.template_file.clone()
-        } else {
-            self.template_file.with_extension("")
-        };
-        let target_files_base = self.target_files_base.clone();
-        let temp_target_path_dir = self.temp_target_path_dir.clone();
-        let data_queue_receiver = self.data_queue_receiver.as_ref().unwrap().clone();
-        let run_method = self.run_method;
-        let run_func = self.run_func_data;
-        let compilation_error_handling_method = self.compilation_error_handling_method;
+    #[test]
+    fn test_panic_on_run_error() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_panic_on_run_error");
+            let log_path = std::fs::canonicalize(tempdir().unwrap().into_path())
+                .unwrap()
+                .join("runs.log");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .panic_on_run_error(true)
+                .run_bash_script(&format!(
+                    "echo x >> {} && test $(./main) -ne 1",
+                    log_path.display()
+                ));
+            parabuilder
+                .set_datas((1..=20).map(|n| json!({"N": n})).collect())
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| parabuilder.run()));
+            assert!(result.is_err());
+            let runs = std::fs::read_to_string(&log_path).unwrap().lines().count();
+            assert!(runs <= 2, "got: {}", runs);
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
 
-        let template_output_path = workspace_path.join(&template_output_file);
-        let mut handlebars = Handlebars::new();
-        if template_path.exists() && template_path.is_file() {
-            handlebars.register_helper("default", Box::new(default_value_helper));
-            handlebars
-                .register_template_string("tpl", std::fs::read_to_string(&template_path).unwrap())
+    #[test]
+    fn test_templated_scripts() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_templated_scripts");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .templated_scripts(true)
+                .strict_templates(true)
+                .compile_bash_script("make -B CPPFLAGS=-DN={{mul N 10}}")
+                .run_bash_script("echo {{add N 1}} '{{mode}}' $(./main)");
+            parabuilder
+                .set_datas(vec![
+                    json!({"N": 1, "mode": "a&b"}),
+                    json!({"N": 2, "mode": "fast"}),
+                    json!({"N": 3}),
+                ])
                 .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+            let mut stdouts: Vec<&str> = run_data
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["stdout"].as_str().unwrap())
+                .collect();
+            stdouts.sort();
+            assert_eq!(stdouts, vec!["2 a&b 10\n", "3 fast 20\n"]);
+            assert_eq!(compile_error_datas.len(), 1);
+            assert_eq!(compile_error_datas[0]["data"], json!({"N": 3}));
+            assert_eq!(compile_error_datas[0]["category"], "render_error");
+            assert!(compile_error_datas[0]["render_error"].is_string());
+            std::fs::remove_dir_all(workspaces_path).unwrap();
         }
-        let mut run_data = JsonValue::Null;
-        let mut compile_error_datas = Vec::new();
-        let run_bash_script = self.run_bash_script.clone();
-        let enable_cppflags = self.enable_cppflags;
-        let disable_progress_bar = self.disable_progress_bar;
-        let mpb = self.mpb.clone();
-        let autosave_dir = self.autosave_dir.clone();
-        let autosave_interval = self.autosave_interval;
-        let gpu_devices = self.gpu_devices.clone();
-        std::thread::spawn(move || {
-            let uuid = Uuid::new_v4();
-            let mut processed_data_ids = Vec::new();
-            let sp = Self::add_spinner2(
-                disable_progress_bar || !matches!(run_method, RunMethod::InPlace),
-                &mpb,
-                serde_json::to_string_pretty(&JsonValue::Null).unwrap(),
-            );
-            let mut autosave_last_time = Instant::now();
-            for (i, data) in data_queue_receiver.iter() {
-                let mut cppflags_val = "-DPARABUILD=ON ".to_string();
-                if enable_cppflags {
-                    /* {"key":value} => -Dkey=value*/
-                    for (key, value) in data.as_object().unwrap().iter() {
-                        cppflags_val.push_str(&format!("-D{}={} ", key, value));
-                    }
-                }
-                if handlebars.has_template("tpl") {
-                    let mut template_output = std::fs::File::create(&template_output_path)
-                        .expect(format!("Failed to create {:?}", template_output_path).as_str());
-                    handlebars
-                        .render_to_write("tpl", &data, &template_output)
-                        .expect(format!("Failed to render {:?}", template_output_path).as_str());
-                    template_output.flush().unwrap();
-                }
-                let mut output = Command::new("bash");
-                let mut output = output
-                    .arg("-c")
-                    .arg(&compile_bash_script)
-                    .current_dir(&workspace_path);
-                if enable_cppflags {
-                    output = output.env("CPPFLAGS", cppflags_val);
-                }
-                let output = output.output();
-                build_pb.inc(1);
-                if output.is_err() || output.is_ok() && !output.as_ref().unwrap().status.success() {
-                    if stop_flag.load(Ordering::Relaxed) {
-                        // current data should be saved, ignore here
-                    } else {
-                        processed_data_ids.push(i);
-                        if compilation_error_handling_method
-                            == CompliationErrorHandlingMethod::Panic
-                        {
-                            if let Ok(output) = output {
-                                panic!(
-                                    "Compilation script failed in data: {:?} with output: {:?}",
-                                    data, output
-                                );
-                            } else {
-                                panic!("Compilation script failed in data: {:?}", data);
-                            }
-                        } else {
-                            if !matches!(run_method, RunMethod::No) {
-                                run_pb.inc(1);
-                            }
-                            match compilation_error_handling_method {
-                                CompliationErrorHandlingMethod::Collect => {
-                                    compile_error_datas.push(data.clone());
-                                    continue;
-                                }
-                                CompliationErrorHandlingMethod::Ignore => {
-                                    continue;
-                                }
-                                CompliationErrorHandlingMethod::Panic => {
-                                    panic!("Compilation script failed in data: {:?}", data);
-                                }
-                            }
-                        }
-                    }
-                }
-                if stop_flag.load(Ordering::Relaxed) {
-                    Self::autosave_save(
-                        &autosave_dir,
-                        &start_time,
-                        &run_data,
-                        &compile_error_datas,
-                        &processed_data_ids,
-                        uuid,
-                    );
-                    break;
-                }
-                match run_method {
-                    RunMethod::InPlace => {
-                        // run
-                        let last_data = run_func(
-                            &std::fs::canonicalize(&workspace_path).unwrap(),
-                            &run_bash_script,
-                            &data,
-                            &mut run_data,
-                            &stop_flag,
-                            &gpu_devices,
-                        )
-                        .unwrap();
-                        sp.set_message(serde_json::to_string_pretty(&last_data).unwrap());
-                        run_pb.inc(1);
-                    }
-                    RunMethod::No | RunMethod::Exclusive(_) | RunMethod::OutOfPlace(_) => {
-                        for (target_path, target_file_base) in
-                            targets_path.iter().zip(target_files_base.iter())
-                        {
-                            let to_target_executable_path_file =
-                                format!("{}_{}", &target_file_base, i);
-                            let to_target_executable_path =
-                                temp_target_path_dir.join(&to_target_executable_path_file);
-                            std::fs::copy(&target_path, &to_target_executable_path).unwrap();
-                        }
-                        match run_method {
-                            RunMethod::No => {
-                                let to_metadata_path =
-                                    temp_target_path_dir.join(format!("data_{}.json", i));
-                                std::fs::write(&to_metadata_path, data.to_string()).unwrap();
-                            }
-                            RunMethod::OutOfPlace(_) | RunMethod::Exclusive(_) => {
-                                executable_queue_sender.send((i, data.clone())).unwrap();
-                            }
-                            _ => panic!("Unexpected run method"),
-                        }
+    }
+
+    #[test]
+    fn test_worker_status() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_worker_status");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method);
+            let worker_status = parabuilder.worker_status();
+            let runs_in_flight = Arc::new(Mutex::new(vec![]));
+            parabuilder = parabuilder.progress_callback({
+                let worker_status = worker_status.clone();
+                let runs_in_flight = runs_in_flight.clone();
+                move |event| {
+                    if let ProgressEvent::RunStarted { .. } = event {
+                        runs_in_flight
+                            .lock()
+                            .unwrap()
+                            .push(worker_status.runs_in_flight());
                     }
                 }
-                if stop_flag.load(Ordering::Relaxed) {
-                    Self::autosave_save(
-                        &autosave_dir,
-                        &start_time,
-                        &run_data,
-                        &compile_error_datas,
-                        &processed_data_ids,
-                        uuid,
-                    );
-                    break;
-                }
-                match run_method {
-                    RunMethod::InPlace | RunMethod::No => {
-                        processed_data_ids.push(i);
-                    }
-                    _ => {}
+            });
+            parabuilder
+                .set_datas((1..=3).map(|n| json!({"N": n})).collect())
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let polled = std::thread::scope(|scope| {
+                let handle = scope.spawn(|| parabuilder.run().unwrap());
+                let mut polled = 0;
+                while !handle.is_finished() {
+                    polled = polled.max(worker_status.builds_completed());
+                    std::thread::sleep(Duration::from_millis(1));
                 }
-                if autosave_interval > 0
-                    && autosave_last_time.elapsed().as_secs() > autosave_interval
-                {
-                    Self::autosave_save(
-                        &autosave_dir,
-                        &start_time,
-                        &run_data,
-                        &compile_error_datas,
-                        &processed_data_ids,
-                        uuid,
+                handle.join().unwrap();
+                polled
+            });
+            assert!(polled <= 3);
+            assert_eq!(worker_status.builds_completed(), 3);
+            assert_eq!(worker_status.runs_completed(), 3);
+            assert_eq!(worker_status.in_flight(), 0);
+            let runs_in_flight = runs_in_flight.lock().unwrap();
+            assert_eq!(runs_in_flight.len(), 3);
+            assert!(runs_in_flight.iter().all(|&runs| runs >= 1));
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    fn run_func_data_id(
+        _: &PathBuf,
+        _: &str,
+        data: &JsonValue,
+        run_data: &mut JsonValue,
+        _: &Arc<AtomicBool>,
+        context: &RunContext,
+    ) -> Result<JsonValue, Box<dyn Error>> {
+        run_func_data_post_(json!({"data": data, "data_id": context.data_id}), run_data)
+    }
+
+    #[test]
+    fn test_data_id() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(2)] {
+            for custom_run_func in [false, true] {
+                let workspaces_path = PathBuf::from("tests/workspaces_test_data_id");
+                let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method);
+                let id_field = if custom_run_func {
+                    parabuilder = parabuilder.run_func(run_func_data_id);
+                    "data_id"
+                } else {
+                    "id"
+                };
+                parabuilder
+                    .set_datas((1..=4).map(|n| json!({"N": n})).collect())
+                    .unwrap();
+                parabuilder.init_workspace().unwrap();
+                let (run_data, _, _) = parabuilder.run().unwrap();
+                let run_data = run_data.as_array().unwrap();
+                assert_eq!(run_data.len(), 4);
+                for item in run_data.iter() {
+                    assert_eq!(
+                        item[id_field].as_u64().unwrap() + 1,
+                        item["data"]["N"].as_u64().unwrap()
                     );
-                    autosave_last_time = Instant::now();
                 }
+                std::fs::remove_dir_all(workspaces_path).unwrap();
             }
-            (run_data, compile_error_datas, processed_data_ids)
-        })
+        }
     }
 
-    fn run_worker(
-        &self,
-        workspace_path: PathBuf,
-        executable_queue_receiver: Receiver<(usize, JsonValue)>,
-        run_pb: ProgressBar,
-        stop_flag: Arc<AtomicBool>,
-        start_time: String,
-    ) -> std::thread::JoinHandle<(JsonValue, Vec<usize>)> {
-        let uuid = Uuid::new_v4();
-        let targets_path: Vec<PathBuf> = self
-            .target_files
+    #[test]
+    fn test_sort_output_by_id() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_sort_output_by_id");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(2))
+            .sort_output_by_id(true)
+            .run_bash_script("sleep 0.$((5 - $(./main))) && ./main");
+        parabuilder
+            .set_datas((1..=4).map(|n| json!({"N": n})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        let ids: Vec<u64> = run_data
+            .as_array()
+            .unwrap()
             .iter()
-            .map(|target_file| workspace_path.join(target_file).to_path_buf())
+            .map(|item| item["id"].as_u64().unwrap())
             .collect();
-        let target_files_base = self.target_files_base.clone();
-        let run_func = self.run_func_data;
-        let mut run_data = JsonValue::Null;
-        let disable_progress_bar = self.disable_progress_bar;
-        let mpb = self.mpb.clone();
-        let run_bash_script = self.run_bash_script.clone();
-        let temp_target_path_dir = self.temp_target_path_dir.clone();
-        let autosave_dir = self.autosave_dir.clone();
-        let autosave_interval = self.autosave_interval;
-        let gpu_devices = self.gpu_devices.clone();
-        std::thread::spawn(move || {
-            let mut processed_data_ids = Vec::new();
-            let mut autosave_last_time = Instant::now();
-            let sp = Self::add_spinner2(
-                disable_progress_bar,
-                &mpb,
-                serde_json::to_string_pretty(&JsonValue::Null).unwrap(),
-            );
-            for (i, data) in executable_queue_receiver.iter() {
-                for (target_path, target_file_base) in
-                    targets_path.iter().zip(target_files_base.iter())
-                {
-                    let to_target_path_file = format!("{}_{}", &target_file_base, i);
-                    let to_target_executable_path = temp_target_path_dir.join(&to_target_path_file);
-                    std::fs::rename(&to_target_executable_path, &target_path).unwrap();
-                }
-                for target_path in targets_path.iter() {
-                    wait_until_file_ready(&target_path).unwrap();
-                }
-                let last_data = run_func(
-                    &std::fs::canonicalize(&workspace_path).unwrap(),
-                    &run_bash_script,
-                    &data,
-                    &mut run_data,
-                    &stop_flag,
-                    &gpu_devices,
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+        assert_eq!(run_data[3]["stdout"], "4\n");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_copy_excludes() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_copy_excludes");
+        let parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .copy_excludes(&["Makefile"]);
+        parabuilder.init_workspace().unwrap();
+        for workspace in ["workspace_0", "workspace_1", "workspace_exe_0"] {
+            let workspace_path = workspaces_path.join(workspace);
+            assert!(workspace_path.join("src/main.cpp").exists());
+            assert!(!workspace_path.join("Makefile").exists());
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_cartesian_datas() {
+        assert_eq!(
+            Parabuilder::cartesian_datas([
+                ("N", vec![json!(1), json!(2)]),
+                ("B", vec![json!(32), json!(64)]),
+            ]),
+            vec![
+                json!({"N": 1, "B": 32}),
+                json!({"N": 1, "B": 64}),
+                json!({"N": 2, "B": 32}),
+                json!({"N": 2, "B": 64}),
+            ]
+        );
+        assert_eq!(
+            Parabuilder::cartesian_datas(Vec::<(String, Vec<JsonValue>)>::new()),
+            vec![json!({})]
+        );
+        assert!(Parabuilder::cartesian_datas([("N", vec![])]).is_empty());
+    }
+
+    #[test]
+    fn test_run_only_labels() {
+        assert!(Parabuilder::has_label(
+            &json!({"__label": ["a", "b"]}),
+            &["b"]
+        ));
+        assert!(!Parabuilder::has_label(&json!({"__label": "a"}), &["b"]));
+        assert!(!Parabuilder::has_label(&json!({"N": 1}), &["b"]));
+
+        let workspaces_path = PathBuf::from("tests/workspaces_test_run_only_labels");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .sort_output_by_id(true)
+            .run_only_labels(&["baseline"]);
+        parabuilder
+            .set_datas(vec![
+                json!({"N": 1, "__label": "baseline"}),
+                json!({"N": 2, "__label": "experimental"}),
+                json!({"N": 3}),
+                json!({"N": 4, "__label": "baseline"}),
+            ])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, mut processed_data_ids) = parabuilder.run().unwrap();
+        processed_data_ids.sort();
+        assert_eq!(processed_data_ids, vec![0, 3]);
+        let results: Vec<(u64, &str, &str)> = run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                (
+                    item["id"].as_u64().unwrap(),
+                    item["label"].as_str().unwrap(),
+                    item["stdout"].as_str().unwrap(),
                 )
-                .unwrap();
-                if stop_flag.load(Ordering::Relaxed) {
-                    Self::autosave_save(
-                        &autosave_dir,
-                        &start_time,
-                        &run_data,
-                        &vec![],
-                        &processed_data_ids,
-                        uuid,
-                    );
-                    break;
-                }
-                sp.set_message(serde_json::to_string_pretty(&last_data).unwrap());
-                run_pb.inc(1);
-                processed_data_ids.push(i);
-                if autosave_interval > 0
-                    && autosave_last_time.elapsed().as_secs() > autosave_interval
-                {
-                    Self::autosave_save(
-                        &autosave_dir,
-                        &start_time,
-                        &run_data,
-                        &vec![],
-                        &processed_data_ids,
-                        uuid,
-                    );
-                    autosave_last_time = Instant::now();
-                }
+            })
+            .collect();
+        assert_eq!(
+            results,
+            vec![(0, "baseline", "1\n"), (3, "baseline", "4\n")]
+        );
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_label_cppflags() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_label_cppflags");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .compile_bash_script(r#"echo "$CPPFLAGS" > cppflags.txt && make -B"#)
+            .run_bash_script("cat cppflags.txt")
+            .parabuild_define(false);
+        parabuilder
+            .set_datas(vec![json!({"N": 1, "__label": "baseline"})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["stdout"], "-DN=1 \n");
+        assert_eq!(run_data[0]["label"], "baseline");
+        assert_eq!(
+            data_to_envs("PARA_", &json!({"N": 1, "__label": ["a", "b"]})),
+            vec![("PARA_N".to_string(), "1".to_string())]
+        );
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_data_sample() {
+        assert_eq!(DataSample::Stride(3).sample_ids(8).unwrap(), vec![0, 3, 6]);
+        assert!(DataSample::Stride(0).sample_ids(8).is_err());
+        let sample = DataSample::Random { count: 3, seed: 7 };
+        let ids = sample.sample_ids(10).unwrap();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.windows(2).all(|w| w[0] < w[1]) && ids[2] < 10);
+        assert_eq!(sample.sample_ids(10).unwrap(), ids);
+        assert_eq!(
+            DataSample::Random { count: 5, seed: 0 }
+                .sample_ids(3)
+                .unwrap(),
+            vec![0, 1, 2]
+        );
+
+        let workspaces_path = PathBuf::from("tests/workspaces_test_data_sample");
+        let mut parabuilder =
+            makefile_parabuilder(&workspaces_path, RunMethod::InPlace).sort_output_by_id(true);
+        parabuilder
+            .set_datas_sampled(
+                (1..=7).map(|n| json!({"N": n})).collect(),
+                DataSample::Stride(3),
+            )
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, processed_data_ids) = parabuilder.run().unwrap();
+        let ids: Vec<u64> = run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(ids, vec![0, 3, 6]);
+        let mut processed_data_ids = processed_data_ids;
+        processed_data_ids.sort();
+        assert_eq!(processed_data_ids, vec![0, 3, 6]);
+        assert!(parabuilder.unprocessed_datas().is_empty());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_init_workspace_failure() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_init_workspace_failure");
+        let parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .init_bash_script("echo configure failed >&2; exit 3");
+        let e = parabuilder.init_workspace().unwrap_err().to_string();
+        for workspace in ["workspace_0", "workspace_1", "workspace_exe_0"] {
+            assert!(e.contains(&format!("Init bash script failed in {}", workspace)));
+        }
+        assert!(e.contains("configure failed"));
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_render_data() {
+        let parabuilder = Parabuilder::new(
+            crate::test_constants::EXAMPLE_CMAKE_PROJECT_PATH,
+            "tests/workspaces_test_render_data",
+            "src/main.cpp.template",
+            &["build/main"],
+        )
+        .templated_scripts(true)
+        .run_bash_script("./build/main {{N}}");
+        let rendered = parabuilder.render_data(&json!({"N": 7})).unwrap();
+        assert_eq!(rendered[0].0, PathBuf::from("src/main.cpp"));
+        assert!(rendered[0].1.contains('7'));
+        assert_eq!(
+            rendered.last().unwrap(),
+            &(PathBuf::from("<run script>"), "./build/main 7".to_string())
+        );
+        let parabuilder = parabuilder.strict_templates(true);
+        assert!(parabuilder.render_data(&json!({})).is_err());
+        assert!(!PathBuf::from("tests/workspaces_test_render_data").exists());
+    }
+
+    #[test]
+    fn test_lazy_run_workspace_init() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_lazy_run_workspace_init");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(3))
+            .lazy_run_workspace_init(true);
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let run_workspaces = || {
+            (0..3)
+                .filter(|i| {
+                    workspaces_path
+                        .join(format!("workspace_exe_{}", i))
+                        .exists()
+                })
+                .count()
+        };
+        assert!(workspaces_path.join("workspace_0").exists());
+        assert_eq!(run_workspaces(), 0);
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["stdout"], "1\n");
+        assert_eq!(run_workspaces(), 1);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_repeat_runs() {
+        let stats = sample_stats(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(stats["min"], 1.0);
+        assert_eq!(stats["max"], 4.0);
+        assert_eq!(stats["mean"], 2.5);
+        assert!((stats["stddev"].as_f64().unwrap() - 1.25f64.sqrt()).abs() < 1e-12);
+
+        let workspaces_path = PathBuf::from("tests/workspaces_test_repeat_runs");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .repeat_runs(3)
+            .metric_extractor(|stdout| stdout.trim().parse::<f64>().ok().map(|n| n * 2.0));
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        for item in run_data.as_array().unwrap() {
+            let n = item["data"]["N"].as_f64().unwrap();
+            assert_eq!(item["repeats"], 3);
+            assert_eq!(item["stats"]["samples"], json!([n * 2.0, n * 2.0, n * 2.0]));
+            assert_eq!(item["stats"]["stddev"], 0.0);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_data_queue_capacity() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_data_queue_capacity");
+        let mut parabuilder =
+            makefile_parabuilder(&workspaces_path, RunMethod::InPlace).data_queue_capacity(1);
+        let data_queue_sender = parabuilder.get_data_queue_sender().unwrap();
+        assert_eq!(data_queue_sender.capacity(), Some(1));
+        parabuilder.init_workspace().unwrap();
+        let producer = std::thread::spawn(move || {
+            for i in 0..4 {
+                data_queue_sender.send((i, json!({"N": i + 1}))).unwrap();
+                assert!(data_queue_sender.len() <= 1);
             }
-            (run_data, processed_data_ids)
-        })
+        });
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        producer.join().unwrap();
+        assert_eq!(run_data.as_array().unwrap().len(), 4);
+
+        parabuilder.close_data_queue();
+        let sender = parabuilder.get_data_queue_sender().unwrap();
+        sender.send((0, json!({"N": 1}))).unwrap();
+        parabuilder.close_data_queue();
+        assert!(sender.send((1, json!({"N": 2}))).is_err());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
-    pub fn gather_data(
-        &self,
-        run_data_array: Vec<JsonValue>,
-        compile_error_datas: Vec<JsonValue>,
-        processed_data_ids: Vec<usize>,
-    ) -> Result<(JsonValue, Vec<JsonValue>, Vec<usize>), Box<dyn Error>> {
-        let run_data_array: Vec<JsonValue> = run_data_array
+    #[test]
+    fn test_compile_error_categories() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_compile_error_categories");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::No)
+            .compile_bash_script(
+                r#"case "$CPPFLAGS" in *N=1\ *) echo "error: no matching function" >&2; exit 1;;
+                *N=2\ *) echo "ptxas error" >&2; exit 1;; *N=3\ *) exit 1;; esac; make -B"#,
+            )
+            .compile_error_categories(&[("no matching function", "overload"), ("ptxas", "ptxas")]);
+        parabuilder
+            .set_datas((1..=5).map(|n| json!({"N": n})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let report: RunReport = parabuilder.run().unwrap().into();
+        let mut compile_errors = report.compile_errors.clone();
+        compile_errors.sort_by_key(|e| e["data"]["N"].as_u64());
+        assert_eq!(
+            compile_errors,
+            vec![
+                json!({"data": {"N": 1}, "category": "overload"}),
+                json!({"data": {"N": 2}, "category": "ptxas"}),
+                json!({"data": {"N": 3}, "category": null}),
+            ]
+        );
+        let mut categories = report.compile_error_categories();
+        categories.sort();
+        assert_eq!(
+            categories,
+            vec![
+                ("overload".to_string(), 1),
+                ("ptxas".to_string(), 1),
+                ("uncategorized".to_string(), 1)
+            ]
+        );
+
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::No)
+            .compile_error_categories(&[("(", "bad")]);
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        assert!(parabuilder.run().is_err());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_upgrade_compile_error_record() {
+        let records = vec![
+            json!({"N": 1}),
+            json!({"data": {"N": 2}, "timeout": true}),
+            json!({"data": {"N": 3}, "render_error": "missing N"}),
+            json!({"data": {"N": 4}, "category": "overload"}),
+        ];
+        let upgraded: Vec<JsonValue> = records
             .into_iter()
-            .filter(|item| !item.is_null())
+            .map(upgrade_compile_error_record)
             .collect();
-        let run_datas = if self.run_method == RunMethod::No {
-            JsonValue::Null
-        } else if self.auto_gather_array_data && run_data_array.iter().all(|item| item.is_array()) {
-            let mut run_data = Vec::new();
-            for run_data_item in run_data_array {
-                run_data.extend(run_data_item.as_array().unwrap().iter().cloned());
-            }
-            JsonValue::Array(run_data)
-        } else {
-            // just return array json
-            JsonValue::Array(run_data_array)
-        };
-        Ok((run_datas, compile_error_datas, processed_data_ids))
+        assert_eq!(
+            upgraded,
+            vec![
+                json!({"data": {"N": 1}, "category": null}),
+                json!({"data": {"N": 2}, "category": "timeout"}),
+                json!({"data": {"N": 3}, "category": "render_error", "render_error": "missing N"}),
+                json!({"data": {"N": 4}, "category": "overload"}),
+            ]
+        );
+        let report = RunReport::from((JsonValue::Null, upgraded, vec![0, 1, 2, 3]));
+        assert_eq!(report.compile_error_categories().len(), 4);
+        assert!(report
+            .compile_error_categories()
+            .contains(&("timeout".to_string(), 1)));
     }
 
-    fn add_progress_bar<S: Into<String>, F: Into<Cow<'static, str>>>(
-        &self,
-        message: S,
-        total: u64,
-        finish_message: F,
-    ) -> ProgressBar {
-        if self.disable_progress_bar {
-            return ProgressBar::hidden();
+    #[test]
+    fn test_max_compile_errors() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_max_compile_errors");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::No)
+            .compile_bash_script("exit 1")
+            .max_compile_errors(2);
+        parabuilder
+            .set_datas((1..=20).map(|n| json!({"N": n})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (_, compile_error_datas, _) = parabuilder.run().unwrap();
+        // the other build worker may fail one more in flight
+        assert!((2..=3).contains(&compile_error_datas.len()));
+        let unprocessed_datas = parabuilder.unprocessed_datas();
+        assert_eq!(compile_error_datas.len() + unprocessed_datas.len(), 20);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_verify_func() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_verify_func");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .sort_output_by_id(true)
+            .verify_func(|data, this_data| {
+                this_data["stdout"].as_str().map(str::trim) == data["expect"].as_str()
+            });
+        parabuilder
+            .set_datas(vec![
+                json!({"N": 1, "expect": "1"}),
+                json!({"N": 2, "expect": "3"}),
+            ])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let report: RunReport = parabuilder.run().unwrap().into();
+        assert_eq!(report.run_data[0]["verified"], true);
+        assert_eq!(report.run_data[1]["verified"], false);
+        let failures = report.verification_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0]["data"]["N"], 2);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_extract_json() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_extract_json");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .run_bash_script(
+                r#"if [ "$(./main)" = 1 ]; then echo '{"result": {"latency": 1.5}}'; else ./main; fi"#,
+            )
+            .extract_json("/result/latency");
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let report: RunReport = parabuilder.run().unwrap().into();
+        let mut run_data = report.run_data.as_array().unwrap().clone();
+        run_data.sort_by_key(|item| item["data"]["N"].as_u64());
+        assert_eq!(run_data[0]["metric"], 1.5);
+        // a plain number is json too, but has no `/result/latency`
+        assert!(run_data[1].get("metric").is_none());
+        assert_eq!(run_data[1]["stdout"], "2\n");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_run_stdin_template() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_run_stdin_template");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .run_bash_script("read x; echo $x $(./main)")
+                .run_stdin_template("in{{N}}");
+            parabuilder
+                .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            let mut stdouts: Vec<&str> = run_data
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["stdout"].as_str().unwrap())
+                .collect();
+            stdouts.sort();
+            assert_eq!(stdouts, vec!["in1 1\n", "in2 2\n"]);
+            let rendered = parabuilder.render_data(&json!({"N": 3})).unwrap();
+            assert_eq!(
+                rendered.last().unwrap(),
+                &(PathBuf::from("<run stdin>"), "in3".to_string())
+            );
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_targets() {
+        let build_path = PathBuf::from("tests/workspaces_test_run_targets_build");
+        let mut parabuilder = makefile_parabuilder(&build_path, RunMethod::No);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        parabuilder.run().unwrap();
+        let manifest_path = build_path
+            .join(Parabuilder::TEMP_TARGET_PATH_DIR)
+            .join(Parabuilder::MANIFEST_FILE);
+        let manifest: JsonValue =
+            serde_json::from_str(&std::fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        assert_eq!(manifest[1]["id"], 1);
+        assert_eq!(manifest[1]["data"], json!({"N": 2}));
+        assert_eq!(manifest[1]["artifacts"], json!(["main_1"]));
+
+        let run_path = PathBuf::from("tests/workspaces_test_run_targets_run");
+        // nothing is compiled
+        let parabuilder = makefile_parabuilder(&run_path, RunMethod::OutOfPlace(1))
+            .compile_bash_script("exit 1")
+            .sort_output_by_id(true);
+        parabuilder.init_workspace().unwrap();
+        for _ in 0..2 {
+            let (run_data, compile_error_datas, processed_data_ids) =
+                parabuilder.run_targets(&manifest_path).unwrap();
+            assert!(compile_error_datas.is_empty());
+            assert_eq!(processed_data_ids.len(), 2);
+            assert_eq!(run_data[0]["stdout"], "1\n");
+            assert_eq!(run_data[1]["stdout"], "2\n");
         }
-        let sty = ProgressStyle::with_template(
-            "[{elapsed_precise}  ETA: {eta_precise}] [{per_sec}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+        std::fs::remove_dir_all(build_path).unwrap();
+        std::fs::remove_dir_all(run_path).unwrap();
+    }
+
+    #[test]
+    fn test_progress_style() {
+        let parabuilder = Parabuilder::new(".", "workspaces", "", &["main"])
+            .progress_style("{pos}/{len} {bar:10}")
+            .progress_ascii(true);
+        parabuilder
+            .mpb
+            .set_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let pb = parabuilder.add_progress_bar("Building", 4, "done");
+        assert_eq!(pb.length(), Some(4));
+
+        let mut parabuilder =
+            Parabuilder::new(".", "workspaces", "", &["main"]).progress_style("{pos:bad}");
+        assert!(parabuilder.progress_bar_style().is_err());
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        assert!(parabuilder.run().is_err());
+    }
+
+    #[test]
+    fn test_workspaces_nested_in_project() {
+        let project_path = tempdir().unwrap().into_path();
+        copy_dir_with_ignore(
+            crate::test_constants::EXAMPLE_MAKEFILE_PROJECT_PATH,
+            &project_path,
+            &[],
+            &ProgressBar::hidden(),
         )
         .unwrap();
-        self.mpb.add(
-            ProgressBar::new(total)
-                .with_message(message.into())
-                .with_style(sty)
-                .with_finish(ProgressFinish::AbandonWithMessage(finish_message.into())),
-        )
+        let workspaces_path = project_path.join("workspaces");
+        let parabuilder = Parabuilder::new(&project_path, &workspaces_path, "", &["main"])
+            .init_bash_script("")
+            .build_workers(2)
+            .disable_progress_bar(true)
+            .without_rsync(true);
+        // the second time the workspaces of the first one are under the project
+        for _ in 0..2 {
+            parabuilder.init_workspace().unwrap();
+        }
+        assert!(workspaces_path.join("workspace_0/Makefile").exists());
+        assert!(!workspaces_path.join("workspace_0/workspaces").exists());
+        assert!(!workspaces_path.join("workspace_1/workspaces").exists());
+        std::fs::remove_dir_all(project_path).unwrap();
     }
 
-    fn add_spinner<S: Into<String>>(&self, message: S) -> ProgressBar {
-        if self.disable_progress_bar {
-            return ProgressBar::hidden();
+    #[test]
+    fn test_run_args_template() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_run_args_template");
+            // `main` prints each of its arguments in brackets
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .compile_bash_script(
+                    r#"printf '#!/bin/sh\nprintf "[%%s]" "$@"\n' > main && chmod +x main"#,
+                )
+                .run_args_template(&["a {{N}}", "{{N}}"])
+                .sort_output_by_id(true);
+            parabuilder
+                .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            let stdouts: Vec<&str> = run_data
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["stdout"].as_str().unwrap())
+                .collect();
+            assert_eq!(stdouts, vec!["[a 1][1]", "[a 2][2]"]);
+            let rendered = parabuilder.render_data(&json!({"N": 3})).unwrap();
+            assert_eq!(
+                rendered.last().unwrap(),
+                &(PathBuf::from("<run command>"), "./main\na 3\n3".to_string())
+            );
+            // a custom run script is run through the shell as before
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .compile_bash_script(
+                    r#"printf '#!/bin/sh\nprintf "[%%s]" "$@"\n' > main && chmod +x main"#,
+                )
+                .run_args_template(&["{{N}}"])
+                .run_bash_script("./main x");
+            parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            assert_eq!(run_data[0]["stdout"], "[x]");
+            std::fs::remove_dir_all(workspaces_path).unwrap();
         }
-        let sp = self
-            .mpb
-            .add(ProgressBar::new_spinner().with_message(message.into()));
-        sp.enable_steady_tick(Duration::from_millis(100));
-        sp
     }
 
-    fn add_spinner2<S: Into<String>>(
-        disable_progress_bar: bool,
-        mpb: &MultiProgress,
-        message: S,
-    ) -> ProgressBar {
-        if disable_progress_bar {
-            return ProgressBar::hidden();
+    #[test]
+    fn test_run_args_template_with_run_cwd() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path =
+                PathBuf::from("tests/workspaces_test_run_args_template_with_run_cwd");
+            // `build/main` prints the directory it runs in and its arguments
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .init_bash_script("mkdir -p build run")
+                .compile_bash_script(
+                    r#"printf '#!/bin/sh\necho "$(basename $PWD) $*"\n' > build/main && chmod +x build/main"#,
+                )
+                .build_dir("build")
+                .run_cwd("run")
+                .run_args_template(&["{{N}}"]);
+            parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+            assert!(compile_error_datas.is_empty());
+            assert_eq!(run_data[0]["stdout"], "run 1\n");
+            std::fs::remove_dir_all(workspaces_path).unwrap();
         }
-        let sp = mpb.add(ProgressBar::new_spinner().with_message(message.into()));
-        sp.enable_steady_tick(Duration::from_millis(100));
-        sp
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    // use std::time::Instant;
-    use serde_json::json;
+    #[test]
+    fn test_missing_target_is_compile_error() {
+        for run_method in [RunMethod::OutOfPlace(1), RunMethod::No] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_missing_target");
+            // the compile succeeds without building `main`
+            let mut parabuilder =
+                makefile_parabuilder(&workspaces_path, run_method).compile_bash_script("true");
+            parabuilder
+                .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+            assert!(run_data.as_array().map_or(true, Vec::is_empty));
+            assert_eq!(compile_error_datas.len(), 2);
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
 
-    const EXAMPLE_PROJECT: &str = crate::test_constants::EXAMPLE_CMAKE_PROJECT_PATH;
-    const EXAMPLE_TEMPLATE_FILE: &str = "src/main.cpp.template";
-    const EXAMPLE_IN_PLACE_TEMPLATE_FILE: &str = "src/main.cpp";
-    const EXAMPLE_TARGET_EXECUTABLE_FILE: &str = "build/main";
-    const EXAMPLE_INIT_BASH_SCRIPT: &str = r#"
-        cmake -B build -S .
-        "#;
-    const EXAMPLE_IN_PLACE_INIT_BASH_SCRIPT: &str = r#"
-        cmake -B build -S . -DPARABUILD=ON
-        "#;
-    const EXAMPLE_COMPILE_BASH_SCRIPT: &str = r#"
-        cmake --build build --target all -- -B
-        "#;
+    #[test]
+    fn test_result_cache() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_result_cache");
+            let log_path = std::fs::canonicalize(tempdir().unwrap().into_path())
+                .unwrap()
+                .join("runs.log");
+            // each invocation is a new `Parabuilder` over the same workspaces
+            let run = |datas: Vec<JsonValue>| {
+                let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                    .no_cache(false)
+                    .result_cache(true)
+                    .sort_output_by_id(true)
+                    .run_bash_script(&format!("echo x >> {} && ./main", log_path.display()));
+                parabuilder.set_datas(datas).unwrap();
+                parabuilder.init_workspace().unwrap();
+                parabuilder.run().unwrap().0
+            };
+            let run_data = run(vec![json!({"N": 1}), json!({"N": 2})]);
+            assert_eq!(run_data.as_array().unwrap().len(), 2);
+            assert!(run_data[0]["cached"].is_null());
+            let run_data = run(vec![json!({"N": 2}), json!({"N": 3})]);
+            assert_eq!(run_data[0]["data"], json!({"N": 2}));
+            assert_eq!(run_data[0]["id"], 0);
+            assert_eq!(run_data[0]["cached"], true);
+            assert_eq!(run_data[0]["stdout"], "2\n");
+            assert!(run_data[1]["cached"].is_null());
+            let runs = std::fs::read_to_string(&log_path).unwrap().lines().count();
+            assert_eq!(runs, 3);
+            assert!(workspaces_path.join(Parabuilder::RESULT_CACHE_DIR).is_dir());
+            std::fs::remove_dir_all(&workspaces_path).unwrap();
+        }
+    }
 
     #[test]
-    fn test_workspaces_under_project_path() {
-        let example_project_path = std::fs::canonicalize(EXAMPLE_PROJECT).unwrap();
-        let workspaces_path = PathBuf::from("workspaces_under_project_path");
-        let parabuilder = Parabuilder::new(
-            ".",
+    fn test_on_workspace_ready() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_on_workspace_ready");
+        let ready = Arc::new(Mutex::new(Vec::new()));
+        let parabuilder = {
+            let ready = ready.clone();
+            makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+                .on_workspace_ready(move |i| ready.lock().unwrap().push(i))
+        };
+        parabuilder.init_workspace().unwrap();
+        let mut ready = ready.lock().unwrap().clone();
+        ready.sort();
+        // 2 build workspaces and then the run workspace
+        assert_eq!(ready, vec![0, 1, 2]);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_collect_warnings() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_collect_warnings");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .compile_bash_script(
+                r#"make -B && if [ "$PARABUILD_N" -gt 1 ]; then echo "main.cpp:1: warning: N > 1" >&2; fi"#,
+            )
+            .data_env_prefix("PARABUILD_")
+            .collect_warnings(true);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let report = parabuilder.run_report().unwrap();
+        assert_eq!(report.run_success(), Some(2));
+        assert_eq!(
+            report.warning_datas,
+            vec![json!({"id": 1, "data": {"N": 2}, "warnings": ["main.cpp:1: warning: N > 1"]})]
+        );
+
+        let parabuilder = parabuilder.warning_regex("(");
+        assert!(parabuilder.run().is_err());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_template_delimiters() {
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(
+            project_path.join("main.txt.template"),
+            "int a[2][1] = {{<<N>>}, {<<add N 1>>}};",
+        )
+        .unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_template_delimiters");
+        let mut parabuilder = Parabuilder::new(
+            &project_path,
             &workspaces_path,
-            example_project_path.join("src/main.cpp.template"),
-            &["build/main"],
+            "main.txt.template",
+            &["main.txt"],
         )
-        .init_bash_script(
-            format!(
-                r#"
-                cmake -B build -S {}
-                "#,
-                EXAMPLE_PROJECT
-            )
-            .as_str(),
+        .template_delimiters("<<", ">>")
+        .init_bash_script("")
+        .compile_bash_script("")
+        .run_bash_script("cat main.txt")
+        .disable_progress_bar(true)
+        .without_rsync(true)
+        .no_cache(true);
+        parabuilder.set_datas(vec![json!({"N": 10})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(
+            compile_error_datas.is_empty(),
+            "got: {:?}",
+            compile_error_datas
+        );
+        assert_eq!(run_data[0]["stdout"], "int a[2][1] = {{10}, {11}};");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn test_template_output_path_template() {
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(project_path.join("main.txt.template"), "{{N}}").unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_template_output_path_template");
+        let mut parabuilder = Parabuilder::new(
+            &project_path,
+            &workspaces_path,
+            "main.txt.template",
+            &["main.txt"],
         )
+        .template_output_path_template("{{dir}}/main_{{N}}.txt")
+        .init_bash_script("")
         .compile_bash_script(
-            r#"
-            cmake --build build --target all -- -B
-            "#,
+            r#"cp "$PARABUILD_TEMPLATE_OUTPUT" main.txt && echo " $(ls out)" >> main.txt"#,
         )
+        .run_bash_script("cat main.txt")
+        .build_workers(1)
+        .sort_output_by_id(true)
         .disable_progress_bar(true)
+        .without_rsync(true)
         .no_cache(true);
+        assert_eq!(
+            parabuilder
+                .render_data(&json!({"N": 1, "dir": "out"}))
+                .unwrap(),
+            vec![(PathBuf::from("out/main_1.txt"), "1".to_string())]
+        );
+        assert!(parabuilder
+            .render_data(&json!({"N": 1, "dir": ".."}))
+            .is_err());
+        parabuilder
+            .set_datas(vec![
+                json!({"N": 1, "dir": "out"}),
+                json!({"N": 2, "dir": "out"}),
+            ])
+            .unwrap();
         parabuilder.init_workspace().unwrap();
-        assert!(workspaces_path
-            .join(format!(
-                "workspace_0/{}/src/main.cpp.template",
-                EXAMPLE_PROJECT
-            ))
-            .exists());
-        assert!(workspaces_path.join("workspace_0/build").exists());
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(
+            compile_error_datas.is_empty(),
+            "got: {:?}",
+            compile_error_datas
+        );
+        assert_eq!(run_data[0]["stdout"], "1 main_1.txt\n");
+        // the file rendered for the first data is left in place
+        assert_eq!(run_data[1]["stdout"], "2 main_1.txt\nmain_2.txt\n");
         std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
     }
 
-    const SINGLETHREADED_N: i64 = 20;
-    const MULTITHREADED_N: i64 = 100;
-
-    fn parabuild_tester(
-        name: &str,
-        size: i64,
-        build_workers: usize,
-        run_method: RunMethod,
-        in_place_template: bool,
-        is_makefile_project: bool,
-    ) {
-        let project = if !is_makefile_project {
-            EXAMPLE_PROJECT
-        } else {
-            crate::test_constants::EXAMPLE_MAKEFILE_PROJECT_PATH
-        };
-        let mut datas = (1..=size)
-            .map(|i| json!({"N": i}))
-            .collect::<Vec<JsonValue>>();
-        if size == 0 {
-            datas.push(json!({}));
-        }
-        let error_data = json!({"N": "a"});
-        datas.push(error_data.clone());
-        let workspaces_path = PathBuf::from(format!("tests/workspaces_{}", name));
+    #[test]
+    fn test_strict_templates() {
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(
+            project_path.join("main.txt.template"),
+            "{{N}} {{default M 42}}",
+        )
+        .unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_strict_templates");
         let mut parabuilder = Parabuilder::new(
-            project,
+            &project_path,
             &workspaces_path,
-            if !is_makefile_project {
-                if in_place_template {
-                    EXAMPLE_IN_PLACE_TEMPLATE_FILE
-                } else {
-                    EXAMPLE_TEMPLATE_FILE
-                }
-            } else {
-                ""
-            },
-            if !is_makefile_project {
-                &[EXAMPLE_TARGET_EXECUTABLE_FILE]
-            } else {
-                &["main"]
-            },
+            "main.txt.template",
+            &["main.txt"],
         )
-        .init_bash_script(if !is_makefile_project {
-            if in_place_template {
-                EXAMPLE_IN_PLACE_INIT_BASH_SCRIPT
-            } else {
-                EXAMPLE_INIT_BASH_SCRIPT
-            }
-        } else {
-            ""
-        })
-        .compile_bash_script(if !is_makefile_project {
-            EXAMPLE_COMPILE_BASH_SCRIPT
-        } else {
-            "make -B"
-        })
-        .build_workers(build_workers)
-        .run_method(run_method)
-        .run_func(PANIC_ON_ERROR_DEFAULT_RUN_FUNC)
+        .strict_templates(true)
+        .init_bash_script("")
+        .compile_bash_script("")
+        .run_bash_script("cat main.txt")
         .disable_progress_bar(true)
-        .compilation_error_handling_method(CompliationErrorHandlingMethod::Collect)
-        .in_place_template(in_place_template)
-        .auto_gather_array_data(true)
-        .enable_cppflags(is_makefile_project);
+        .without_rsync(true)
+        .no_cache(true);
+        parabuilder
+            .set_datas(vec![
+                json!({"N": 1}),
+                json!({"M": 2}),
+                json!({"N": 3, "M": 4}),
+            ])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, mut processed_data_ids) = parabuilder.run().unwrap();
+        assert_eq!(compile_error_datas.len(), 1);
+        assert_eq!(compile_error_datas[0]["data"], json!({"M": 2}));
+        assert!(compile_error_datas[0]["render_error"].is_string());
+        let mut stdouts: Vec<&str> = run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["stdout"].as_str().unwrap())
+            .collect();
+        stdouts.sort();
+        assert_eq!(stdouts, vec!["1 42", "3 4"]);
+        processed_data_ids.sort();
+        assert_eq!(processed_data_ids, vec![0, 1, 2]);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn test_tee_build_logs() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_tee_build_logs");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::No)
+            .build_workers(1)
+            .tee_build_logs(true);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        parabuilder.run().unwrap();
+        let log = std::fs::read_to_string(workspaces_path.join("workspace_0").join(BUILD_LOG_FILE))
+            .unwrap();
+        let data_0 = log.find("==== data 0: {\"N\":1} (status: 0) ====").unwrap();
+        let data_1 = log.find("==== data 1: {\"N\":2} (status: 0) ====").unwrap();
+        assert!(data_0 < data_1, "got: {}", log);
+        assert!(log.contains("-DN=1  -o main"), "got: {}", log);
+        assert!(log.contains("-DN=2  -o main"), "got: {}", log);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_control_cancel() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_control_cancel");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1));
+        let control_sender = parabuilder.get_control_sender();
+        parabuilder
+            .set_datas((1..=4).map(|n| json!({"N": n})).collect())
+            .unwrap();
+        control_sender.send(ControlMsg::Cancel(1)).unwrap();
+        control_sender.send(ControlMsg::Cancel(3)).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, mut processed_data_ids) = parabuilder.run().unwrap();
+        processed_data_ids.sort();
+        assert_eq!(processed_data_ids, vec![0, 2]);
+        let mut stdouts: Vec<&str> = run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["stdout"].as_str().unwrap())
+            .collect();
+        stdouts.sort();
+        assert_eq!(stdouts, vec!["1\n", "3\n"]);
+        assert_eq!(
+            parabuilder.unprocessed_datas(),
+            vec![
+                UnprocessedData {
+                    id: 1,
+                    data: json!({"N": 2}),
+                    reason: UnprocessedReason::Cancelled
+                },
+                UnprocessedData {
+                    id: 3,
+                    data: json!({"N": 4}),
+                    reason: UnprocessedReason::Cancelled
+                },
+            ]
+        );
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
 
-        parabuilder.set_datas(datas).unwrap();
+    #[test]
+    fn test_unprocessed_datas() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_unprocessed_datas");
+            let stop_flags = StopFlags::default();
+            let stop_flag = stop_flags.stop.clone();
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .build_workers(1)
+                .progress_callback(move |event| {
+                    if event
+                        == (ProgressEvent::RunFinished {
+                            id: 0,
+                            status: Some(0),
+                        })
+                    {
+                        stop_flag.store(true, Ordering::Relaxed);
+                    }
+                });
+            parabuilder.stop_flags = stop_flags;
+            parabuilder
+                .set_datas((1..=5).map(|n| json!({"N": n})).collect())
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let report = parabuilder.run_report().unwrap();
+            assert_eq!(report.processed_ids, vec![0]);
+            let ids: Vec<usize> = report.unprocessed.iter().map(|data| data.id).collect();
+            assert_eq!(ids, vec![1, 2, 3, 4]);
+            assert_eq!(report.unprocessed[0].data, json!({"N": 2}));
+            for unprocessed_data in report.unprocessed.iter() {
+                if run_method == RunMethod::InPlace {
+                    assert_eq!(unprocessed_data.reason, UnprocessedReason::NotStarted);
+                } else {
+                    assert!(matches!(
+                        unprocessed_data.reason,
+                        UnprocessedReason::NotStarted | UnprocessedReason::Stopped
+                    ));
+                }
+            }
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_non_utf8_run_output() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_non_utf8_run_output");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .run_bash_script(r"./main; printf 'a\377b'; printf '\377' >&2");
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
         parabuilder.init_workspace().unwrap();
-        let (run_data, compile_error_datas, processed_data_ids) = parabuilder.run().unwrap();
-        assert!(
-            compile_error_datas == vec![error_data],
-            "got: {:?} {:?}",
-            run_data,
-            compile_error_datas
-        );
-        assert!(
-            processed_data_ids.len() == if size == 0 { 2 } else { size as usize + 1 }, // default
-            "got: {:?}",
-            processed_data_ids
-        );
-        if matches!(run_method, RunMethod::No) {
-            assert!(run_data.is_null(), "got: {}", run_data);
-            for i in 0..size {
-                assert!(workspaces_path
-                    .join(format!("{}/main_{}", Parabuilder::TEMP_TARGET_PATH_DIR, i))
-                    .exists());
-                assert!(workspaces_path
-                    .join(format!(
-                        "{}/data_{}.json",
-                        Parabuilder::TEMP_TARGET_PATH_DIR,
-                        i
-                    ))
-                    .exists());
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["stdout"], "1\na\u{FFFD}b");
+        assert_eq!(run_data[0]["stderr"], "\u{FFFD}");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_interleaved_run() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_interleaved_run");
+        let log_dir = tempdir().unwrap().into_path();
+        let log = log_dir.join("log");
+        let log = log.to_str().unwrap();
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::Interleaved(2))
+            .build_workers(3)
+            .compile_bash_script(&format!(
+                "echo build+ >> {log}; make -B; sleep 0.05; echo build- >> {log}"
+            ))
+            .run_bash_script(&format!(
+                "echo run+ >> {log}; sleep 0.05; ./main; echo run- >> {log}"
+            ));
+        parabuilder
+            .set_datas((0..8).map(|n| json!({"N": n})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, processed_data_ids) = parabuilder.run().unwrap();
+        assert_eq!(run_data.as_array().unwrap().len(), 8);
+        assert_eq!(processed_data_ids.len(), 8);
+        let (mut builds, mut runs) = (0, 0);
+        // builds resume after a run instead of all finishing before the first run
+        let (mut ran, mut interleaved) = (false, false);
+        for line in std::fs::read_to_string(log).unwrap().lines() {
+            match line {
+                "build+" => builds += 1,
+                "build-" => builds -= 1,
+                "run+" => runs += 1,
+                "run-" => runs -= 1,
+                _ => panic!("unexpected line: {}", line),
             }
-        } else {
-            let ground_truth = if size > 0 {
-                (1..=size).sum::<i64>()
+            assert!(builds == 0 || runs == 0, "a build and a run overlap");
+            ran |= line == "run+";
+            interleaved |= ran && line == "build+";
+        }
+        assert!(interleaved);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(log_dir).unwrap();
+    }
+
+    #[test]
+    fn test_glob_target_files() {
+        for run_method in [RunMethod::OutOfPlace(1), RunMethod::No] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_glob_target_files");
+            let mut parabuilder = Parabuilder::new(
+                crate::test_constants::EXAMPLE_MAKEFILE_PROJECT_PATH,
+                &workspaces_path,
+                "",
+                &["main", "out/*.txt"],
+            )
+            .init_bash_script("")
+            .compile_bash_script(
+                "make -B && rm -rf out && mkdir out && ./main > out/a.txt && echo b > out/b.txt",
+            )
+            .run_bash_script("cat out/*.txt")
+            .build_workers(1)
+            .run_method(run_method)
+            .disable_progress_bar(true)
+            .without_rsync(true)
+            .no_cache(true)
+            .enable_cppflags(true);
+            parabuilder
+                .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+            assert!(
+                compile_error_datas.is_empty(),
+                "got: {:?}",
+                compile_error_datas
+            );
+            if run_method == RunMethod::No {
+                let targets = workspaces_path.join("targets");
+                assert_eq!(
+                    std::fs::read_to_string(targets.join("a.txt_1")).unwrap(),
+                    "2\n"
+                );
+                assert!(targets.join("b.txt_0").exists());
+                assert!(targets.join("main_0").exists());
             } else {
-                42
-            };
-            assert!(run_data.is_array());
-            let sum = run_data.as_array().unwrap().iter().fold(0, |acc, item| {
-                acc + item["stdout"]
-                    .as_str()
-                    .unwrap()
-                    .trim()
-                    .parse::<i64>()
+                let mut stdouts: Vec<&str> = run_data
+                    .as_array()
                     .unwrap()
+                    .iter()
+                    .map(|item| item["stdout"].as_str().unwrap())
+                    .collect();
+                stdouts.sort();
+                assert_eq!(stdouts, vec!["1\nb\n", "2\nb\n"]);
+            }
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reduce_func() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_reduce_func");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(2))
+            .reduce_func(|run_datas| {
+                run_datas
+                    .iter()
+                    .min_by_key(|item| item["data"]["N"].as_i64().unwrap())
+                    .map(|item| item["data"].clone())
+                    .unwrap_or(JsonValue::Null)
             });
-            assert!(
-                sum == ground_truth,
-                "expected: {}, got: {}, run_data: {}",
-                ground_truth,
-                sum,
-                run_data
+        assert!(parabuilder.reduced_data().is_none());
+        parabuilder
+            .set_datas((1..=5).rev().map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        parabuilder.run().unwrap();
+        assert_eq!(parabuilder.reduced_data(), Some(json!({"N": 1})));
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_directory_target() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_directory_target");
+        let mut parabuilder = Parabuilder::new(
+            crate::test_constants::EXAMPLE_MAKEFILE_PROJECT_PATH,
+            &workspaces_path,
+            "",
+            &["out"],
+        )
+        .init_bash_script("")
+        .compile_bash_script("make -B && mkdir -p out/bin && mv main out/bin/")
+        .run_bash_script("./out/bin/main")
+        .build_workers(2)
+        .run_method(RunMethod::OutOfPlace(2))
+        .disable_progress_bar(true)
+        .without_rsync(true)
+        .no_cache(true)
+        .enable_cppflags(true);
+        parabuilder
+            .set_datas((1..=4).map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(compile_error_datas.is_empty());
+        let run_data = run_data.as_array().unwrap();
+        assert_eq!(run_data.len(), 4);
+        for item in run_data {
+            assert_eq!(item["status"], 0, "got: {}", item);
+            assert_eq!(
+                item["stdout"].as_str().unwrap().trim(),
+                item["data"]["N"].to_string()
             );
         }
         std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_singlethreaded_parabuild_without_run() {
-        parabuild_tester(
-            "test_singlethreaded_parabuild_without_run",
-            SINGLETHREADED_N,
-            1,
-            RunMethod::No,
-            false,
-            false,
-        );
+    fn test_parabuild_define() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_parabuild_define");
+        for parabuild_define in [true, false] {
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+                .compile_bash_script(r#"echo "$CPPFLAGS" > cppflags.txt && make -B"#)
+                .run_bash_script("cat cppflags.txt")
+                .parabuild_define(parabuild_define);
+            parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            let expected = if parabuild_define {
+                "-DPARABUILD=ON -DN=1 \n"
+            } else {
+                "-DN=1 \n"
+            };
+            assert_eq!(run_data[0]["stdout"], expected);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_singlethreaded_parabuild_in_place_run() {
-        parabuild_tester(
-            "test_singlethreaded_parabuild_in_place_run",
-            SINGLETHREADED_N,
-            1,
-            RunMethod::InPlace,
-            false,
-            false,
+    fn test_no_template() {
+        let parabuilder = Parabuilder::new(".", "workspaces", "", &["main"]);
+        assert!(parabuilder.template_files.is_empty());
+        let parabuilder = parabuilder.template_files(&["src/main.cpp.template", ""]);
+        assert_eq!(
+            parabuilder.template_files,
+            vec![PathBuf::from("src/main.cpp.template")]
+        );
+        let parabuilder = parabuilder.no_template();
+        assert!(parabuilder.template_files.is_empty());
+        assert_eq!(
+            parabuilder.copy_independent_paths(),
+            vec![PathBuf::from("main")]
         );
+
+        let workspaces_path = PathBuf::from("tests/workspaces_test_no_template");
+        let mut parabuilder =
+            makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1)).no_template();
+        assert!(parabuilder
+            .render_data(&json!({"N": 1}))
+            .unwrap()
+            .is_empty());
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(compile_error_datas.is_empty());
+        let mut stdouts: Vec<&str> = run_data
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["stdout"].as_str().unwrap())
+            .collect();
+        stdouts.sort();
+        assert_eq!(stdouts, vec!["1\n", "2\n"]);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_multithreaded_parabuild_without_run() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_without_run",
-            MULTITHREADED_N,
-            4,
-            RunMethod::No,
-            false,
-            false,
-        );
+    fn test_dedupe_identical_renders() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_dedupe_identical_renders");
+        for (enable_cppflags, compiles) in [(false, 1), (true, 4)] {
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+                .build_workers(1)
+                .enable_cppflags(enable_cppflags)
+                .compile_bash_script("make -B && echo compiled >> compile.log")
+                .dedupe_identical_renders(true);
+            parabuilder
+                .set_datas((1..=4).map(|i| json!({"N": i})).collect())
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+            assert!(compile_error_datas.is_empty());
+            let run_data = run_data.as_array().unwrap();
+            assert_eq!(run_data.len(), 4);
+            for item in run_data {
+                assert_eq!(item["status"], 0, "got: {}", item);
+            }
+            let compile_log =
+                std::fs::read_to_string(workspaces_path.join("workspace_0/compile.log")).unwrap();
+            assert_eq!(compile_log.lines().count(), compiles);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_multithreaded_parabuild_in_place_run() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_in_place_run",
-            MULTITHREADED_N,
-            4,
-            RunMethod::InPlace,
-            false,
-            false,
-        );
+    fn test_trust_incremental() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_trust_incremental");
+        for (trust_incremental, compiles) in [(true, 2), (false, 4)] {
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+                .build_workers(1)
+                .enable_cppflags(false)
+                .templated_scripts(true)
+                .compile_bash_script("make -B CPPFLAGS=-DN={{N}} && echo compiled >> compile.log")
+                .run_bash_script("echo {{R}} $(./main)")
+                .trust_incremental(trust_incremental);
+            parabuilder
+                .set_datas(vec![
+                    json!({"N": 1, "R": 1}),
+                    json!({"N": 1, "R": 2}),
+                    json!({"N": 2, "R": 3}),
+                    json!({"N": 2, "R": 4}),
+                ])
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, _, _) = parabuilder.run().unwrap();
+            let stdouts: Vec<&str> = run_data
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["stdout"].as_str().unwrap())
+                .collect();
+            assert_eq!(stdouts, vec!["1 1\n", "2 1\n", "3 2\n", "4 2\n"]);
+            let compile_log =
+                std::fs::read_to_string(workspaces_path.join("workspace_0/compile.log")).unwrap();
+            assert_eq!(compile_log.lines().count(), compiles);
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_multithreaded_parabuild_out_of_place_single_run() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_out_of_place_single_run",
-            MULTITHREADED_N,
-            4,
-            RunMethod::OutOfPlace(1),
-            false,
-            false,
-        );
+    fn test_build_dir() {
+        for run_method in [RunMethod::InPlace, RunMethod::OutOfPlace(1)] {
+            let workspaces_path = PathBuf::from("tests/workspaces_test_build_dir");
+            let mut parabuilder = makefile_parabuilder(&workspaces_path, run_method)
+                .compile_bash_script("make -B && mkdir -p out/bin && mv main out/bin/")
+                .run_bash_script("./main")
+                .build_dir("out/bin");
+            parabuilder
+                .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+                .unwrap();
+            parabuilder.init_workspace().unwrap();
+            let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+            assert!(compile_error_datas.is_empty());
+            let mut stdouts: Vec<&str> = run_data
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["stdout"].as_str().unwrap())
+                .collect();
+            stdouts.sort();
+            assert_eq!(stdouts, vec!["1\n", "2\n"]);
+            std::fs::remove_dir_all(workspaces_path).unwrap();
+        }
     }
 
+    #[cfg(feature = "tokio")]
     #[test]
-    fn test_multithreaded_parabuild_out_of_place_run() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_out_of_place_run",
-            MULTITHREADED_N,
-            4,
-            RunMethod::OutOfPlace(2),
-            false,
-            false,
-        );
+    fn test_run_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_run_async");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1));
+        parabuilder
+            .set_datas((1..=3).map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = runtime.block_on(parabuilder.run_async()).unwrap();
+        assert_eq!(run_data.as_array().unwrap().len(), 3);
+
+        // dropping the future aborts the long running script
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .run_bash_script("sleep 10")
+            .autosave_dir(workspaces_path.join("autosave"));
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let stop_flags = parabuilder.stop_flags.clone();
+        let start = Instant::now();
+        let result = runtime.block_on(async {
+            tokio::time::timeout(Duration::from_millis(500), parabuilder.run_async()).await
+        });
+        assert!(result.is_err());
+        assert!(stop_flags.abort.load(Ordering::Relaxed));
+        // wait for the run thread to drop its `Parabuilder`
+        while Arc::strong_count(&stop_flags.abort) > 1 {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_multithreaded_parabuild_exclusive_run() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_exclusive_run",
-            MULTITHREADED_N,
-            4,
-            RunMethod::Exclusive(2),
-            false,
-            false,
-        );
+    fn test_script_steps() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_script_steps");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .init_bash_scripts(&["echo init > init.log", "echo init again >> init.log"])
+            .compile_bash_scripts(&["make -B", "echo compiled"])
+            .run_bash_scripts(&["./main", "exit 3", "echo never"]);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let init_log =
+            std::fs::read_to_string(workspaces_path.join("workspace_0/init.log")).unwrap();
+        assert_eq!(init_log.lines().count(), 2);
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(compile_error_datas.is_empty());
+        for item in run_data.as_array().unwrap() {
+            assert_eq!(item["status"], 3, "got: {}", item);
+            assert_eq!(item["failed_step"], 1, "got: {}", item);
+            let stdout = item["stdout"].as_str().unwrap();
+            assert_eq!(stdout.trim(), item["data"]["N"].to_string());
+        }
+
+        let parabuilder = parabuilder.init_bash_scripts(&["true", "exit 1"]);
+        let error = parabuilder.run_one(json!({"N": 1})).unwrap_err();
+        assert!(error.to_string().contains("at step 1: exit 1"), "{}", error);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_multithreaded_parabuild_out_of_place_run_in_place_template() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_out_of_place_run_in_place_template",
-            MULTITHREADED_N,
-            4,
-            RunMethod::OutOfPlace(2),
-            true,
-            false,
-        );
+    fn test_list_workspaces() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_list_workspaces");
+        assert!(Parabuilder::list_workspaces(&workspaces_path).is_empty());
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .workspace_pool_size(11)
+            .init_bash_script("mkdir build");
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let names: Vec<String> = Parabuilder::list_workspaces(&workspaces_path)
+            .iter()
+            .map(|workspace| {
+                assert!(workspace.has_build_dir);
+                workspace
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        let mut expected: Vec<String> = (0..11).map(|i| format!("workspace_{}", i)).collect();
+        expected.push("workspace_exe_0".to_string());
+        assert_eq!(names, expected);
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     #[test]
-    fn test_multithreaded_parabuild_out_of_place_run_default_template() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_out_of_place_run_default_template",
-            0,
-            4,
-            RunMethod::OutOfPlace(2),
-            true,
-            false,
+    fn test_stream_output_writer() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_stream_output_writer");
+        let jsonl_path = tempdir().unwrap().into_path().join("output.jsonl");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(2))
+            .stream_output_writer(Box::new(std::fs::File::create(&jsonl_path).unwrap()));
+        parabuilder
+            .set_datas((1..=5).map(|i| json!({"N": i})).collect())
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        let lines: Vec<JsonValue> = std::fs::read_to_string(&jsonl_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        for item in run_data.as_array().unwrap() {
+            assert!(lines.contains(item));
+        }
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_event_socket() {
+        let socket_dir = tempdir().unwrap();
+        let socket_path = socket_dir.path().join("events.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        let monitor = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            std::io::BufRead::lines(std::io::BufReader::new(stream))
+                .map(|line| serde_json::from_str::<JsonValue>(&line.unwrap()).unwrap())
+                .collect::<Vec<_>>()
+        });
+        let workspaces_path = PathBuf::from("tests/workspaces_test_event_socket");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .event_socket(&socket_path);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        parabuilder.run().unwrap();
+        let events = monitor.join().unwrap();
+        assert_eq!(events.len(), 8);
+        for id in 0..2 {
+            assert!(events.contains(&json!({"event": "build_finished", "id": id, "success": true})));
+            assert!(events.contains(&json!({"event": "run_finished", "id": id, "status": 0})));
+        }
+        // nothing listens any more, the run still succeeds
+        drop(socket_dir);
+        parabuilder.run().unwrap();
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_progress_callback() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_progress_callback");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .progress_callback({
+                let events = Arc::clone(&events);
+                move |event| events.lock().unwrap().push(event)
+            });
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": "a"})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        parabuilder.run().unwrap();
+        let events = events.lock().unwrap();
+        assert!(events.contains(&ProgressEvent::BuildFinished {
+            id: 0,
+            success: true
+        }));
+        assert!(events.contains(&ProgressEvent::BuildFinished {
+            id: 1,
+            success: false
+        }));
+        assert!(events.contains(&ProgressEvent::RunFinished {
+            id: 0,
+            status: Some(0)
+        }));
+        assert!(!events.contains(&ProgressEvent::RunStarted { id: 1 }));
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let project_path = tempdir().unwrap().into_path();
+        std::fs::write(project_path.join("a.txt.template"), "{{N}}").unwrap();
+        std::fs::write(project_path.join("b.txt.template"), "{{#if}}{{/if}}").unwrap();
+        let workspaces_path = PathBuf::from("tests/workspaces_test_dry_run");
+        let mut parabuilder = Parabuilder::new(&project_path, &workspaces_path, "", &["out"])
+            .template_files(&["a.txt.template"])
+            .init_bash_script("")
+            .compile_bash_script("exit 1")
+            .dry_run(true)
+            .disable_progress_bar(true)
+            .without_rsync(true)
+            .no_cache(true);
+        parabuilder
+            .set_datas(vec![json!({"N": 1}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, render_error_datas, processed_data_ids) = parabuilder.run().unwrap();
+        assert!(run_data.is_null());
+        assert!(render_error_datas.is_empty());
+        assert_eq!(processed_data_ids.len(), 2);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut parabuilder = parabuilder
+            .template_files(&["a.txt.template", "b.txt.template"])
+            .progress_callback({
+                let events = Arc::clone(&events);
+                move |event| events.lock().unwrap().push(event)
+            });
+        parabuilder.data_queue_receiver = None;
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        let (_, render_error_datas, _) = parabuilder.run().unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ProgressEvent::BuildStarted { id: 0 },
+                ProgressEvent::BuildFinished {
+                    id: 0,
+                    success: false
+                }
+            ]
         );
+        assert_eq!(render_error_datas.len(), 1);
+        assert_eq!(render_error_datas[0]["data"], json!({"N": 1}));
+        assert!(render_error_datas[0]["render_error"].is_string());
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+        std::fs::remove_dir_all(project_path).unwrap();
     }
 
     #[test]
-    fn test_multithreaded_parabuild_makefile_project() {
-        parabuild_tester(
-            "test_multithreaded_parabuild_makefile_project",
-            10,
-            4,
-            RunMethod::OutOfPlace(2),
-            true,
-            true,
+    fn test_shell() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_shell");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .shell(ShellKind::Sh)
+            .run_bash_script("echo $0");
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, _, _) = parabuilder.run().unwrap();
+        assert_eq!(run_data[0]["stdout"], "sh\n");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_envs() {
+        let workspaces_path = PathBuf::from("tests/workspaces_test_envs");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::InPlace)
+            .envs(HashMap::from([
+                ("MY_ENV".to_string(), "hello".to_string()),
+                ("PARABUILD_ID".to_string(), "overridden".to_string()),
+            ]))
+            .compile_bash_script("test \"$MY_ENV\" = hello && make -B")
+            .run_bash_script("echo $MY_ENV $PARABUILD_ID");
+        parabuilder.set_datas(vec![json!({"N": 1})]).unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert!(compile_error_datas.is_empty());
+        assert_eq!(run_data[0]["stdout"], "hello 0\n");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
+    }
+
+    #[test]
+    fn test_data_env_prefix() {
+        assert_eq!(
+            data_to_envs("PARA_", &json!({"N": 10, "MODE": "fast", "V": [1, 2]})),
+            vec![
+                ("PARA_MODE".to_string(), "fast".to_string()),
+                ("PARA_N".to_string(), "10".to_string()),
+                ("PARA_V".to_string(), "[1,2]".to_string()),
+            ]
         );
+        assert!(data_to_envs("PARA_", &JsonValue::Null).is_empty());
+
+        let workspaces_path = PathBuf::from("tests/workspaces_test_data_env_prefix");
+        let mut parabuilder = makefile_parabuilder(&workspaces_path, RunMethod::OutOfPlace(1))
+            .data_env_prefix("PARA_")
+            .compile_bash_script("test \"$PARA_MODE\" = fast && make -B")
+            .run_bash_script("echo $PARA_N");
+        parabuilder
+            .set_datas(vec![json!({"N": 1, "MODE": "fast"}), json!({"N": 2})])
+            .unwrap();
+        parabuilder.init_workspace().unwrap();
+        let (run_data, compile_error_datas, _) = parabuilder.run().unwrap();
+        assert_eq!(
+            compile_error_datas,
+            vec![json!({"data": {"N": 2}, "category": null})]
+        );
+        assert_eq!(run_data[0]["stdout"], "1\n");
+        std::fs::remove_dir_all(workspaces_path).unwrap();
     }
 
     // #[test]
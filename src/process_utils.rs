@@ -0,0 +1,424 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Run the command to completion like `Command::output()`, but kill it when `timeout` is exceeded
+/// or `abort_flag` is set
+///
+/// When `abort_flag` is given, the command runs in its own process group, so a Ctrl-C in the
+/// terminal does not reach it, and only `abort_flag` decides when to stop it
+///
+/// `stdin` is written to the stdin of the command from another thread, so a command that does not
+/// read all of it, or writes a lot before reading, does not deadlock
+///
+/// Returns the output and whether the timeout was hit
+pub fn output_with_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    abort_flag: Option<&AtomicBool>,
+    stdin: Option<&[u8]>,
+) -> Result<(Output, bool), std::io::Error> {
+    if timeout.is_none() && abort_flag.is_none() && stdin.is_none() {
+        return command.output().map(|output| (output, false));
+    }
+    output_with_max_rss(command, timeout, abort_flag, stdin)
+        .map(|(output, timed_out, _)| (output, timed_out))
+}
+
+/// Like `output_with_timeout`, also returns the peak resident set size in KiB of the command,
+/// or of the largest of its descendants it waited for, `None` where it can not be measured
+pub fn output_with_max_rss(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    abort_flag: Option<&AtomicBool>,
+    stdin: Option<&[u8]>,
+) -> Result<(Output, bool, Option<u64>), std::io::Error> {
+    #[cfg(unix)]
+    {
+        // put the script and everything it spawns into a new process group, so we can kill them all
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdin_handle = child
+        .stdin
+        .take()
+        .zip(stdin)
+        .map(|(mut child_stdin, stdin)| {
+            let stdin = stdin.to_vec();
+            std::thread::spawn(move || {
+                // a broken pipe only means the command exited without reading everything
+                let _ = child_stdin.write_all(&stdin);
+            })
+        });
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let stdout_handle = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let stderr_handle = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        stderr.read_to_end(&mut buf).map(|_| buf)
+    });
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut timed_out = false;
+    let (status, max_rss) = loop {
+        if let Some(exited) = wait_with_max_rss(&mut child, false)? {
+            break exited;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            timed_out = true;
+            kill(&mut child);
+            break wait_with_max_rss(&mut child, true)?.unwrap();
+        }
+        if abort_flag.is_some_and(|abort_flag| abort_flag.load(Ordering::Relaxed)) {
+            kill(&mut child);
+            break wait_with_max_rss(&mut child, true)?.unwrap();
+        }
+        sleep(Duration::from_millis(10));
+    };
+    if let Some(stdin_handle) = stdin_handle {
+        stdin_handle.join().unwrap();
+    }
+    let stdout = stdout_handle.join().unwrap()?;
+    let stderr = stderr_handle.join().unwrap()?;
+    Ok((
+        Output {
+            status,
+            stdout,
+            stderr,
+        },
+        timed_out,
+        max_rss,
+    ))
+}
+
+/// Run `commands` one by one like `output_with_timeout`, stop at the first failing one,
+/// `stdin` is written to each of them
+///
+/// Returns the stdout/stderr of the commands run concatenated with the status of the last one,
+/// whether its timeout was hit, and the index of the failing command
+///
+/// `commands` must not be empty
+pub fn output_of_steps_with_timeout(
+    commands: impl IntoIterator<Item = Command>,
+    timeout: Option<Duration>,
+    abort_flag: Option<&AtomicBool>,
+    stdin: Option<&[u8]>,
+) -> Result<(Output, bool, Option<usize>), std::io::Error> {
+    let mut output: Option<Output> = None;
+    for (i, mut command) in commands.into_iter().enumerate() {
+        let (step_output, timed_out) =
+            output_with_timeout(&mut command, timeout, abort_flag, stdin)?;
+        let output = match &mut output {
+            Some(output) => {
+                output.stdout.extend(step_output.stdout);
+                output.stderr.extend(step_output.stderr);
+                output.status = step_output.status;
+                output
+            }
+            None => output.insert(step_output),
+        };
+        if timed_out || !output.status.success() {
+            return Ok((output.clone(), timed_out, Some(i)));
+        }
+    }
+    Ok((output.expect("no command to run"), false, None))
+}
+
+/// Like `output_of_steps_with_timeout`, also returns the largest `output_with_max_rss` of the
+/// commands run
+pub fn output_of_steps_with_max_rss(
+    commands: impl IntoIterator<Item = Command>,
+    timeout: Option<Duration>,
+    abort_flag: Option<&AtomicBool>,
+    stdin: Option<&[u8]>,
+) -> Result<(Output, bool, Option<usize>, Option<u64>), std::io::Error> {
+    let mut output: Option<Output> = None;
+    let mut max_rss = None;
+    for (i, mut command) in commands.into_iter().enumerate() {
+        let (step_output, timed_out, step_max_rss) =
+            output_with_max_rss(&mut command, timeout, abort_flag, stdin)?;
+        max_rss = max_rss.max(step_max_rss);
+        let output = match &mut output {
+            Some(output) => {
+                output.stdout.extend(step_output.stdout);
+                output.stderr.extend(step_output.stderr);
+                output.status = step_output.status;
+                output
+            }
+            None => output.insert(step_output),
+        };
+        if timed_out || !output.status.success() {
+            return Ok((output.clone(), timed_out, Some(i), max_rss));
+        }
+    }
+    Ok((output.expect("no command to run"), false, None, max_rss))
+}
+
+/// `Child::try_wait()`, or `Child::wait()` when `block`, that also reads the `ru_maxrss` in KiB
+/// of the exited child through `wait4`
+#[cfg(unix)]
+fn wait_with_max_rss(
+    child: &mut Child,
+    block: bool,
+) -> Result<Option<(ExitStatus, Option<u64>)>, std::io::Error> {
+    use std::os::unix::process::ExitStatusExt;
+    let mut status = 0;
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    let options = if block { 0 } else { libc::WNOHANG };
+    loop {
+        let pid =
+            unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, options, &mut rusage) };
+        match pid {
+            0 => return Ok(None),
+            -1 => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            _ => break,
+        }
+    }
+    // bytes on macOS, KiB elsewhere
+    let max_rss = if cfg!(target_os = "macos") {
+        rusage.ru_maxrss as u64 / 1024
+    } else {
+        rusage.ru_maxrss as u64
+    };
+    Ok(Some((ExitStatus::from_raw(status), Some(max_rss))))
+}
+
+#[cfg(not(unix))]
+fn wait_with_max_rss(
+    child: &mut Child,
+    block: bool,
+) -> Result<Option<(ExitStatus, Option<u64>)>, std::io::Error> {
+    let status = if block {
+        Some(child.wait()?)
+    } else {
+        child.try_wait()?
+    };
+    Ok(status.map(|status| (status, None)))
+}
+
+/// CPUs the current process is allowed to run on, empty if unknown
+#[cfg(target_os = "linux")]
+pub fn allowed_cpus() -> Vec<usize> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Vec::new();
+        }
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+            .collect()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn allowed_cpus() -> Vec<usize> {
+    Vec::new()
+}
+
+/// Pin the process spawned by `command` to `cpu`
+#[cfg(target_os = "linux")]
+pub fn pin_command_to_cpu(command: &mut Command, cpu: usize) {
+    use std::os::unix::process::CommandExt;
+    let set = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        set
+    };
+    // only a syscall between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// CPU pinning is only supported on Linux, elsewhere this is a no-op
+#[cfg(not(target_os = "linux"))]
+pub fn pin_command_to_cpu(_command: &mut Command, _cpu: usize) {}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    // SIGKILL the whole process group created in `output_with_max_rss`, the fast path of
+    // `output_with_timeout` creates no group but never kills either
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_with_timeout() {
+        let (output, timed_out) = output_with_timeout(
+            Command::new("bash").arg("-c").arg("echo hi"),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(!timed_out);
+        assert_eq!(output.stdout, b"hi\n");
+
+        let start = Instant::now();
+        let (output, timed_out) = output_with_timeout(
+            Command::new("bash").arg("-c").arg("sleep 10; echo hi"),
+            Some(Duration::from_millis(200)),
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(timed_out);
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // much more than a pipe buffer, and not read at all by `head -c 1`
+        let stdin = vec![b'x'; 1 << 20];
+        for (script, expected) in [("wc -c", "1048576"), ("head -c 1", "x")] {
+            let (output, _) = output_with_timeout(
+                Command::new("bash").arg("-c").arg(script),
+                None,
+                None,
+                Some(&stdin),
+            )
+            .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), expected);
+        }
+    }
+
+    #[test]
+    fn test_output_of_steps_with_timeout() {
+        let steps = |steps: &[&str]| -> Vec<Command> {
+            steps
+                .iter()
+                .map(|step| {
+                    let mut command = Command::new("bash");
+                    command.arg("-c").arg(step);
+                    command
+                })
+                .collect()
+        };
+        let (output, timed_out, failed_step) =
+            output_of_steps_with_timeout(steps(&["echo a", "echo b"]), None, None, None).unwrap();
+        assert!(output.status.success());
+        assert!(!timed_out);
+        assert_eq!(failed_step, None);
+        assert_eq!(output.stdout, b"a\nb\n");
+
+        let (output, timed_out, failed_step) =
+            output_of_steps_with_timeout(steps(&["echo a", "exit 3", "echo c"]), None, None, None)
+                .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(!timed_out);
+        assert_eq!(failed_step, Some(1));
+        assert_eq!(output.stdout, b"a\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_with_max_rss() {
+        // bash keeps the 32 MiB string in memory
+        let (output, timed_out, max_rss) = output_with_max_rss(
+            Command::new("bash")
+                .arg("-c")
+                .arg("x=$(head -c 33554432 /dev/zero | tr '\\0' a); echo ${#x}"),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(!timed_out);
+        assert_eq!(output.stdout, b"33554432\n");
+        assert!(max_rss.unwrap() > 32 * 1024, "got: {:?}", max_rss);
+
+        let abort_flag = AtomicBool::new(true);
+        let mut command = Command::new("bash");
+        command.arg("-c").arg("sleep 10");
+        let (output, _, _, max_rss) =
+            output_of_steps_with_max_rss([command], None, Some(&abort_flag), None).unwrap();
+        assert!(!output.status.success());
+        assert!(max_rss.is_some());
+    }
+
+    #[test]
+    fn test_output_with_abort_flag() {
+        let abort_flag = AtomicBool::new(false);
+        let (output, timed_out) = output_with_timeout(
+            Command::new("bash").arg("-c").arg("echo hi"),
+            None,
+            Some(&abort_flag),
+            None,
+        )
+        .unwrap();
+        assert!(!timed_out);
+        assert_eq!(output.stdout, b"hi\n");
+
+        let start = Instant::now();
+        let (output, timed_out) = std::thread::scope(|s| {
+            s.spawn(|| {
+                sleep(Duration::from_millis(200));
+                abort_flag.store(true, Ordering::Relaxed);
+            });
+            output_with_timeout(
+                Command::new("bash").arg("-c").arg("sleep 10; echo hi"),
+                None,
+                Some(&abort_flag),
+                None,
+            )
+            .unwrap()
+        });
+        assert!(!timed_out);
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pin_command_to_cpu() {
+        let cpus = allowed_cpus();
+        assert!(!cpus.is_empty());
+        let cpu = *cpus.last().unwrap();
+        let mut command = Command::new("bash");
+        command
+            .arg("-c")
+            .arg("grep Cpus_allowed_list /proc/self/status");
+        pin_command_to_cpu(&mut command, cpu);
+        let output = command.output().unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout)
+                .unwrap()
+                .split_whitespace()
+                .last(),
+            Some(cpu.to_string().as_str())
+        );
+    }
+}